@@ -0,0 +1,831 @@
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::mpsc::UnboundedSender;
+use uuid::Uuid;
+
+use crate::audio::RecordingResult;
+use crate::capture::PreparedImage;
+use crate::config::{OpenAIConfig, SpeechSettings, TranscriptionLanguage};
+use crate::error::ApiError;
+use crate::openai::{build_endpoint, run_tool_call};
+use crate::provider::{
+    AnalyzeRequest, AnalyzeResponse, Provider, StreamEvent, Transcription, REQUEST_CANCELLED,
+};
+use crate::retry::RetryPolicy;
+use crate::session::ConversationRole;
+use crate::sse::SseStream;
+use crate::timeouts::{HttpClients, Timeouts};
+use crate::tokens::{budget_history, PromptParts};
+use crate::tools::ToolDefinition;
+use crate::usage::TokenUsage;
+
+const MESSAGES_PATH: &str = "messages";
+const MODELS_PATH: &str = "models";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// The Messages API requires `max_tokens`; used when the config leaves it unset.
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+/// Native client for the Anthropic Messages API.
+pub struct AnthropicClient {
+    http: HttpClients,
+}
+
+impl AnthropicClient {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            http: HttpClients::default(),
+        })
+    }
+}
+
+impl AnthropicClient {
+    /// Streams one Messages API response, forwarding text and thinking
+    /// deltas and collecting any tools the model asks to use. `separate`
+    /// starts the streamed text with a blank line when earlier rounds
+    /// produced text.
+    async fn stream_messages_round(
+        &self,
+        url: &str,
+        headers: &HeaderMap,
+        payload: &MessagesPayload,
+        request: &AnalyzeRequest,
+        separate: bool,
+        stream_tx: &UnboundedSender<(Uuid, StreamEvent)>,
+    ) -> Result<MessagesRound> {
+        let request_id = request.request_id;
+        let cancel = &request.cancel;
+
+        let timeouts = Timeouts::from(&request.config.timeouts);
+        let http = self.http.get(&timeouts)?;
+        let policy = timeouts.apply_to(RetryPolicy::from(&request.config.retry));
+        let mut sent = Instant::now();
+        let res = cancel
+            .run_until_cancelled(policy.send(
+                || {
+                    sent = Instant::now();
+                    http.post(url).headers(headers.clone()).json(payload)
+                },
+                |notice| {
+                    let _ = stream_tx.send((request_id, StreamEvent::Retrying(notice)));
+                },
+            ))
+            .await
+            .ok_or_else(|| anyhow!(REQUEST_CANCELLED))?
+            .context("failed to send Anthropic messages request")?;
+
+        if !res.status().is_success() {
+            let error = ApiError::from_response(res).await;
+            let _ = stream_tx.send((request_id, StreamEvent::Error(error.to_string())));
+            return Err(error.into());
+        }
+
+        let mut events = SseStream::new(timeouts.watch_body(sent, res.bytes_stream()));
+        let mut round = MessagesRound::default();
+
+        while let Some(event) = cancel
+            .run_until_cancelled(events.next_event())
+            .await
+            .flatten()
+        {
+            let event = event.context("failed to read chunk from Anthropic stream")?;
+
+            match serde_json::from_str::<MessagesStreamEvent>(&event.data) {
+                Ok(MessagesStreamEvent::ContentBlockStart {
+                    index,
+                    content_block,
+                }) => {
+                    if let StartedBlock::ToolUse { id, name } = content_block {
+                        round.tool_uses.push(StreamedToolUse {
+                            index,
+                            id,
+                            name,
+                            input: String::new(),
+                        });
+                    }
+                }
+                Ok(MessagesStreamEvent::ContentBlockDelta { index, delta }) => match delta {
+                    ContentDelta::TextDelta { text } => {
+                        if separate && round.text.is_empty() && !text.is_empty() {
+                            let _ = stream_tx.send((request_id, StreamEvent::Delta("\n\n".into())));
+                        }
+                        round.text.push_str(&text);
+                        let _ = stream_tx.send((request_id, StreamEvent::Delta(text)));
+                    }
+                    ContentDelta::ThinkingDelta { thinking } => {
+                        round.reasoning.push_str(&thinking);
+                        let _ = stream_tx.send((request_id, StreamEvent::ReasoningDelta(thinking)));
+                    }
+                    ContentDelta::InputJsonDelta { partial_json } => {
+                        if let Some(tool_use) = round
+                            .tool_uses
+                            .iter_mut()
+                            .find(|tool_use| tool_use.index == index)
+                        {
+                            tool_use.input.push_str(&partial_json);
+                        }
+                    }
+                    ContentDelta::Other => {}
+                },
+                Ok(MessagesStreamEvent::MessageStart { message }) => {
+                    round.usage = message.usage.map(TokenUsage::from);
+                }
+                Ok(MessagesStreamEvent::MessageDelta {
+                    delta,
+                    usage: output,
+                }) => {
+                    // `output_tokens` in message_delta is cumulative.
+                    if let (Some(usage), Some(output)) = (round.usage.as_mut(), output) {
+                        usage.completion_tokens = output.output_tokens;
+                    }
+                    if delta.and_then(|delta| delta.stop_reason).as_deref() == Some("max_tokens") {
+                        log::warn!("Anthropic message hit the max_tokens limit");
+                        round.incomplete = true;
+                    }
+                }
+                Ok(MessagesStreamEvent::MessageStop) => {
+                    round.finished = true;
+                    break;
+                }
+                Ok(MessagesStreamEvent::Error { error }) => {
+                    let error = format!("Anthropic stream error: {}", error.message);
+                    let _ = stream_tx.send((request_id, StreamEvent::Error(error.clone())));
+                    return Err(anyhow!(error));
+                }
+                Ok(MessagesStreamEvent::Other) => {}
+                Err(err) => {
+                    log::warn!("failed to parse Anthropic stream event: {err}");
+                }
+            }
+        }
+
+        Ok(round)
+    }
+}
+
+#[async_trait]
+impl Provider for AnthropicClient {
+    async fn analyze_stream(
+        &self,
+        request: AnalyzeRequest,
+        stream_tx: UnboundedSender<(Uuid, StreamEvent)>,
+    ) -> Result<AnalyzeResponse> {
+        let request_id = request.request_id;
+        let cancel = request.cancel.clone();
+        let model = request.config.model.clone();
+
+        let url = build_endpoint(&request.config.base_url, MESSAGES_PATH)?;
+        let mut headers = auth_headers(&request.config.api_key)?;
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let mut payload = build_messages_payload(&request)?;
+        payload.stream = true;
+        let tools = request.tools.clone().filter(|tools| !tools.is_empty());
+        if let Some(tools) = &tools {
+            payload.tools = tools
+                .definitions()
+                .into_iter()
+                .map(ToolSpec::from)
+                .collect();
+        }
+
+        let mut answer_text = String::new();
+        let mut reasoning_text = String::new();
+        let mut usage: Option<TokenUsage> = None;
+        let mut rounds = 0;
+        let interrupted = loop {
+            if tools.is_some() {
+                // Out of tool rounds: answer from what the tools returned.
+                payload.tool_choice =
+                    (rounds >= request.max_tool_rounds).then_some(ToolChoice { kind: "none" });
+            }
+            let separate = !answer_text.is_empty();
+            let round = self
+                .stream_messages_round(&url, &headers, &payload, &request, separate, &stream_tx)
+                .await?;
+            if let Some(round_usage) = round.usage {
+                *usage.get_or_insert_with(TokenUsage::default) += round_usage;
+            }
+            if !round.text.is_empty() && separate {
+                answer_text.push_str("\n\n");
+            }
+            answer_text.push_str(&round.text);
+            reasoning_text.push_str(&round.reasoning);
+            // Every complete stream ends with `message_stop`; one without it
+            // was cut off, e.g. by a dropped connection.
+            if !round.finished && !cancel.is_cancelled() {
+                log::warn!("Anthropic stream for {model} ended before message_stop");
+            }
+            if round.incomplete || !round.finished {
+                break true;
+            }
+
+            let Some(tools) = tools.as_ref() else {
+                break false;
+            };
+            if round.tool_uses.is_empty() {
+                break false;
+            }
+            if rounds >= request.max_tool_rounds {
+                log::warn!("model called tools after {rounds} rounds despite tool_choice none");
+                break false;
+            }
+            rounds += 1;
+
+            let mut assistant = Vec::new();
+            if !round.text.is_empty() {
+                assistant.push(ContentBlock::Text { text: round.text });
+            }
+            assistant.extend(
+                round
+                    .tool_uses
+                    .iter()
+                    .map(|tool_use| ContentBlock::ToolUse {
+                        id: tool_use.id.clone(),
+                        name: tool_use.name.clone(),
+                        // The API wants an object back, even for cut-off JSON.
+                        input: serde_json::from_str(&tool_use.input).unwrap_or_else(|_| json!({})),
+                    }),
+            );
+            payload.messages.push(Message {
+                role: "assistant".into(),
+                content: assistant,
+            });
+
+            let mut results = Vec::new();
+            for tool_use in round.tool_uses {
+                let mut images = Vec::new();
+                let Some(output) = run_tool_call(
+                    &request,
+                    tools,
+                    tool_use.name,
+                    tool_use.input,
+                    &mut images,
+                    &stream_tx,
+                )
+                .await
+                else {
+                    break;
+                };
+                // Unlike OpenAI tool messages, tool results may carry images.
+                let mut content = vec![ContentBlock::Text { text: output }];
+                content.extend(images.iter().map(ContentBlock::attachment));
+                results.push(ContentBlock::ToolResult {
+                    tool_use_id: tool_use.id,
+                    content,
+                });
+            }
+            if cancel.is_cancelled() {
+                break true;
+            }
+            payload.messages.push(Message {
+                role: "user".into(),
+                content: results,
+            });
+        };
+
+        if !reasoning_text.is_empty() {
+            let _ = stream_tx.send((request_id, StreamEvent::ReasoningDone(reasoning_text)));
+        }
+        let _ = stream_tx.send((request_id, StreamEvent::Done(answer_text.clone())));
+
+        Ok(AnalyzeResponse {
+            request_id,
+            answer: answer_text,
+            model,
+            interrupted,
+            usage,
+            citations: Vec::new(),
+        })
+    }
+
+    async fn analyze(&self, request: AnalyzeRequest) -> Result<AnalyzeResponse> {
+        let url = build_endpoint(&request.config.base_url, MESSAGES_PATH)?;
+        let mut headers = auth_headers(&request.config.api_key)?;
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let payload = build_messages_payload(&request)?;
+
+        let timeouts = Timeouts::from(&request.config.timeouts);
+        let http = self.http.get(&timeouts)?;
+        let policy = RetryPolicy::from(&request.config.retry);
+        let res = policy
+            .send(
+                || timeouts.limit(http.post(&url).headers(headers.clone()).json(&payload)),
+                |_| {},
+            )
+            .await
+            .context("failed to send Anthropic messages request")?;
+
+        if !res.status().is_success() {
+            return Err(ApiError::from_response(res).await.into());
+        }
+
+        let parsed: MessagesResponse = res
+            .json()
+            .await
+            .context("failed to decode Anthropic messages response")?;
+
+        let answer = parsed
+            .content
+            .iter()
+            .filter_map(|block| match block {
+                ResponseContentBlock::Text { text } => Some(text.as_str()),
+                ResponseContentBlock::Other => None,
+            })
+            .collect::<Vec<_>>()
+            .join("");
+        let answer = if answer.is_empty() {
+            "<empty response>".to_string()
+        } else {
+            answer
+        };
+
+        Ok(AnalyzeResponse {
+            request_id: request.request_id,
+            answer,
+            model: parsed.model.unwrap_or(request.config.model),
+            interrupted: false,
+            usage: parsed.usage.map(TokenUsage::from),
+            citations: Vec::new(),
+        })
+    }
+
+    async fn list_models(&self, config: &OpenAIConfig) -> Result<Vec<String>> {
+        let url = build_endpoint(&config.base_url, MODELS_PATH)?;
+        let headers = auth_headers(&config.api_key)?;
+        let timeouts = Timeouts::from(&config.timeouts);
+        let res = timeouts
+            .limit(self.http.get(&timeouts)?.get(url).headers(headers))
+            .send()
+            .await
+            .map_err(ApiError::network)
+            .context("failed to send model list request")?;
+
+        if !res.status().is_success() {
+            return Err(ApiError::from_response(res).await.into());
+        }
+
+        let parsed: ModelListResponse = res
+            .json()
+            .await
+            .context("failed to decode model list response")?;
+        Ok(parsed.data.into_iter().map(|model| model.id).collect())
+    }
+
+    async fn transcribe(
+        &self,
+        _config: &OpenAIConfig,
+        _recording: RecordingResult,
+        _language: &TranscriptionLanguage,
+        _model: &str,
+        _prompt: Option<&str>,
+    ) -> Result<Transcription> {
+        bail!("Anthropic does not offer an audio transcription API")
+    }
+
+    async fn synthesize_speech(
+        &self,
+        _config: &OpenAIConfig,
+        _settings: &SpeechSettings,
+        _text: &str,
+    ) -> Result<Vec<u8>> {
+        bail!("Anthropic does not offer a speech synthesis API")
+    }
+}
+
+fn auth_headers(api_key: &str) -> Result<HeaderMap> {
+    if api_key.trim().is_empty() {
+        bail!("missing Anthropic API key");
+    }
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "x-api-key",
+        HeaderValue::from_str(api_key).context("invalid API key for x-api-key header")?,
+    );
+    headers.insert(
+        "anthropic-version",
+        HeaderValue::from_static(ANTHROPIC_VERSION),
+    );
+    Ok(headers)
+}
+
+fn build_messages_payload(request: &AnalyzeRequest) -> Result<MessagesPayload> {
+    // The Messages API takes the system prompt as a top-level field rather
+    // than as a message, so system and error entries are folded into it.
+    let mut system_parts: Vec<String> = Vec::new();
+    if let Some(prompt) = request.custom_prompt.as_ref() {
+        if !prompt.trim().is_empty() {
+            system_parts.push(prompt.trim().to_string());
+        }
+    }
+
+    let mut messages: Vec<Message> = Vec::new();
+    for entry in budget_history(&PromptParts::from_request(request)).entries {
+        let role = match entry.role {
+            ConversationRole::User => "user",
+            ConversationRole::Assistant | ConversationRole::Reasoning => "assistant",
+            ConversationRole::System | ConversationRole::Error => {
+                system_parts.push(entry.content.clone());
+                continue;
+            }
+            ConversationRole::Tool => continue,
+        };
+        messages.push(Message {
+            role: role.to_string(),
+            content: vec![ContentBlock::Text {
+                text: entry.content.clone(),
+            }],
+        });
+    }
+
+    let mut user_content = Vec::new();
+    for image in request
+        .images
+        .iter()
+        .filter(|_| request.capabilities.vision)
+    {
+        user_content.push(ContentBlock::attachment(image));
+    }
+    let text_prompt = request.text_prompt.trim();
+    if !text_prompt.is_empty() {
+        user_content.push(ContentBlock::Text {
+            text: text_prompt.to_string(),
+        });
+    }
+    if user_content.is_empty() {
+        bail!("nothing to send: the question is empty and no screenshot is attached");
+    }
+    messages.push(Message {
+        role: "user".into(),
+        content: user_content,
+    });
+
+    let max_tokens = request
+        .config
+        .max_output_tokens
+        .unwrap_or(DEFAULT_MAX_TOKENS);
+    Ok(MessagesPayload {
+        model: request.config.model.clone(),
+        system: if system_parts.is_empty() {
+            None
+        } else {
+            Some(system_parts.join("\n\n"))
+        },
+        messages,
+        max_tokens: request
+            .capabilities
+            .max_output_tokens
+            .map_or(max_tokens, |limit| max_tokens.min(limit)),
+        // Anthropic accepts 0.0..=1.0 while the shared setting allows up to 2.0.
+        temperature: request.config.temperature.clamp(0.0, 1.0),
+        tools: Vec::new(),
+        tool_choice: None,
+        stream: false,
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct MessagesPayload {
+    model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<Message>,
+    max_tokens: u32,
+    temperature: f32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ToolSpec>,
+    /// `none` asks for a final answer once the tool round limit is reached.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<ToolChoice>,
+    stream: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ToolChoice {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct ToolSpec {
+    name: String,
+    description: String,
+    input_schema: Value,
+}
+
+impl From<ToolDefinition> for ToolSpec {
+    fn from(definition: ToolDefinition) -> Self {
+        Self {
+            name: definition.name,
+            description: definition.description,
+            input_schema: definition.parameters,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Message {
+    role: String,
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text {
+        text: String,
+    },
+    Image {
+        source: ImageSource,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: Vec<ContentBlock>,
+    },
+}
+
+impl ContentBlock {
+    fn attachment(image: &PreparedImage) -> Self {
+        Self::image(image.mime_type(), &image.bytes)
+    }
+
+    fn image(media_type: &str, bytes: &[u8]) -> Self {
+        ContentBlock::Image {
+            source: ImageSource {
+                kind: "base64".to_string(),
+                media_type: media_type.to_string(),
+                data: general_purpose::STANDARD.encode(bytes),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ImageSource {
+    #[serde(rename = "type")]
+    kind: String,
+    media_type: String,
+    data: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesResponse {
+    pub model: Option<String>,
+    pub content: Vec<ResponseContentBlock>,
+    #[serde(default)]
+    pub usage: Option<MessagesUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessagesUsage {
+    #[serde(default)]
+    pub input_tokens: u64,
+    #[serde(default)]
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub cache_creation_input_tokens: Option<u64>,
+    #[serde(default)]
+    pub cache_read_input_tokens: Option<u64>,
+}
+
+impl From<MessagesUsage> for TokenUsage {
+    /// Anthropic reports cache reads and writes separately from
+    /// `input_tokens`; they are folded into the prompt total here.
+    fn from(usage: MessagesUsage) -> Self {
+        let cached = usage.cache_read_input_tokens.unwrap_or_default();
+        Self {
+            prompt_tokens: usage.input_tokens
+                + cached
+                + usage.cache_creation_input_tokens.unwrap_or_default(),
+            completion_tokens: usage.output_tokens,
+            reasoning_tokens: 0,
+            cached_tokens: cached,
+        }
+    }
+}
+
+/// Output of one streamed Messages API response.
+#[derive(Debug, Default)]
+struct MessagesRound {
+    text: String,
+    reasoning: String,
+    tool_uses: Vec<StreamedToolUse>,
+    usage: Option<TokenUsage>,
+    /// Stopped at `max_tokens`.
+    incomplete: bool,
+    /// `message_stop` arrived.
+    finished: bool,
+}
+
+/// A `tool_use` block whose JSON input arrives in fragments.
+#[derive(Debug)]
+struct StreamedToolUse {
+    /// Position of the block in the message, repeated by its deltas.
+    index: usize,
+    id: String,
+    name: String,
+    input: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamMessage {
+    #[serde(default)]
+    pub usage: Option<MessagesUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessageDeltaBody {
+    #[serde(default)]
+    pub stop_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OutputUsage {
+    pub output_tokens: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponseContentBlock {
+    Text {
+        text: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum MessagesStreamEvent {
+    MessageStart {
+        message: StreamMessage,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: StartedBlock,
+    },
+    ContentBlockDelta {
+        #[serde(default)]
+        index: usize,
+        delta: ContentDelta,
+    },
+    MessageDelta {
+        #[serde(default)]
+        delta: Option<MessageDeltaBody>,
+        #[serde(default)]
+        usage: Option<OutputUsage>,
+    },
+    MessageStop,
+    Error {
+        error: StreamError,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentDelta {
+    TextDelta {
+        text: String,
+    },
+    ThinkingDelta {
+        thinking: String,
+    },
+    /// A fragment of a `tool_use` block's JSON input.
+    InputJsonDelta {
+        partial_json: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StartedBlock {
+    ToolUse {
+        id: String,
+        name: String,
+    },
+    /// Text and thinking blocks, whose content arrives as deltas.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct StreamError {
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelListResponse {
+    pub data: Vec<ModelListEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelListEntry {
+    pub id: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_streamed_tool_use_blocks() {
+        let parse = |data: &str| serde_json::from_str::<MessagesStreamEvent>(data).unwrap();
+
+        let MessagesStreamEvent::ContentBlockStart {
+            index,
+            content_block: StartedBlock::ToolUse { id, name },
+        } = parse(
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use",
+                "id":"toolu_01","name":"current_time","input":{}}}"#,
+        )
+        else {
+            panic!("expected a tool_use block");
+        };
+        assert_eq!(
+            (index, id.as_str(), name.as_str()),
+            (1, "toolu_01", "current_time")
+        );
+
+        let MessagesStreamEvent::ContentBlockDelta {
+            index,
+            delta: ContentDelta::InputJsonDelta { partial_json },
+        } = parse(
+            r#"{"type":"content_block_delta","index":1,
+                "delta":{"type":"input_json_delta","partial_json":"{\"tz\":"}}"#,
+        )
+        else {
+            panic!("expected an input_json_delta");
+        };
+        assert_eq!((index, partial_json.as_str()), (1, r#"{"tz":"#));
+
+        assert!(matches!(
+            parse(
+                r#"{"type":"content_block_start","index":0,
+                    "content_block":{"type":"text","text":""}}"#
+            ),
+            MessagesStreamEvent::ContentBlockStart {
+                content_block: StartedBlock::Other,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn parses_the_stop_reason_of_message_delta() {
+        let event: MessagesStreamEvent = serde_json::from_str(
+            r#"{"type":"message_delta","delta":{"stop_reason":"max_tokens","stop_sequence":null},
+                "usage":{"output_tokens":1024}}"#,
+        )
+        .unwrap();
+
+        let MessagesStreamEvent::MessageDelta {
+            delta: Some(delta),
+            usage: Some(usage),
+        } = event
+        else {
+            panic!("expected a message_delta");
+        };
+        assert_eq!(delta.stop_reason.as_deref(), Some("max_tokens"));
+        assert_eq!(usage.output_tokens, 1024);
+    }
+
+    #[test]
+    fn serializes_tool_results_with_images() {
+        let result = ContentBlock::ToolResult {
+            tool_use_id: "toolu_01".into(),
+            content: vec![
+                ContentBlock::Text {
+                    text: "captured".into(),
+                },
+                ContentBlock::image("image/png", &[1, 2, 3]),
+            ],
+        };
+        assert_eq!(
+            serde_json::to_value(&result).unwrap(),
+            json!({
+                "type": "tool_result",
+                "tool_use_id": "toolu_01",
+                "content": [
+                    { "type": "text", "text": "captured" },
+                    {
+                        "type": "image",
+                        "source": { "type": "base64", "media_type": "image/png", "data": "AQID" },
+                    },
+                ],
+            })
+        );
+    }
+}
@@ -1,45 +1,83 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use anyhow::Result;
+use anyhow::{bail, Context as _, Result};
 use eframe::egui::{self, Color32, Margin, RichText, TextureOptions};
-use pulldown_cmark::{Event, Parser, Tag};
 use image::imageops::FilterType;
 use image::GenericImageView;
 use tokio::runtime::Handle;
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
-use crate::capture::{capture_screen, CaptureResult};
-use crate::config::{self, AppConfig, CaptureMode, ThemeVariant};
+use crate::audio::{AudioRecorder, RecordingResult};
+use crate::capture::{capture_screen, prepare_upload, CaptureResult, PreparedImage};
+use crate::catalog::{self, filter_models, CachedModelList, ModelCatalog};
+use crate::compaction;
+use crate::config::{
+    self, AppConfig, AuthScheme, CaptureMode, CompareTarget, EndpointSettings, ImageDetail,
+    McpServerConfig, OpenAIConfig, ProviderKind, ReasoningEffort, ResponsesSettings, ServiceTier,
+    SpeechFormat, SpeechSettings, ThemeVariant, TranscriptionLanguage, UploadFormat,
+    UploadSettings, Verbosity,
+};
+use crate::error::{ApiError, ApiErrorKind};
 use crate::hotkeys::{self, HotkeyAction, HotkeyHandle};
-use crate::openai::{AnalyzeRequest, AnalyzeResponse, OpenAIClient, StreamEvent};
+use crate::mcp::{McpManager, McpServerStatus};
+use crate::models::{ApiFlavor, ModelCapabilities};
+use crate::provider::{
+    AnalyzeRequest, AnalyzeResponse, Providers, StreamEvent, TranscriptSegment, Transcription,
+};
+use crate::realtime::{self, TranscriptEvent};
 use crate::session::{ConversationEntry, ConversationRole, SessionManager, WebSearchStatus};
+use crate::speech::{self, SentenceChunker, SpeechPlayer};
+use crate::tokens::{budget_history, last_tokens, PromptEstimate, PromptParts};
+use crate::tools::{SetWindowVisible, ToolRegistry};
+use crate::usage::{format_cost, TokenUsage};
+
+/// Whisper reads at most 224 tokens of a transcription prompt.
+const TRANSCRIPTION_PROMPT_TOKENS: usize = 224;
+/// Wait after a rate limit when the server does not say how long.
+const RATE_LIMIT_FALLBACK_WAIT: Duration = Duration::from_secs(10);
 
 pub struct GhostApp {
     runtime: Handle,
-    openai: Arc<OpenAIClient>,
+    providers: Providers,
+    mcp: Arc<McpManager>,
     config: AppConfig,
     session: Arc<SessionManager>,
     conversation: Vec<ConversationEntry>,
     ask_input: String,
     ask_panel_open: bool,
-    attach: Option<ScreenshotAttachment>,
-    attach_texture: Option<ScreenshotTexture>,
+    /// Images sent with the next question, in order.
+    attachments: Vec<ImageAttachment>,
+    attachment_textures: HashMap<Uuid, egui::TextureHandle>,
+    /// Attachment shown enlarged, if any.
+    previewed_attachment: Option<Uuid>,
+    /// Image file path typed into the attachment tray.
+    attachment_path: String,
     events_rx: UnboundedReceiver<AppEvent>,
     events_tx: UnboundedSender<AppEvent>,
-    request_tx: UnboundedSender<AnalyzeRequest>,
+    request_tx: UnboundedSender<AnalysisJob>,
     stream_rx: UnboundedReceiver<(Uuid, StreamEvent)>,
-    stream_tx: UnboundedSender<(Uuid, StreamEvent)>,
     hotkey_rx: UnboundedReceiver<HotkeyAction>,
     _hotkey_handle: Option<HotkeyHandle>,
     status: Option<StatusMessage>,
     settings_open: bool,
     is_hidden: bool,
     active_request: Option<Uuid>,
+    cancel_tokens: HashMap<Uuid, CancellationToken>,
+    /// The last request sent to the active model, kept to retry it. Its
+    /// config holds the question's own options, which regenerating reuses.
+    last_request: Option<AnalyzeRequest>,
+    /// A failed answer the user can retry from the status bar.
+    failed_answer: Option<FailedAnswer>,
+    /// Responses API options changed in the ask panel for the next question
+    /// only; `None` uses the configured ones.
+    question_options: Option<ResponsesSettings>,
     auto_scroll: bool,
     prompt_files: Vec<String>,
     prompt_editor_selected: Option<String>,
@@ -47,6 +85,32 @@ pub struct GhostApp {
     prompt_editor_dirty: bool,
     new_prompt_name: String,
     history_index: Option<usize>, // None = Live mode
+    /// Cached ask-panel estimate, keyed by a hash of its inputs.
+    prompt_estimate: Option<(u64, PromptEstimate)>,
+    compaction_running: bool,
+    model_catalog: ModelCatalog,
+    model_filter: String,
+    /// Base URLs whose model list was requested during this run.
+    models_requested: HashSet<String>,
+    transcribe_tx: UnboundedSender<TranscribeRequest>,
+    recording: Option<VoiceRecording>,
+    transcribing: bool,
+    /// Realtime transcript of the current recording, per utterance.
+    live_transcript: Vec<(String, String)>,
+    /// Timed segments of the last batch transcript, until the next recording.
+    transcript_segments: Vec<TranscriptSegment>,
+    speech_tx: UnboundedSender<SpeechRequest>,
+    /// Bumped to drop the queued and in-flight speech of earlier answers.
+    speech_generation: Arc<AtomicU64>,
+    speech_chunker: SentenceChunker,
+    /// Opened on the first spoken answer.
+    speech_player: Option<SpeechPlayer>,
+    /// Sentences sent for synthesis whose audio has not arrived yet.
+    speech_pending: usize,
+    /// Answers of a side-by-side comparison awaiting the user's pick.
+    comparison: Option<Comparison>,
+    /// Discarded comparison requests whose late events are ignored.
+    dropped_requests: HashSet<Uuid>,
 }
 
 impl GhostApp {
@@ -73,20 +137,34 @@ impl GhostApp {
         cc.egui_ctx.set_visuals(egui::Visuals::dark());
         cc.egui_ctx.style_mut(|style| style.url_in_tooltip = true);
 
-        let openai = Arc::new(OpenAIClient::new().unwrap_or_else(|err| {
-            log::error!("failed to construct OpenAI client: {err}");
-            OpenAIClient::new().expect("OpenAI client")
-        }));
+        let providers = Providers::new().unwrap_or_else(|err| {
+            log::error!("failed to construct provider clients: {err}");
+            Providers::new().expect("provider clients")
+        });
+
+        let mcp = Arc::new(McpManager::new());
+        Self::sync_mcp_servers(&runtime, &mcp, &config);
 
         let (events_tx, events_rx) = mpsc::unbounded_channel();
         let (request_tx, request_rx) = mpsc::unbounded_channel();
         let (stream_tx, stream_rx) = mpsc::unbounded_channel();
         spawn_analyze_worker(
             &runtime,
-            Arc::clone(&openai),
+            providers.clone(),
             request_rx,
             events_tx.clone(),
-            stream_tx.clone(),
+            stream_tx,
+        );
+        let (transcribe_tx, transcribe_rx) = mpsc::unbounded_channel();
+        spawn_transcribe_worker(&runtime, providers.clone(), transcribe_rx, events_tx.clone());
+        let (speech_tx, speech_rx) = mpsc::unbounded_channel();
+        let speech_generation = Arc::new(AtomicU64::new(0));
+        spawn_speech_worker(
+            &runtime,
+            providers.clone(),
+            speech_rx,
+            speech_generation.clone(),
+            events_tx.clone(),
         );
 
         let (hotkey_tx, hotkey_rx) = mpsc::unbounded_channel();
@@ -145,25 +223,31 @@ impl GhostApp {
 
         Self {
             runtime,
-            openai,
+            providers,
+            mcp,
             config,
             session,
             conversation: Vec::new(),
             ask_input: String::new(),
             ask_panel_open: true,
-            attach: None,
-            attach_texture: None,
+            attachments: Vec::new(),
+            attachment_textures: HashMap::new(),
+            previewed_attachment: None,
+            attachment_path: String::new(),
             events_rx,
             events_tx,
             request_tx,
             stream_rx,
-            stream_tx,
             hotkey_rx,
             _hotkey_handle: hotkey_handle,
             status: None,
             settings_open: false,
             is_hidden: false,
             active_request: None,
+            cancel_tokens: HashMap::new(),
+            last_request: None,
+            failed_answer: None,
+            question_options: None,
             auto_scroll: true,
             prompt_files,
             prompt_editor_selected,
@@ -171,6 +255,25 @@ impl GhostApp {
             prompt_editor_dirty: false,
             new_prompt_name: String::new(),
             history_index: None,
+            prompt_estimate: None,
+            compaction_running: false,
+            model_catalog: catalog::catalog_path()
+                .map(|path| ModelCatalog::load(&path))
+                .unwrap_or_default(),
+            model_filter: String::new(),
+            models_requested: HashSet::new(),
+            transcribe_tx,
+            recording: None,
+            transcribing: false,
+            live_transcript: Vec::new(),
+            transcript_segments: Vec::new(),
+            speech_tx,
+            speech_generation,
+            speech_chunker: SentenceChunker::default(),
+            speech_player: None,
+            speech_pending: 0,
+            comparison: None,
+            dropped_requests: HashSet::new(),
         }
     }
 
@@ -228,8 +331,6 @@ impl GhostApp {
         for ch in trimmed.chars() {
             if ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.') {
                 sanitized.push(ch);
-            } else if ch.is_whitespace() {
-                sanitized.push('_');
             } else {
                 sanitized.push('_');
             }
@@ -423,43 +524,220 @@ impl GhostApp {
     }
     fn process_background_events(&mut self) {
         while let Ok(event) = self.events_rx.try_recv() {
+            let event = match self.apply_comparison_event(event) {
+                Some(event) => event,
+                None => continue,
+            };
             match event {
                 AppEvent::AnalysisStarted { request_id } => {
                     self.active_request = Some(request_id);
+                    self.stop_speech();
                     // Create placeholder entry for streaming
                     let entry = ConversationEntry::new(ConversationRole::Assistant, String::new());
                     self.conversation.push(entry);
                     self.auto_scroll = true;
                     self.show_status(
-                        "Analyzing with OpenAI…",
+                        format!("Analyzing with {}…", self.config.provider.label()),
                         StatusKind::Info,
                         Some(Duration::from_secs(2)),
                     );
                 }
                 AppEvent::AnalysisFinished { response } => {
+                    self.cancel_tokens.remove(&response.request_id);
                     // Update the last entry with the final answer
                     if let Some(last) = self.conversation.last_mut() {
                         if matches!(last.role, ConversationRole::Assistant) {
                             last.content = response.answer.clone();
+                            last.interrupted = response.interrupted;
+                            last.usage = response.usage;
+                            // Citation events may still be queued behind this one.
+                            for citation in response.citations.iter().cloned() {
+                                last.add_citation(citation);
+                            }
+                            last.cost_usd = response.usage.and_then(|usage| {
+                                self.config
+                                    .model_capabilities(&response.model)
+                                    .price
+                                    .map(|price| price.cost(&usage))
+                            });
                             self.session.append(last.clone());
                         }
                     }
                     self.active_request = None;
+                    if let Some(rest) = self.speech_chunker.finish() {
+                        self.queue_speech(rest);
+                    }
                     if let Err(err) = self.session.write_plaintext_log() {
                         log::warn!("failed to persist conversation log: {err}");
                     }
+                    self.maybe_compact_history();
+                    if response.interrupted {
+                        self.show_status(
+                            "Answer stopped",
+                            StatusKind::Info,
+                            Some(Duration::from_secs(2)),
+                        );
+                    } else {
+                        self.show_status(
+                            "Response received",
+                            StatusKind::Success,
+                            Some(Duration::from_secs(2)),
+                        );
+                    }
+                }
+                AppEvent::AnalysisCancelled { request_id } => {
+                    self.cancel_tokens.remove(&request_id);
+                    // Cancelled before any output arrived: drop the placeholder.
+                    self.drop_empty_placeholder();
+                    self.active_request = None;
                     self.show_status(
-                        "Response received",
-                        StatusKind::Success,
+                        "Request cancelled",
+                        StatusKind::Info,
                         Some(Duration::from_secs(2)),
                     );
                 }
                 AppEvent::AnalysisFailed {
-                    request_id: _,
+                    request_id,
                     error,
+                    api_error,
                 } => {
+                    log::error!("analysis request {request_id} failed: {error}");
+                    self.cancel_tokens.remove(&request_id);
                     self.active_request = None;
-                    self.show_status(format!("Analysis failed: {error}"), StatusKind::Error, None);
+                    let partial = self.keep_partial_answer();
+                    self.drop_empty_placeholder();
+                    match api_error {
+                        Some(api_error) => self.handle_api_error(request_id, api_error, partial),
+                        None => self.show_status(
+                            format!("Analysis failed: {error}"),
+                            StatusKind::Error,
+                            None,
+                        ),
+                    }
+                }
+                AppEvent::HistoryCompacted {
+                    session_id,
+                    summary,
+                    covered,
+                    model,
+                    usage,
+                } => {
+                    self.compaction_running = false;
+                    // The session was cleared while the summary was running.
+                    if session_id != self.session.current_session_id() {
+                        continue;
+                    }
+                    let count = covered.len();
+                    self.session.apply_summary(summary, covered);
+                    if let Some(usage) = usage {
+                        let price = self.config.model_capabilities(&model).price;
+                        self.session.add_usage(usage, price.map(|price| price.cost(&usage)));
+                    }
+                    if let Err(err) = self.session.write_plaintext_log() {
+                        log::warn!("failed to persist conversation log: {err}");
+                    }
+                    self.show_status(
+                        format!("Summarized {count} older messages to save context"),
+                        StatusKind::Info,
+                        Some(Duration::from_secs(3)),
+                    );
+                }
+                AppEvent::CompactionFailed { error } => {
+                    self.compaction_running = false;
+                    log::warn!("history compaction failed: {error}");
+                    self.show_status(
+                        format!("Could not summarize older messages: {error}"),
+                        StatusKind::Warning,
+                        Some(Duration::from_secs(5)),
+                    );
+                }
+                AppEvent::Transcript(event) => self.apply_transcript_event(event),
+                AppEvent::TranscriptionFinished { transcription } => {
+                    self.transcribing = false;
+                    self.live_transcript.clear();
+                    self.apply_transcript(transcription);
+                }
+                AppEvent::TranscriptionFailed { error } => {
+                    match self.recording.as_mut() {
+                        // Live transcription broke while still recording; the
+                        // buffered audio is transcribed in one go on stop.
+                        Some(recording) if recording.realtime => {
+                            recording.realtime = false;
+                            self.live_transcript.clear();
+                            log::warn!("realtime transcription failed: {error}");
+                            self.show_status(
+                                format!(
+                                    "Live transcription unavailable ({error}); \
+                                     the recording will be transcribed when it stops"
+                                ),
+                                StatusKind::Warning,
+                                Some(Duration::from_secs(5)),
+                            );
+                        }
+                        _ => {
+                            self.transcribing = false;
+                            self.live_transcript.clear();
+                            self.show_status(
+                                format!("Transcription failed: {error}"),
+                                StatusKind::Error,
+                                None,
+                            );
+                        }
+                    }
+                }
+                AppEvent::SpeechReady {
+                    generation,
+                    samples,
+                    sample_rate,
+                } => {
+                    if generation != self.speech_generation.load(Ordering::SeqCst) {
+                        continue;
+                    }
+                    self.speech_pending = self.speech_pending.saturating_sub(1);
+                    if self.speech_player.is_none() {
+                        match SpeechPlayer::new() {
+                            Ok(player) => self.speech_player = Some(player),
+                            Err(err) => {
+                                self.stop_speech();
+                                self.show_status(
+                                    format!("Audio playback failed: {err:#}"),
+                                    StatusKind::Error,
+                                    None,
+                                );
+                                continue;
+                            }
+                        }
+                    }
+                    if let Some(player) = &self.speech_player {
+                        player.enqueue(&samples, sample_rate);
+                    }
+                }
+                AppEvent::SpeechFailed { generation, error } => {
+                    if generation != self.speech_generation.load(Ordering::SeqCst) {
+                        continue;
+                    }
+                    // Give up on the rest of this answer instead of failing
+                    // once per sentence.
+                    self.stop_speech();
+                    self.show_status(
+                        format!("Speech failed: {error}"),
+                        StatusKind::Error,
+                        None,
+                    );
+                }
+                AppEvent::UploadPrepared {
+                    attachment_id,
+                    settings,
+                    result,
+                } => self.apply_prepared_upload(attachment_id, settings, result),
+                AppEvent::WindowVisibility { visible } => self.is_hidden = !visible,
+                AppEvent::ModelsLoaded { base_url, models } => {
+                    self.model_catalog.insert(&base_url, models);
+                    if let Err(err) = catalog::catalog_path()
+                        .and_then(|path| self.model_catalog.save(&path))
+                    {
+                        log::warn!("failed to cache model list: {err}");
+                    }
                 }
                 AppEvent::Status {
                     text,
@@ -473,6 +751,10 @@ impl GhostApp {
 
         // Process streaming events
         while let Ok((request_id, stream_event)) = self.stream_rx.try_recv() {
+            if let Some(column) = self.comparison_column(request_id) {
+                column.apply_stream_event(stream_event);
+                continue;
+            }
             if Some(request_id) != self.active_request {
                 continue;
             }
@@ -485,6 +767,11 @@ impl GhostApp {
                             self.auto_scroll = true;
                         }
                     }
+                    if self.config.speech.enabled {
+                        for sentence in self.speech_chunker.push(&delta) {
+                            self.queue_speech(sentence);
+                        }
+                    }
                 }
                 StreamEvent::ReasoningDelta(delta) => {
                     if let Some(last) = self.conversation.last_mut() {
@@ -533,6 +820,53 @@ impl GhostApp {
                         }
                     }
                 }
+                StreamEvent::Citation(citation) => {
+                    if let Some(last) = self.conversation.last_mut() {
+                        if matches!(last.role, ConversationRole::Assistant) {
+                            last.add_citation(citation);
+                        }
+                    }
+                }
+                StreamEvent::ToolCall { name, .. } => {
+                    self.show_status(
+                        format!("Running tool {name}…"),
+                        StatusKind::Info,
+                        Some(Duration::from_secs(2)),
+                    );
+                }
+                StreamEvent::ToolResult {
+                    name,
+                    arguments,
+                    output,
+                } => {
+                    let entry = ConversationEntry::new(
+                        ConversationRole::Tool,
+                        format_tool_entry(&name, &arguments, &output),
+                    );
+                    self.session.append(entry.clone());
+                    // Keep the streaming answer placeholder last.
+                    let index = match self.conversation.last() {
+                        Some(last) if matches!(last.role, ConversationRole::Assistant) => {
+                            self.conversation.len() - 1
+                        }
+                        _ => self.conversation.len(),
+                    };
+                    self.conversation.insert(index, entry);
+                    self.auto_scroll = true;
+                }
+                StreamEvent::Retrying(notice) => {
+                    self.show_status(
+                        format!(
+                            "{reason}; retrying in {secs}s ({attempt}/{max})",
+                            reason = notice.reason,
+                            secs = notice.delay.as_secs_f32().ceil() as u64,
+                            attempt = notice.attempt,
+                            max = notice.max_retries,
+                        ),
+                        StatusKind::Warning,
+                        Some(notice.delay),
+                    );
+                }
                 StreamEvent::Error(error) => {
                     log::error!("Stream error: {error}");
                     self.show_status(format!("Stream error: {error}"), StatusKind::Error, None);
@@ -559,11 +893,208 @@ impl GhostApp {
                         self.show_status(format!("Capture failed: {err}"), StatusKind::Error, None);
                     }
                 }
+                HotkeyAction::StopAnswer => {
+                    self.stop_active_answer();
+                }
+                HotkeyAction::ToggleRecording => {
+                    self.toggle_recording();
+                }
+            }
+        }
+    }
+
+    fn toggle_recording(&mut self) {
+        let result = if self.recording.is_some() {
+            self.stop_recording()
+        } else {
+            self.start_recording()
+        };
+        if let Err(err) = result {
+            self.show_status(format!("Voice input failed: {err:#}"), StatusKind::Error, None);
+        }
+    }
+
+    fn start_recording(&mut self) -> Result<()> {
+        let settings = self.config.transcription.clone();
+        if !settings.enabled {
+            bail!("voice transcription is disabled in Settings");
+        }
+        if self.transcribing {
+            bail!("the previous recording is still being transcribed");
+        }
+        // Transcription always goes through the OpenAI endpoint.
+        let openai = self.config.openai.clone();
+        if !openai.has_credentials() {
+            bail!("OpenAI API Key 未設定");
+        }
+
+        let prompt = self.transcription_prompt();
+        let recording = if settings.realtime {
+            let (audio_tx, audio_rx) = mpsc::unbounded_channel();
+            let recorder = AudioRecorder::start_streaming(audio_tx)?;
+            let sample_rate = recorder.sample_rate();
+            let events = self.events_tx.clone();
+            self.runtime.spawn(async move {
+                let (transcript_tx, mut transcript_rx) = mpsc::unbounded_channel();
+                let forward = {
+                    let events = events.clone();
+                    async move {
+                        while let Some(event) = transcript_rx.recv().await {
+                            let _ = events.send(AppEvent::Transcript(event));
+                        }
+                    }
+                };
+                let (result, ()) = tokio::join!(
+                    realtime::stream_transcription(
+                        &openai,
+                        &settings,
+                        prompt.as_deref(),
+                        sample_rate,
+                        audio_rx,
+                        transcript_tx,
+                    ),
+                    forward
+                );
+                let _ = events.send(match result {
+                    Ok(text) => AppEvent::TranscriptionFinished {
+                        transcription: Transcription {
+                            text,
+                            ..Transcription::default()
+                        },
+                    },
+                    Err(err) => AppEvent::TranscriptionFailed {
+                        error: format!("{err:#}"),
+                    },
+                });
+            });
+            VoiceRecording {
+                recorder,
+                started_at: Instant::now(),
+                realtime: true,
+            }
+        } else {
+            VoiceRecording {
+                recorder: AudioRecorder::start()?,
+                started_at: Instant::now(),
+                realtime: false,
+            }
+        };
+        self.recording = Some(recording);
+        self.live_transcript.clear();
+        self.transcript_segments.clear();
+        Ok(())
+    }
+
+    fn stop_recording(&mut self) -> Result<()> {
+        let Some(recording) = self.recording.take() else {
+            return Ok(());
+        };
+        // Stopping the recorder closes the realtime audio channel, which
+        // commits the last utterance and ends that session.
+        let result = recording.recorder.stop()?;
+        self.transcribing = true;
+        if recording.realtime {
+            return Ok(());
+        }
+        if result.samples.is_empty() {
+            self.transcribing = false;
+            bail!("no audio was recorded");
+        }
+        let request = TranscribeRequest {
+            config: self.config.openai.clone(),
+            recording: result,
+            language: self.config.transcription.language.clone(),
+            model: self.config.transcription.model.clone(),
+            prompt: self.transcription_prompt(),
+        };
+        if self.transcribe_tx.send(request).is_err() {
+            self.transcribing = false;
+            bail!("transcription worker stopped");
+        }
+        Ok(())
+    }
+
+    /// Vocabulary hint for the transcription model: the active prompt, if
+    /// enabled, then the configured terms. The models only read the end of
+    /// the prompt, so the terms go last and the start is what gets cut.
+    fn transcription_prompt(&self) -> Option<String> {
+        let settings = &self.config.transcription;
+        let mut parts = Vec::new();
+        if settings.use_active_prompt {
+            if let Some(prompt) = self.load_active_prompt() {
+                if !prompt.trim().is_empty() {
+                    parts.push(prompt.trim().to_string());
+                }
+            }
+        }
+        if !settings.vocabulary.trim().is_empty() {
+            parts.push(settings.vocabulary.trim().to_string());
+        }
+        if parts.is_empty() {
+            return None;
+        }
+        let prompt = parts.join("\n");
+        Some(last_tokens(&settings.model, &prompt, TRANSCRIPTION_PROMPT_TOKENS).to_string())
+    }
+
+    fn apply_transcript_event(&mut self, event: TranscriptEvent) {
+        match event {
+            TranscriptEvent::Delta { item_id, text } => {
+                match self.live_transcript.iter_mut().find(|(id, _)| *id == item_id) {
+                    Some((_, live)) => live.push_str(&text),
+                    None => self.live_transcript.push((item_id, text)),
+                }
+            }
+            TranscriptEvent::Completed { item_id, text } => {
+                match self.live_transcript.iter_mut().find(|(id, _)| *id == item_id) {
+                    Some((_, live)) => *live = text,
+                    None => self.live_transcript.push((item_id, text)),
+                }
+            }
+            TranscriptEvent::Error(error) => {
+                log::warn!("realtime transcription error: {error}");
             }
         }
     }
 
+    /// Puts a finished transcript into the Ask box, or sends it right away
+    /// when auto-submit is on.
+    fn apply_transcript(&mut self, transcription: Transcription) {
+        if let Some(duration) = transcription.duration {
+            log::info!(
+                "transcribed {duration:.1}s of {} audio in {} segments",
+                transcription.language.as_deref().unwrap_or("unknown"),
+                transcription.segments.len()
+            );
+        }
+        self.transcript_segments = transcription.segments;
+        let text = transcription.text.trim();
+        if text.is_empty() {
+            self.show_status(
+                "No speech recognized",
+                StatusKind::Warning,
+                Some(Duration::from_secs(3)),
+            );
+            return;
+        }
+        if !self.ask_input.trim().is_empty() {
+            self.ask_input.push(' ');
+        }
+        self.ask_input.push_str(text);
+        self.ask_panel_open = true;
+        if self.config.transcription.auto_submit {
+            self.submit_current_prompt();
+        }
+    }
+
     fn capture_and_attach(&mut self) -> Result<()> {
+        if !self.current_capabilities().vision {
+            bail!(
+                "{} does not accept images",
+                self.config.active_llm_config().model
+            );
+        }
+
         // Check if we should hide before capture
         let should_hide = self.config.capture.hide_before_capture;
 
@@ -582,22 +1113,126 @@ impl GhostApp {
             self.is_hidden = false;
         }
 
-        let attachment = ScreenshotAttachment::from_capture(capture)?;
-        self.attach = Some(attachment);
-        self.attach_texture = None;
+        let attachment = ImageAttachment::from_capture(capture, &self.config.capture.upload)?;
+        self.add_attachment(attachment);
+        Ok(())
+    }
+
+    /// Attaches an image file from disk.
+    fn attach_file(&mut self, path: &Path) -> Result<()> {
+        if !self.current_capabilities().vision {
+            bail!(
+                "{} does not accept images",
+                self.config.active_llm_config().model
+            );
+        }
+        let attachment = ImageAttachment::from_file(path, &self.config.capture.upload)?;
+        self.add_attachment(attachment);
+        Ok(())
+    }
+
+    fn add_attachment(&mut self, attachment: ImageAttachment) {
+        let name = attachment.name.clone();
+        self.attachments.push(attachment);
         self.show_status(
-            "Screenshot attached",
+            format!("{name} attached ({} images)", self.attachments.len()),
             StatusKind::Info,
             Some(Duration::from_secs(2)),
         );
-        Ok(())
+    }
+
+    fn remove_attachment(&mut self, id: Uuid) {
+        self.attachments.retain(|att| att.id != id);
+        self.attachment_textures.remove(&id);
+        if self.previewed_attachment == Some(id) {
+            self.previewed_attachment = None;
+        }
+    }
+
+    fn clear_attachments(&mut self) {
+        self.attachments.clear();
+        self.attachment_textures.clear();
+        self.previewed_attachment = None;
+    }
+
+    /// Uploads of all attachments, in order.
+    fn attachment_uploads(&self) -> Vec<PreparedImage> {
+        self.attachments
+            .iter()
+            .map(|att| att.upload.clone())
+            .collect()
+    }
+
+    /// Attaches image files dropped onto the window.
+    fn attach_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped = ctx.input(|i| i.raw.dropped_files.clone());
+        for file in dropped {
+            let Some(path) = file.path else {
+                continue;
+            };
+            if let Err(err) = self.attach_file(&path) {
+                self.show_status(
+                    format!("Cannot attach {}: {err}", path.display()),
+                    StatusKind::Error,
+                    None,
+                );
+            }
+        }
+    }
+
+    /// Sends one sentence of the streaming answer for synthesis. Speech
+    /// always goes through the OpenAI endpoint.
+    fn queue_speech(&mut self, text: String) {
+        if !self.config.speech.enabled {
+            return;
+        }
+        let request = SpeechRequest {
+            generation: self.speech_generation.load(Ordering::SeqCst),
+            config: self.config.openai.clone(),
+            settings: self.config.speech.clone(),
+            text,
+        };
+        if self.speech_tx.send(request).is_ok() {
+            self.speech_pending += 1;
+        }
+    }
+
+    fn stop_speech(&mut self) {
+        self.speech_generation.fetch_add(1, Ordering::SeqCst);
+        self.speech_chunker = SentenceChunker::default();
+        self.speech_pending = 0;
+        if let Some(player) = &self.speech_player {
+            player.stop();
+        }
+    }
+
+    fn is_speaking(&self) -> bool {
+        self.speech_pending > 0
+            || self
+                .speech_player
+                .as_ref()
+                .is_some_and(SpeechPlayer::is_playing)
+    }
+
+    fn stop_active_answer(&mut self) {
+        let Some(request_id) = self.active_request else {
+            return;
+        };
+        if let Some(token) = self.cancel_tokens.get(&request_id) {
+            token.cancel();
+            self.show_status("Stopping…", StatusKind::Info, Some(Duration::from_secs(2)));
+        }
     }
 
     fn clear_session(&mut self) {
+        self.stop_active_answer();
+        self.discard_comparison();
+        self.stop_speech();
         self.session.reset();
         self.conversation.clear();
-        self.attach = None;
-        self.attach_texture = None;
+        self.clear_attachments();
+        self.last_request = None;
+        self.failed_answer = None;
         self.auto_scroll = true;
         self.history_index = None;
         self.show_status(
@@ -652,12 +1287,11 @@ impl GhostApp {
 
     fn regenerate_answer(&mut self) {
         // Get current display index
-        let current_idx = self.history_index.unwrap_or_else(|| {
-            if self.conversation.is_empty() {
-                return;
-            }
-            self.conversation.len() - 1
-        });
+        let current_idx = match self.history_index {
+            Some(idx) => idx,
+            None if self.conversation.is_empty() => return,
+            None => self.conversation.len() - 1,
+        };
 
         if current_idx >= self.conversation.len() {
             self.show_status(
@@ -668,19 +1302,11 @@ impl GhostApp {
             return;
         }
 
-        // Get the question from current entry
-        let entry = &self.conversation[current_idx];
-        let question = if matches!(entry.role, ConversationRole::User) {
-            entry.content.clone()
-        } else if current_idx > 0 {
-            // If current entry is assistant, look for the previous user message
-            self.conversation[..current_idx]
-                .iter()
-                .rev()
-                .find(|e| matches!(e.role, ConversationRole::User))
-                .map(|e| e.content.clone())
-                .unwrap_or_default()
-        } else {
+        // The question is the current entry, or the user message before it.
+        let Some(question_idx) = self.conversation[..=current_idx]
+            .iter()
+            .rposition(|e| matches!(e.role, ConversationRole::User))
+        else {
             self.show_status(
                 "No question to regenerate from",
                 StatusKind::Warning,
@@ -688,6 +1314,7 @@ impl GhostApp {
             );
             return;
         };
+        let question = self.conversation[question_idx].content.clone();
 
         if question.is_empty() {
             self.show_status(
@@ -698,44 +1325,43 @@ impl GhostApp {
             return;
         }
 
-        // Build history (exclude current and later entries)
-        let history: VecDeque<ConversationEntry> = self.conversation
-            .iter()
-            .take(current_idx)
-            .cloned()
-            .collect();
-
-        // Truncate conversation at current index
-        self.conversation.truncate(current_idx);
+        // The question travels as `text_prompt`, so history stops before it.
+        let history = self
+            .session
+            .request_history(self.conversation.iter().take(question_idx));
 
-        // Re-add the question if it was the assistant response we're regenerating
-        if !matches!(entry.role, ConversationRole::User) {
-            let user_entry = ConversationEntry::new(ConversationRole::User, question.clone());
-            self.session.append(user_entry.clone());
-            self.conversation.push(user_entry);
-        }
+        // Keep the question and drop the answers that follow it.
+        self.conversation.truncate(question_idx + 1);
 
         // Prepare request
-        let request_id = Uuid::new_v4();
         let custom_prompt = self.load_active_prompt();
-        let screenshot = self.attach.as_ref().map(|att| att.png.clone());
+        let images = self.attachment_uploads();
+        let mut llm_config = self.config.active_llm_config();
+        // Ask again with the options the question was last sent with.
+        if let Some(previous) = self
+            .last_request
+            .as_ref()
+            .or(self.failed_answer.as_ref().map(|failed| &failed.request))
+            .filter(|previous| previous.text_prompt == question)
+        {
+            llm_config.responses = previous.config.responses;
+        }
 
         let analyze_request = AnalyzeRequest {
-            request_id,
-            config: self.config.openai.clone(),
+            request_id: Uuid::new_v4(),
+            provider: self.config.provider,
+            capabilities: self.current_capabilities(),
+            config: llm_config,
             text_prompt: question,
             custom_prompt,
-            screenshot_png: screenshot,
+            images,
             history,
+            tools: self.tool_registry(),
+            max_tool_rounds: self.config.tools.max_rounds,
+            cancel: CancellationToken::new(),
         };
 
-        if let Err(err) = self.request_tx.send(analyze_request) {
-            self.show_status(
-                format!("Failed to regenerate: {err}"),
-                StatusKind::Error,
-                None,
-            );
-        } else {
+        if self.dispatch(AnalysisJob::Single(Box::new(analyze_request))) {
             self.history_index = None; // Reset to Live mode
             self.auto_scroll = true;
             self.show_status(
@@ -747,59 +1373,541 @@ impl GhostApp {
     }
 
     fn submit_current_prompt(&mut self) {
-        let trimmed = self.ask_input.trim().to_string();
-        if trimmed.is_empty() && self.attach.is_none() {
+        let Some(question) = self.pending_question() else {
+            return;
+        };
+        let llm_config = self.config.active_llm_config();
+        if !llm_config.has_credentials() {
             self.show_status(
-                "輸入問題或附加截圖後再送出",
+                format!("{} API Key 未設定", self.config.provider.label()),
+                StatusKind::Error,
+                None,
+            );
+            return;
+        }
+
+        self.discard_comparison();
+        let history = self.push_question(&question);
+        let request = self.build_request(self.config.provider, llm_config, &question, history);
+        self.queue_job(AnalysisJob::Single(Box::new(request)));
+    }
+
+    /// Asks every configured comparison model the same question, with the
+    /// same screenshot, and shows their answers side by side.
+    fn submit_comparison(&mut self) {
+        let targets = self.config.compare.targets.clone();
+        if targets.len() < 2 {
+            self.show_status(
+                "Add at least two models to compare in Settings",
                 StatusKind::Warning,
                 Some(Duration::from_secs(3)),
             );
             return;
         }
-        if self.config.openai.api_key.trim().is_empty() {
-            self.show_status("OpenAI API Key 未設定", StatusKind::Error, None);
+        let Some(question) = self.pending_question() else {
             return;
-        }
-
-        if !trimmed.is_empty() {
-            let entry = ConversationEntry::new(ConversationRole::User, trimmed.clone());
-            self.session.append(entry.clone());
-            self.conversation.push(entry);
-        }
-
-        let history: VecDeque<ConversationEntry> = self.conversation.iter().cloned().collect();
-        let request_id = Uuid::new_v4();
-        let custom_prompt = self.load_active_prompt();
-        let screenshot = self.attach.as_ref().map(|att| att.png.clone());
-        let analyze_request = AnalyzeRequest {
-            request_id,
-            config: self.config.openai.clone(),
-            text_prompt: if trimmed.is_empty() {
-                String::new()
-            } else {
-                trimmed
-            },
-            custom_prompt,
-            screenshot_png: screenshot,
-            history,
         };
-
-        if let Err(err) = self.request_tx.send(analyze_request) {
+        if let Some(target) = targets
+            .iter()
+            .find(|target| !self.config.compare_config(target).has_credentials())
+        {
             self.show_status(
-                format!("Failed to queue analysis request: {err}"),
+                format!("{} API Key 未設定", target.provider.label()),
                 StatusKind::Error,
                 None,
             );
-        } else {
-            self.attach = None;
-            self.attach_texture = None;
-            self.ask_input.clear();
-            self.auto_scroll = true;
-            self.history_index = None; // Reset to Live mode when submitting new prompt
+            return;
         }
-    }
 
-    fn load_active_prompt(&self) -> Option<String> {
+        self.stop_active_answer();
+        self.discard_comparison();
+        let history = self.push_question(&question);
+        let question_id = (!question.is_empty())
+            .then(|| self.conversation.last().map(|entry| entry.id))
+            .flatten();
+        let requests: Vec<AnalyzeRequest> = targets
+            .iter()
+            .map(|target| {
+                self.build_request(
+                    target.provider,
+                    self.config.compare_config(target),
+                    &question,
+                    history.clone(),
+                )
+            })
+            .collect();
+        let columns = targets
+            .iter()
+            .zip(&requests)
+            .map(|(target, request)| ComparisonColumn::new(request.request_id, target.label()))
+            .collect();
+        if self.queue_job(AnalysisJob::Compare(requests)) {
+            self.comparison = Some(Comparison {
+                question_id,
+                columns,
+            });
+        }
+    }
+
+    /// The typed question, or `None` after a warning when there is neither
+    /// a question nor a screenshot to send.
+    fn pending_question(&mut self) -> Option<String> {
+        let trimmed = self.ask_input.trim().to_string();
+        if trimmed.is_empty() && self.attachments.is_empty() {
+            self.show_status(
+                "輸入問題或附加截圖後再送出",
+                StatusKind::Warning,
+                Some(Duration::from_secs(3)),
+            );
+            return None;
+        }
+        Some(trimmed)
+    }
+
+    /// Adds the question to the conversation and returns the history that
+    /// precedes it.
+    fn push_question(&mut self, question: &str) -> VecDeque<ConversationEntry> {
+        // The question travels as `text_prompt`, so history stops before it.
+        let history = self.session.request_history(&self.conversation);
+        if !question.is_empty() {
+            let entry = ConversationEntry::new(ConversationRole::User, question);
+            self.session.append(entry.clone());
+            self.conversation.push(entry);
+        }
+        history
+    }
+
+    fn build_request(
+        &self,
+        provider: ProviderKind,
+        mut llm_config: OpenAIConfig,
+        question: &str,
+        history: VecDeque<ConversationEntry>,
+    ) -> AnalyzeRequest {
+        if let Some(options) = self.question_options {
+            llm_config.responses = options;
+        }
+        AnalyzeRequest {
+            request_id: Uuid::new_v4(),
+            provider,
+            capabilities: self.config.model_capabilities(&llm_config.model),
+            config: llm_config,
+            text_prompt: question.to_string(),
+            custom_prompt: self.load_active_prompt(),
+            images: self.attachment_uploads(),
+            history,
+            tools: self.tool_registry(),
+            max_tool_rounds: self.config.tools.max_rounds,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Hands a submission to the worker and clears the ask panel.
+    fn queue_job(&mut self, job: AnalysisJob) -> bool {
+        if !self.dispatch(job) {
+            return false;
+        }
+        self.clear_attachments();
+        self.ask_input.clear();
+        self.question_options = None;
+        self.auto_scroll = true;
+        self.history_index = None; // Reset to Live mode when submitting new prompt
+        true
+    }
+
+    /// Sends a job to the analysis worker and tracks its cancel tokens.
+    fn dispatch(&mut self, job: AnalysisJob) -> bool {
+        let cancels: Vec<(Uuid, CancellationToken)> = match &job {
+            AnalysisJob::Single(request) => vec![(request.request_id, request.cancel.clone())],
+            AnalysisJob::Compare(requests) => requests
+                .iter()
+                .map(|request| (request.request_id, request.cancel.clone()))
+                .collect(),
+        };
+        let single = match &job {
+            AnalysisJob::Single(request) => Some((**request).clone()),
+            AnalysisJob::Compare(_) => None,
+        };
+        if let Err(err) = self.request_tx.send(job) {
+            self.show_status(
+                format!("Failed to queue analysis request: {err}"),
+                StatusKind::Error,
+                None,
+            );
+            return false;
+        }
+        self.cancel_tokens.extend(cancels);
+        self.last_request = single;
+        self.failed_answer = None;
+        true
+    }
+
+    /// Reacts to a rejected request according to what went wrong. `partial`
+    /// is the interrupted answer kept from the failed stream, if any.
+    fn handle_api_error(&mut self, request_id: Uuid, error: ApiError, partial: Option<Uuid>) {
+        let kind = error.kind;
+        self.show_status(
+            format!("{error} — {}", kind.hint()),
+            StatusKind::Error,
+            None,
+        );
+        match kind {
+            ApiErrorKind::Auth | ApiErrorKind::ModelNotFound | ApiErrorKind::EndpointNotFound => {
+                self.settings_open = true
+            }
+            ApiErrorKind::RateLimit
+            | ApiErrorKind::ContextLengthExceeded
+            | ApiErrorKind::Server
+            | ApiErrorKind::Network
+            | ApiErrorKind::Timeout => {
+                let Some(request) = self
+                    .last_request
+                    .take()
+                    .filter(|request| request.request_id == request_id)
+                else {
+                    return;
+                };
+                let retry_at = (kind == ApiErrorKind::RateLimit).then(|| {
+                    Instant::now() + error.retry_after.unwrap_or(RATE_LIMIT_FALLBACK_WAIT)
+                });
+                self.failed_answer = Some(FailedAnswer {
+                    request,
+                    kind,
+                    retry_at,
+                    partial,
+                });
+            }
+            ApiErrorKind::Quota | ApiErrorKind::ContentFilter | ApiErrorKind::InvalidRequest => {}
+        }
+    }
+
+    /// Sends the failed request again, optionally without the older half of
+    /// its history.
+    fn retry_failed_answer(&mut self, trim_history: bool) {
+        let Some(failed) = self.failed_answer.take() else {
+            return;
+        };
+        let mut request = failed.request;
+        if trim_history {
+            let dropped = request.history.len().div_ceil(2);
+            request.history.drain(..dropped);
+        }
+        request.request_id = Uuid::new_v4();
+        request.cancel = CancellationToken::new();
+        if self.dispatch(AnalysisJob::Single(Box::new(request))) {
+            // The new answer takes the place of what the failed one delivered.
+            if let Some(id) = failed.partial {
+                self.conversation.retain(|entry| entry.id != id);
+                self.session.remove(id);
+                if let Err(err) = self.session.write_plaintext_log() {
+                    log::warn!("failed to persist conversation log: {err}");
+                }
+            }
+            self.history_index = None;
+            self.auto_scroll = true;
+            self.show_status("Retrying…", StatusKind::Info, Some(Duration::from_secs(2)));
+        }
+    }
+
+    /// Keeps what a failed stream delivered before it broke off, such as a
+    /// stalled answer, as an interrupted entry and returns its id.
+    fn keep_partial_answer(&mut self) -> Option<Uuid> {
+        let last = self.conversation.last_mut()?;
+        if !matches!(last.role, ConversationRole::Assistant) || last.content.is_empty() {
+            return None;
+        }
+        last.interrupted = true;
+        self.session.append(last.clone());
+        if let Err(err) = self.session.write_plaintext_log() {
+            log::warn!("failed to persist conversation log: {err}");
+        }
+        Some(last.id)
+    }
+
+    /// Removes the streaming placeholder if no output arrived.
+    fn drop_empty_placeholder(&mut self) {
+        if self.conversation.last().is_some_and(|last| {
+            matches!(last.role, ConversationRole::Assistant) && last.content.is_empty()
+        }) {
+            self.conversation.pop();
+        }
+    }
+
+    fn comparison_column(&mut self, request_id: Uuid) -> Option<&mut ComparisonColumn> {
+        self.comparison
+            .as_mut()?
+            .columns
+            .iter_mut()
+            .find(|column| column.request_id == request_id)
+    }
+
+    /// Routes worker events of comparison requests to their columns. Returns
+    /// the events that belong to the regular conversation.
+    fn apply_comparison_event(&mut self, event: AppEvent) -> Option<AppEvent> {
+        let request_id = match &event {
+            AppEvent::AnalysisStarted { request_id }
+            | AppEvent::AnalysisCancelled { request_id }
+            | AppEvent::AnalysisFailed { request_id, .. } => *request_id,
+            AppEvent::AnalysisFinished { response } => response.request_id,
+            _ => return Some(event),
+        };
+        if self.dropped_requests.contains(&request_id) {
+            if !matches!(event, AppEvent::AnalysisStarted { .. }) {
+                self.dropped_requests.remove(&request_id);
+            }
+            return None;
+        }
+        let cost = match &event {
+            AppEvent::AnalysisFinished { response } => response.usage.and_then(|usage| {
+                self.config
+                    .model_capabilities(&response.model)
+                    .price
+                    .map(|price| price.cost(&usage))
+            }),
+            _ => None,
+        };
+        let column = self.comparison_column(request_id)?;
+        match event {
+            AppEvent::AnalysisStarted { .. } => column.start(),
+            AppEvent::AnalysisFinished { response } => {
+                column.entry.content = response.answer;
+                column.entry.interrupted = response.interrupted;
+                column.entry.usage = response.usage;
+                for citation in response.citations {
+                    column.entry.add_citation(citation);
+                }
+                column.entry.cost_usd = cost;
+                column.finish(ColumnStatus::Finished);
+            }
+            AppEvent::AnalysisCancelled { .. } => {
+                column.entry.interrupted = true;
+                column.finish(ColumnStatus::Finished);
+            }
+            AppEvent::AnalysisFailed { error, .. } => column.finish(ColumnStatus::Failed(error)),
+            _ => {}
+        }
+        self.cancel_tokens.remove(&request_id);
+        None
+    }
+
+    /// Stops the comparison answers that are still streaming.
+    fn stop_comparison(&mut self) {
+        let Some(comparison) = &self.comparison else {
+            return;
+        };
+        for column in comparison.columns.iter().filter(|column| column.is_running()) {
+            if let Some(token) = self.cancel_tokens.get(&column.request_id) {
+                token.cancel();
+            }
+        }
+    }
+
+    /// Drops the comparison without keeping an answer.
+    fn discard_comparison(&mut self) {
+        self.close_comparison(None);
+    }
+
+    /// Ends the comparison and returns the column at `keep`. Answers still
+    /// streaming are cancelled and their remaining events ignored. The other
+    /// finished answers still count toward the session's usage, and without
+    /// a kept answer the question leaves the session again.
+    fn close_comparison(&mut self, keep: Option<usize>) -> Option<ComparisonColumn> {
+        self.stop_comparison();
+        let comparison = self.comparison.take()?;
+        let mut kept = None;
+        for (index, column) in comparison.columns.into_iter().enumerate() {
+            if Some(index) == keep {
+                kept = Some(column);
+                continue;
+            }
+            if column.is_running() {
+                self.cancel_tokens.remove(&column.request_id);
+                self.dropped_requests.insert(column.request_id);
+            } else if let Some(usage) = column.entry.usage {
+                self.session.add_usage(usage, column.entry.cost_usd);
+            }
+        }
+        if kept.is_none() {
+            if let Some(id) = comparison.question_id {
+                self.conversation.retain(|entry| entry.id != id);
+                self.session.remove(id);
+            }
+            if let Err(err) = self.session.write_plaintext_log() {
+                log::warn!("failed to persist conversation log: {err}");
+            }
+        }
+        kept
+    }
+
+    /// Keeps one comparison answer as the reply in the session history.
+    fn keep_comparison_answer(&mut self, index: usize) {
+        let Some(column) = self.close_comparison(Some(index)) else {
+            return;
+        };
+        let entry = column.entry;
+        let label = column.label;
+
+        self.session.append(entry.clone());
+        self.conversation.push(entry);
+        self.auto_scroll = true;
+        if let Err(err) = self.session.write_plaintext_log() {
+            log::warn!("failed to persist conversation log: {err}");
+        }
+        self.maybe_compact_history();
+        self.show_status(
+            format!("Kept the answer from {label}"),
+            StatusKind::Success,
+            Some(Duration::from_secs(2)),
+        );
+    }
+
+    /// Starts a background summary of the oldest turns once the request
+    /// history nears the model's budget.
+    fn maybe_compact_history(&mut self) {
+        let settings = self.config.compaction.clone();
+        if !settings.enabled || self.compaction_running {
+            return;
+        }
+        let llm_config = self.config.active_llm_config();
+        let capabilities = self.config.model_capabilities(&llm_config.model);
+        let history = self.session.request_history(&self.conversation);
+        let estimate = budget_history(&PromptParts {
+            model: &llm_config.model,
+            context_window: capabilities.context_window,
+            max_output_tokens: capabilities.clamp_output_tokens(llm_config.max_output_tokens),
+            system_prompt: None,
+            text_prompt: "",
+            image_tokens: 0,
+            history: history.iter().collect(),
+        })
+        .estimate;
+        if !compaction::needs_compaction(&estimate, &settings) {
+            return;
+        }
+
+        let summary = self.session.summary();
+        let summary_model = settings.summary_model(self.config.provider, &llm_config.model);
+        let summary_capabilities = self.config.model_capabilities(&summary_model);
+        let entries = compaction::select_entries(
+            &history,
+            summary.as_ref().map(|s| s.entry.id),
+            &settings,
+            &summary_model,
+            &summary_capabilities,
+        );
+        if entries.is_empty() {
+            return;
+        }
+
+        self.compaction_running = true;
+        let session_id = self.session.current_session_id();
+        let provider_kind = self.config.provider;
+        let provider = self.providers.get(provider_kind);
+        let tx = self.events_tx.clone();
+        self.runtime.spawn(async move {
+            let previous = summary.map(|s| s.entry.content);
+            let result = compaction::summarize(
+                provider,
+                provider_kind,
+                llm_config,
+                &summary_model,
+                summary_capabilities,
+                previous.as_deref(),
+                &entries,
+            )
+            .await;
+            let event = match result {
+                Ok((summary, usage)) => AppEvent::HistoryCompacted {
+                    session_id,
+                    summary,
+                    covered: entries.iter().map(|entry| entry.id).collect(),
+                    model: summary_model,
+                    usage,
+                },
+                Err(err) => AppEvent::CompactionFailed {
+                    error: err.to_string(),
+                },
+            };
+            let _ = tx.send(event);
+        });
+    }
+
+    fn current_capabilities(&self) -> ModelCapabilities {
+        self.config
+            .model_capabilities(&self.config.active_llm_config().model)
+    }
+
+    fn tool_registry(&self) -> Option<Arc<ToolRegistry>> {
+        if !self.config.tools.enabled {
+            return None;
+        }
+        let events = self.events_tx.clone();
+        let set_visible: SetWindowVisible = Arc::new(move |visible| {
+            let _ = events.send(AppEvent::WindowVisibility { visible });
+        });
+        let mut registry =
+            ToolRegistry::builtin(&self.config.tools, &self.config.capture, Some(set_visible));
+        for tool in self.mcp.tools() {
+            registry.register(tool);
+        }
+        Some(Arc::new(registry))
+    }
+
+    /// Fetches the model list of `provider`'s endpoint in the background.
+    fn refresh_models(&mut self, provider: ProviderKind, announce: bool) {
+        let cfg = self.config.llm_config(provider);
+        self.models_requested.insert(cfg.base_url.clone());
+        let client = self.providers.get(provider);
+        let label = provider.label().to_string();
+        let tx = self.events_tx.clone();
+        self.runtime.spawn(async move {
+            match client.list_models(&cfg).await {
+                Ok(models) => {
+                    if announce {
+                        let _ = tx.send(AppEvent::Status {
+                            text: format!("{label} credentials valid · {} models", models.len()),
+                            kind: StatusKind::Success,
+                            duration: Some(Duration::from_secs(3)),
+                        });
+                    }
+                    let _ = tx.send(AppEvent::ModelsLoaded {
+                        base_url: cfg.base_url,
+                        models,
+                    });
+                }
+                Err(err) => {
+                    log::warn!("failed to list {label} models: {err:#}");
+                    if announce {
+                        let _ = tx.send(AppEvent::Status {
+                            text: format!("Validation error: {err:#}"),
+                            kind: StatusKind::Error,
+                            duration: None,
+                        });
+                    }
+                }
+            }
+        });
+    }
+
+    /// Loads missing model lists once per run while Settings is open.
+    fn ensure_model_lists(&mut self) {
+        for provider in [ProviderKind::OpenAI, ProviderKind::Anthropic] {
+            let cfg = self.config.llm_config(provider);
+            if self.model_catalog.get(&cfg.base_url).is_none()
+                && !self.models_requested.contains(&cfg.base_url)
+            {
+                self.refresh_models(provider, false);
+            }
+        }
+    }
+
+    fn sync_mcp_servers(runtime: &Handle, mcp: &Arc<McpManager>, config: &AppConfig) {
+        let mcp = mcp.clone();
+        let servers = config.mcp.servers.clone();
+        runtime.spawn(async move { mcp.sync(&servers).await });
+    }
+
+    fn load_active_prompt(&self) -> Option<String> {
         let name = self.config.prompts.active_prompt_name.as_ref()?;
         let path = config::prompts_dir().ok()?.join(name);
         fs::read_to_string(path).ok()
@@ -828,26 +1936,75 @@ impl GhostApp {
         }
     }
 
-    fn ensure_attachment_texture(&mut self, ctx: &egui::Context) {
-        if let Some(att) = &self.attach {
-            if self
-                .attach_texture
-                .as_ref()
-                .map(|tex| tex.id == att.id)
-                .unwrap_or(false)
-            {
-                return;
+    /// Re-encodes attachments on the runtime once the upload settings
+    /// change, waiting until a slider or drag value is released.
+    fn refresh_attachment_uploads(&mut self, ctx: &egui::Context) {
+        if ctx.input(|i| i.pointer.any_down()) {
+            return;
+        }
+        let settings = &self.config.capture.upload;
+        for att in &mut self.attachments {
+            if att.upload_settings == *settings || att.pending_settings.as_ref() == Some(settings) {
+                continue;
+            }
+            att.pending_settings = Some(settings.clone());
+            let attachment_id = att.id;
+            let original = Arc::clone(&att.original);
+            let settings = settings.clone();
+            let events = self.events_tx.clone();
+            self.runtime.spawn_blocking(move || {
+                let result = prepare_upload(&original, &settings).map_err(|err| format!("{err:#}"));
+                let _ = events.send(AppEvent::UploadPrepared {
+                    attachment_id,
+                    settings,
+                    result,
+                });
+            });
+        }
+    }
+
+    fn apply_prepared_upload(
+        &mut self,
+        attachment_id: Uuid,
+        settings: UploadSettings,
+        result: Result<PreparedImage, String>,
+    ) {
+        let Some(att) = self
+            .attachments
+            .iter_mut()
+            .find(|att| att.id == attachment_id)
+        else {
+            return;
+        };
+        if att.pending_settings.as_ref() == Some(&settings) {
+            att.pending_settings = None;
+        }
+        // A newer encode is on its way.
+        if settings != self.config.capture.upload {
+            return;
+        }
+        att.upload_settings = settings;
+        match result {
+            Ok(upload) => att.upload = upload,
+            Err(err) => {
+                let error = format!("Failed to prepare {}: {err}", att.name);
+                self.show_status(error, StatusKind::Error, None);
+            }
+        }
+    }
+
+    fn ensure_attachment_textures(&mut self, ctx: &egui::Context) {
+        for att in &self.attachments {
+            if self.attachment_textures.contains_key(&att.id) {
+                continue;
             }
             if let Some(preview) = &att.preview {
-                let tex = ctx.load_texture(
-                    format!("screenshot-preview-{}", att.id),
+                let texture = ctx.load_texture(
+                    format!("attachment-preview-{}", att.id),
                     preview.clone(),
                     TextureOptions::LINEAR,
                 );
-                self.attach_texture = Some(ScreenshotTexture {
-                    id: att.id,
-                    texture: tex,
-                });
+                self.attachment_textures.insert(att.id, texture);
             }
         }
     }
@@ -865,12 +2022,20 @@ impl GhostApp {
                 self.ask_panel_open = !self.ask_panel_open;
             }
 
-            if ui.button("Capture Screenshot").clicked() {
+            let vision = self.current_capabilities().vision;
+            if ui
+                .add_enabled(vision, egui::Button::new("Capture Screenshot"))
+                .on_disabled_hover_text("The selected model does not accept images")
+                .clicked()
+            {
                 if let Err(err) = self.capture_and_attach() {
                     self.show_status(format!("Capture failed: {err}"), StatusKind::Error, None);
                 }
             }
 
+            self.render_voice_button(ui);
+            self.render_speech_controls(ui);
+
             if ui.button("Settings").clicked() {
                 self.settings_open = true;
             }
@@ -894,6 +2059,10 @@ impl GhostApp {
                 self.regenerate_answer();
             }
 
+            if self.active_request.is_some() && ui.button("⏹ Stop").clicked() {
+                self.stop_active_answer();
+            }
+
             ui.separator();
             if ui
                 .button(if self.is_hidden { "Show" } else { "Hide" })
@@ -904,6 +2073,51 @@ impl GhostApp {
         });
     }
 
+    fn render_voice_button(&mut self, ui: &mut egui::Ui) {
+        if let Some(recording) = &self.recording {
+            let elapsed = recording.started_at.elapsed().as_secs();
+            let label = RichText::new(format!("⏺ {}:{:02}", elapsed / 60, elapsed % 60))
+                .color(Color32::from_rgb(255, 90, 90));
+            if ui
+                .button(label)
+                .on_hover_text("Stop recording and transcribe")
+                .clicked()
+            {
+                self.toggle_recording();
+            }
+        } else if self.transcribing {
+            ui.spinner();
+            ui.label("Transcribing…");
+        } else if ui
+            .add_enabled(
+                self.config.transcription.enabled,
+                egui::Button::new("🎤 Voice"),
+            )
+            .on_hover_text(format!(
+                "Record a question ({})",
+                self.config.hotkeys.toggle_recording
+            ))
+            .on_disabled_hover_text("Voice transcription is disabled in Settings")
+            .clicked()
+        {
+            self.toggle_recording();
+        }
+    }
+
+    fn render_speech_controls(&mut self, ui: &mut egui::Ui) {
+        if !self.is_speaking() {
+            return;
+        }
+        if ui.button("⏭").on_hover_text("Skip this sentence").clicked() {
+            if let Some(player) = &self.speech_player {
+                player.skip();
+            }
+        }
+        if ui.button("⏹").on_hover_text("Stop reading the answer").clicked() {
+            self.stop_speech();
+        }
+    }
+
     fn render_conversation(&mut self, ui: &mut egui::Ui) {
         let scroll_to_bottom = self.auto_scroll && self.history_index.is_none();
         let displayed_entries = self.get_displayed_entries();
@@ -912,12 +2126,23 @@ impl GhostApp {
             .auto_shrink([false, false])
             .stick_to_bottom(self.history_index.is_none())
             .show(ui, |ui| {
+                if let Some(summary) = self.session.summary() {
+                    egui::CollapsingHeader::new(format!(
+                        "📌 Summary of {} earlier messages",
+                        summary.covered.len()
+                    ))
+                    .id_source("pinned-summary")
+                    .show(ui, |ui| {
+                        self.render_entry(ui, &summary.entry);
+                    });
+                    ui.add_space(6.0);
+                }
                 for entry in &displayed_entries {
                     self.render_entry(ui, entry);
                     ui.add_space(6.0);
                 }
                 if scroll_to_bottom {
-                    ui.scroll_to_cursor(egui::Align::BOTTOM);
+                    ui.scroll_to_cursor(Some(egui::Align::BOTTOM));
                 }
             });
         if scroll_to_bottom {
@@ -925,6 +2150,63 @@ impl GhostApp {
         }
     }
 
+    fn render_comparison(&mut self, ui: &mut egui::Ui) {
+        let Some(comparison) = &self.comparison else {
+            return;
+        };
+        let running = comparison.is_running();
+        let mut stop = false;
+        let mut discard = false;
+        let mut keep = None;
+
+        ui.horizontal(|ui| {
+            ui.strong(format!("Comparing {} models", comparison.columns.len()));
+            if running && ui.button("⏹ Stop").clicked() {
+                stop = true;
+            }
+            if ui.button("Discard").clicked() {
+                discard = true;
+            }
+        });
+        ui.columns(comparison.columns.len(), |uis| {
+            for (index, (ui, column)) in uis.iter_mut().zip(&comparison.columns).enumerate() {
+                ui.strong(&column.label);
+                let status = match &column.status {
+                    ColumnStatus::Queued => "Queued".to_string(),
+                    ColumnStatus::Streaming => "Streaming…".to_string(),
+                    ColumnStatus::Finished => match column.elapsed {
+                        Some(elapsed) => format!("Done in {:.1}s", elapsed.as_secs_f32()),
+                        None => "Done".to_string(),
+                    },
+                    ColumnStatus::Failed(error) => format!("Failed: {error}"),
+                };
+                ui.label(RichText::new(status).weak());
+                let finished = column.status == ColumnStatus::Finished
+                    && !column.entry.content.trim().is_empty();
+                if ui
+                    .add_enabled(finished, egui::Button::new("Keep this answer"))
+                    .clicked()
+                {
+                    keep = Some(index);
+                }
+                egui::ScrollArea::vertical()
+                    .id_source(("compare", index))
+                    .auto_shrink([false, false])
+                    .show(ui, |ui| self.render_entry(ui, &column.entry));
+            }
+        });
+
+        if stop {
+            self.stop_comparison();
+        }
+        if discard {
+            self.discard_comparison();
+        }
+        if let Some(index) = keep {
+            self.keep_comparison_answer(index);
+        }
+    }
+
     fn render_entry(&self, ui: &mut egui::Ui, entry: &ConversationEntry) {
         use egui::{Color32, Frame, Margin, RichText};
 
@@ -934,6 +2216,7 @@ impl GhostApp {
             ConversationRole::System => Color32::from_rgba_premultiplied(100, 100, 100, 40),
             ConversationRole::Reasoning => Color32::from_rgba_premultiplied(150, 100, 200, 40),
             ConversationRole::Error => Color32::from_rgba_premultiplied(255, 40, 40, 40),
+            ConversationRole::Tool => Color32::from_rgba_premultiplied(40, 140, 120, 40),
         };
 
         let label_color = match entry.role {
@@ -942,6 +2225,7 @@ impl GhostApp {
             ConversationRole::System => Color32::from_rgb(150, 150, 150),
             ConversationRole::Reasoning => Color32::from_rgb(200, 150, 255),
             ConversationRole::Error => Color32::from_rgb(255, 150, 150),
+            ConversationRole::Tool => Color32::from_rgb(120, 220, 190),
         };
 
         Frame::none()
@@ -965,10 +2249,36 @@ impl GhostApp {
                         }
                         WebSearchStatus::NotUsed => {}
                     }
+
+                    if entry.interrupted {
+                        ui.label(
+                            RichText::new("⏹ Interrupted").color(Color32::from_rgb(255, 220, 120)),
+                        );
+                    }
+
+                    if let Some(usage) = entry.usage {
+                        let mut text = format!(
+                            "{} in · {} out",
+                            usage.prompt_tokens, usage.completion_tokens
+                        );
+                        if let Some(cost) = entry.cost_usd {
+                            text.push_str(&format!(" · {}", format_cost(cost)));
+                        }
+                        ui.label(RichText::new(text).small().weak()).on_hover_text(format!(
+                            "Prompt {} (cached {})\nCompletion {} (reasoning {})",
+                            usage.prompt_tokens,
+                            usage.cached_tokens,
+                            usage.completion_tokens,
+                            usage.reasoning_tokens
+                        ));
+                    }
                 });
                 ui.add_space(2.0);
 
-                if matches!(entry.role, ConversationRole::Assistant | ConversationRole::Reasoning) {
+                if matches!(
+                    entry.role,
+                    ConversationRole::Assistant | ConversationRole::Reasoning | ConversationRole::Tool
+                ) {
                     Self::render_markdown(ui, &entry.content);
                 } else {
                     ui.label(&entry.content);
@@ -984,6 +2294,17 @@ impl GhostApp {
                         Self::render_markdown(ui, reasoning);
                     }
                 }
+
+                if !entry.citations.is_empty() {
+                    ui.add_space(4.0);
+                    ui.separator();
+                    ui.label(RichText::new("Sources").small().strong());
+                    for (index, citation) in entry.citations.iter().enumerate() {
+                        let text = format!("[{}] {}", index + 1, citation.label());
+                        ui.hyperlink_to(text, &citation.url)
+                            .on_hover_text(&citation.url);
+                    }
+                }
             });
     }
 
@@ -1006,11 +2327,11 @@ impl GhostApp {
 
         for event in Parser::new(text) {
             match event {
-                Event::Start(Tag::Heading(level)) => {
+                Event::Start(Tag::Heading(level, _, _)) => {
                     heading_level = Some(level as usize);
                     current.clear();
                 }
-                Event::End(Tag::Heading(_)) => {
+                Event::End(Tag::Heading(..)) => {
                     let content = current.trim().to_string();
                     if !content.is_empty() {
                         blocks.push(Block::Heading(heading_level.unwrap_or(1), content));
@@ -1030,13 +2351,11 @@ impl GhostApp {
                     }
                     current.clear();
                 }
-                Event::Start(Tag::BulletList(_)) => {
+                Event::Start(Tag::List(_)) => {
                     list_level += 1;
                 }
-                Event::End(Tag::BulletList(_)) => {
-                    if list_level > 0 {
-                        list_level -= 1;
-                    }
+                Event::End(Tag::List(_)) => {
+                    list_level = list_level.saturating_sub(1);
                 }
                 Event::Start(Tag::Item) => {
                     in_item = true;
@@ -1079,7 +2398,10 @@ impl GhostApp {
                 Event::HardBreak => {
                     current.push('\n');
                 }
-                Event::Html(_) | Event::FootnoteReference(_) | Event::TaskListMarker(_) => {}
+                Event::Html(_)
+                | Event::FootnoteReference(_)
+                | Event::TaskListMarker(_)
+                | Event::Rule => {}
                 Event::Start(_) | Event::End(_) => {}
             }
         }
@@ -1126,10 +2448,11 @@ impl GhostApp {
                 }
                 Block::Code(content) => {
                     let mut code = content;
+                    let rows = code.lines().count().max(1);
                     ui.add(
                         egui::TextEdit::multiline(&mut code)
                             .font(egui::TextStyle::Monospace)
-                            .desired_rows(code.lines().count().max(1) as u32)
+                            .desired_rows(rows)
                             .lock_focus(true)
                             .interactive(false),
                     );
@@ -1139,6 +2462,28 @@ impl GhostApp {
         }
     }
 
+    /// Quick toggles for the next question to a Responses API model.
+    fn render_question_options(&mut self, ui: &mut egui::Ui) {
+        let capabilities = self.current_capabilities();
+        if capabilities.api != ApiFlavor::Responses {
+            return;
+        }
+        let defaults = self.config.openai.responses;
+        let mut options = self.question_options.unwrap_or(defaults);
+        ui.horizontal(|ui| {
+            responses_editor(ui, "question", &mut options, &capabilities);
+            if self.question_options.is_some()
+                && ui
+                    .small_button("↺")
+                    .on_hover_text("Back to the configured options")
+                    .clicked()
+            {
+                options = defaults;
+            }
+        });
+        self.question_options = (options != defaults).then_some(options);
+    }
+
     fn render_ask_panel(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
         let response = egui::TextEdit::multiline(&mut self.ask_input)
             .desired_rows(4)
@@ -1151,19 +2496,64 @@ impl GhostApp {
         {
             self.submit_current_prompt();
         }
+        if !self.live_transcript.is_empty() {
+            let live: Vec<&str> = self
+                .live_transcript
+                .iter()
+                .map(|(_, text)| text.trim())
+                .collect();
+            ui.label(RichText::new(live.join(" ")).italics().weak());
+        }
+        if !self.transcript_segments.is_empty() {
+            egui::CollapsingHeader::new(format!(
+                "Transcript segments ({})",
+                self.transcript_segments.len()
+            ))
+            .id_source("transcript_segments")
+            .show(ui, |ui| {
+                for segment in &self.transcript_segments {
+                    ui.label(
+                        RichText::new(format!(
+                            "{:.1}s – {:.1}s  {}",
+                            segment.start,
+                            segment.end,
+                            segment.text.trim()
+                        ))
+                        .small(),
+                    );
+                }
+            });
+        }
 
+        self.render_question_options(ui);
         ui.horizontal(|ui| {
             if ui.button("Send").clicked() {
                 self.submit_current_prompt();
             }
-            if ui.button("Attach Screenshot").clicked() {
+            let targets = self.config.compare.targets.len();
+            if ui
+                .add_enabled(targets >= 2, egui::Button::new("Compare"))
+                .on_hover_text(format!("Ask {targets} models side by side"))
+                .on_disabled_hover_text("Add at least two models to compare in Settings")
+                .clicked()
+            {
+                self.submit_comparison();
+            }
+            let vision = self.current_capabilities().vision;
+            if ui
+                .add_enabled(vision, egui::Button::new("Attach Screenshot"))
+                .on_disabled_hover_text("The selected model does not accept images")
+                .clicked()
+            {
                 if let Err(err) = self.capture_and_attach() {
                     self.show_status(format!("Capture failed: {err}"), StatusKind::Error, None);
                 }
             }
-            if ui.button("Remove Attachment").clicked() {
-                self.attach = None;
-                self.attach_texture = None;
+            if ui
+                .add_enabled(!self.attachments.is_empty(), egui::Button::new("Clear Attachments"))
+                .clicked()
+            {
+                self.clear_attachments();
             }
             if ui.button("Save Config").clicked() {
                 if let Err(err) = config::save(&self.config) {
@@ -1182,60 +2572,317 @@ impl GhostApp {
             }
         });
 
-        // Separate the attachment rendering to avoid borrow checker issues
-        let (width, height) = self
-            .attach
-            .as_ref()
-            .map(|a| (a.width, a.height))
-            .unwrap_or((0, 0));
-        if self.attach.is_some() {
-            self.ensure_attachment_texture(ctx);
-            ui.separator();
-            ui.label(format!("Attached screenshot: {}x{}", width, height));
-            if let Some(tex) = self.attach_texture.as_ref().map(|t| t.texture.clone()) {
-                let size = tex.size_vec2();
-                let max_width = 240.0;
-                let scale = if size.x > 0.0 {
-                    f32::min(max_width / size.x, 1.0)
-                } else {
-                    1.0
-                };
-                ui.image(egui::load::SizedTexture::new(tex.id(), size * scale));
-            }
+        self.render_attachment_tray(ctx, ui);
+        let estimate = self.current_prompt_estimate();
+        let mut estimate_text = format!(
+            "≈ {} / {} prompt tokens",
+            estimate.tokens, estimate.budget
+        );
+        if estimate.history_trimmed() {
+            estimate_text.push_str(&format!(
+                " · {} older messages left out",
+                estimate.history_total - estimate.history_included
+            ));
         }
+        ui.label(RichText::new(estimate_text).small().weak());
+
         if self.active_request.is_some() {
             ui.add_space(8.0);
-            ui.label(RichText::new("Waiting for OpenAI response…").color(Color32::LIGHT_BLUE));
+            ui.horizontal(|ui| {
+                ui.label(
+                    RichText::new(format!(
+                        "Waiting for {} response…",
+                        self.config.provider.label()
+                    ))
+                    .color(Color32::LIGHT_BLUE),
+                );
+                if ui.button("Stop").clicked() {
+                    self.stop_active_answer();
+                }
+            });
+        }
+    }
+
+    /// Thumbnails of the attachments with their upload size, a way to remove
+    /// each one, and a path field for attaching image files.
+    fn render_attachment_tray(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        let vision = self.current_capabilities().vision;
+        ui.horizontal(|ui| {
+            let field = ui.add_enabled(
+                vision,
+                egui::TextEdit::singleline(&mut self.attachment_path)
+                    .hint_text("Image file path, or drop images on the window")
+                    .desired_width(280.0),
+            );
+            let submitted =
+                field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            let path = self.attachment_path.trim().to_string();
+            if (ui
+                .add_enabled(vision && !path.is_empty(), egui::Button::new("Attach File"))
+                .clicked()
+                || submitted)
+                && !path.is_empty()
+            {
+                match self.attach_file(Path::new(&path)) {
+                    Ok(()) => self.attachment_path.clear(),
+                    Err(err) => self.show_status(
+                        format!("Cannot attach {path}: {err}"),
+                        StatusKind::Error,
+                        None,
+                    ),
+                }
+            }
+        });
+        if self.attachments.is_empty() {
+            return;
+        }
+
+        self.ensure_attachment_textures(ctx);
+        ui.separator();
+        let mut remove = None;
+        let mut preview = None;
+        egui::ScrollArea::horizontal()
+            .id_source("attachment-tray")
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for att in &self.attachments {
+                        ui.vertical(|ui| {
+                            let thumbnail = match self.attachment_textures.get(&att.id) {
+                                Some(texture) => {
+                                    let size = texture.size_vec2();
+                                    let scale = (120.0 / size.x.max(1.0)).min(1.0);
+                                    ui.add(
+                                        egui::ImageButton::new(egui::load::SizedTexture::new(
+                                            texture.id(),
+                                            size * scale,
+                                        ))
+                                        .frame(false),
+                                    )
+                                }
+                                None => ui.button(&att.name),
+                            };
+                            if thumbnail.on_hover_text(att.upload_summary()).clicked() {
+                                preview = Some(att.id);
+                            }
+                            ui.horizontal(|ui| {
+                                ui.label(RichText::new(&att.name).small());
+                                if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                                    remove = Some(att.id);
+                                }
+                            });
+                            ui.label(
+                                RichText::new(format!(
+                                    "{:.0} KB · ≈{} tokens",
+                                    att.upload.bytes.len() as f64 / 1024.0,
+                                    att.upload.estimated_tokens()
+                                ))
+                                .small()
+                                .weak(),
+                            );
+                        });
+                    }
+                });
+            });
+        if let Some(id) = preview {
+            self.previewed_attachment = Some(id);
+        }
+        if let Some(id) = remove {
+            self.remove_attachment(id);
         }
+        self.render_attachment_preview(ctx);
+    }
+
+    fn render_attachment_preview(&mut self, ctx: &egui::Context) {
+        let Some(id) = self.previewed_attachment else {
+            return;
+        };
+        let Some(att) = self.attachments.iter().find(|att| att.id == id) else {
+            self.previewed_attachment = None;
+            return;
+        };
+        let mut open = true;
+        egui::Window::new(&att.name)
+            .id(egui::Id::new("attachment-preview"))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.label(RichText::new(att.upload_summary()).small().weak());
+                if let Some(texture) = self.attachment_textures.get(&id) {
+                    let size = texture.size_vec2();
+                    let scale = (ui.available_width() / size.x.max(1.0)).min(1.0);
+                    ui.image(egui::load::SizedTexture::new(texture.id(), size * scale));
+                }
+            });
+        if !open {
+            self.previewed_attachment = None;
+        }
+    }
+
+    /// Estimates the next request's prompt size. Tokenizing the whole
+    /// conversation is too slow to do every frame, so the result is reused
+    /// until one of its inputs changes, and frozen while an answer streams.
+    fn current_prompt_estimate(&mut self) -> PromptEstimate {
+        use std::hash::{Hash, Hasher};
+
+        let llm_config = self.config.active_llm_config();
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        llm_config.model.hash(&mut hasher);
+        llm_config.max_output_tokens.hash(&mut hasher);
+        self.ask_input.hash(&mut hasher);
+        for att in &self.attachments {
+            att.upload.estimated_tokens().hash(&mut hasher);
+        }
+        self.attachments.len().hash(&mut hasher);
+        self.config.prompts.active_prompt_name.hash(&mut hasher);
+        self.conversation.len().hash(&mut hasher);
+        if let Some(last) = self.conversation.last() {
+            last.id.hash(&mut hasher);
+        }
+        let summary = self.session.summary();
+        summary.as_ref().map(|s| s.covered.len()).hash(&mut hasher);
+        let key = hasher.finish();
+
+        match self.prompt_estimate {
+            Some((_, estimate)) if self.active_request.is_some() => return estimate,
+            Some((cached, estimate)) if cached == key => return estimate,
+            _ => {}
+        }
+
+        let custom_prompt = self.load_active_prompt();
+        let capabilities = self.config.model_capabilities(&llm_config.model);
+        let estimate = budget_history(&PromptParts {
+            model: &llm_config.model,
+            context_window: capabilities.context_window,
+            max_output_tokens: capabilities.clamp_output_tokens(llm_config.max_output_tokens),
+            system_prompt: custom_prompt.as_deref(),
+            text_prompt: &self.ask_input,
+            image_tokens: self
+                .attachments
+                .iter()
+                .map(|att| att.upload.estimated_tokens())
+                .sum(),
+            history: self.session.request_history(&self.conversation).iter().collect(),
+        })
+        .estimate;
+        self.prompt_estimate = Some((key, estimate));
+        estimate
     }
 
     fn render_status_bar(&mut self, ui: &mut egui::Ui) {
         self.update_status();
-        if let Some(status) = &self.status {
-            let color = status.kind.color();
-            ui.horizontal(|ui| {
-                ui.label(RichText::new(&status.text).color(color));
-            });
-        } else {
-            ui.label("Ready");
+        let (usage, cost) = self.session.usage_totals();
+        ui.horizontal(|ui| {
+            if let Some(status) = &self.status {
+                ui.label(RichText::new(&status.text).color(status.kind.color()));
+            } else {
+                ui.label("Ready");
+            }
+            self.render_retry_controls(ui);
+            if usage.total() > 0 {
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.label(
+                        RichText::new(format!(
+                            "Session: {} tokens · {}",
+                            usage.total(),
+                            format_cost(cost)
+                        ))
+                        .small()
+                        .weak(),
+                    );
+                });
+            }
+        });
+    }
+
+    /// Retry button for a failed answer, with a countdown after rate limits.
+    fn render_retry_controls(&mut self, ui: &mut egui::Ui) {
+        let Some(failed) = &self.failed_answer else {
+            return;
+        };
+        let mut retry = None;
+        match failed.kind {
+            ApiErrorKind::ContextLengthExceeded => {
+                if ui
+                    .add_enabled(
+                        !failed.request.history.is_empty(),
+                        egui::Button::new("Trim history and retry"),
+                    )
+                    .on_hover_text("Send the question again without the older half of the history")
+                    .clicked()
+                {
+                    retry = Some(true);
+                }
+            }
+            _ => {
+                let wait = failed
+                    .retry_at
+                    .map(|at| at.saturating_duration_since(Instant::now()))
+                    .filter(|wait| !wait.is_zero());
+                match wait {
+                    Some(wait) => {
+                        ui.label(format!("Retry in {}s", wait.as_secs() + 1));
+                    }
+                    None => {
+                        if ui.button("Retry").clicked() {
+                            retry = Some(false);
+                        }
+                    }
+                }
+            }
+        }
+        if ui.small_button("✖").on_hover_text("Dismiss").clicked() {
+            self.failed_answer = None;
+        }
+        if let Some(trim_history) = retry {
+            self.retry_failed_answer(trim_history);
         }
     }
 
     fn render_settings(&mut self, ctx: &egui::Context) {
         let mut settings_open = self.settings_open;
         if settings_open {
+            self.ensure_model_lists();
             egui::Window::new("Settings")
                 .open(&mut settings_open)
                 .resizable(true)
                 .default_width(520.0)
                 .show(ctx, |ui| {
+                    ui.heading("Provider");
+                    egui::ComboBox::from_id_source("llm-provider")
+                        .selected_text(self.config.provider.label())
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(
+                                &mut self.config.provider,
+                                ProviderKind::OpenAI,
+                                ProviderKind::OpenAI.label(),
+                            );
+                            ui.selectable_value(
+                                &mut self.config.provider,
+                                ProviderKind::Anthropic,
+                                ProviderKind::Anthropic.label(),
+                            );
+                        });
+
+                    ui.separator();
                     ui.heading("OpenAI");
                     ui.label("API Key");
                     ui.text_edit_singleline(&mut self.config.openai.api_key);
                     ui.label("Base URL");
                     ui.text_edit_singleline(&mut self.config.openai.base_url);
                     ui.label("Model");
-                    ui.text_edit_singleline(&mut self.config.openai.model);
+                    if model_picker(
+                        ui,
+                        "openai-models",
+                        &mut self.config.openai.model,
+                        self.model_catalog.get(&self.config.openai.base_url),
+                        &mut self.model_filter,
+                    ) {
+                        self.refresh_models(ProviderKind::OpenAI, true);
+                    }
+                    let openai_capabilities =
+                        self.config.model_capabilities(&self.config.openai.model);
+                    ui.label(RichText::new(openai_capabilities.describe()).small().weak());
                     ui.horizontal(|ui| {
                         ui.label("Temperature");
                         ui.add(
@@ -1251,7 +2898,11 @@ impl GhostApp {
                             .add(
                                 egui::DragValue::new(&mut max_tokens)
                                     .speed(16.0)
-                                    .clamp_range(0..=32768),
+                                    .range(
+                                        0..=openai_capabilities
+                                            .max_output_tokens
+                                            .unwrap_or(32_768),
+                                    ),
                             )
                             .changed()
                         {
@@ -1261,36 +2912,210 @@ impl GhostApp {
                                 Some(max_tokens)
                             };
                         }
-                        ui.label("(0 disables the limit)");
+                        ui.label("(0 disables the limit)");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Max retries");
+                        ui.add(
+                            egui::DragValue::new(&mut self.config.openai.retry.max_retries)
+                                .range(0..=10),
+                        );
+                        ui.label("Initial backoff (ms)");
+                        ui.add(
+                            egui::DragValue::new(&mut self.config.openai.retry.initial_backoff_ms)
+                                .speed(50.0)
+                                .range(0..=60_000),
+                        );
+                        ui.label("Max backoff (ms)");
+                        ui.add(
+                            egui::DragValue::new(&mut self.config.openai.retry.max_backoff_ms)
+                                .speed(500.0)
+                                .range(100..=300_000),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        let timeouts = &mut self.config.openai.timeouts;
+                        ui.label("Connect timeout (s)");
+                        ui.add(egui::DragValue::new(&mut timeouts.connect_secs).range(0..=120));
+                        ui.label("First byte (s)");
+                        ui.add(
+                            egui::DragValue::new(&mut timeouts.first_byte_secs).range(0..=1_800),
+                        );
+                        ui.label("Stall after (s)");
+                        ui.add(egui::DragValue::new(&mut timeouts.idle_secs).range(0..=600))
+                            .on_hover_text("Fails a streamed answer after this long without data");
+                        ui.label("(0 disables)");
+                    });
+                    ui.add_enabled_ui(openai_capabilities.api == ApiFlavor::Responses, |ui| {
+                        ui.horizontal(|ui| {
+                            responses_editor(
+                                ui,
+                                "settings",
+                                &mut self.config.openai.responses,
+                                &openai_capabilities,
+                            );
+                        });
+                    })
+                    .response
+                    .on_disabled_hover_text("Only models on the Responses API use these options");
+                    egui::CollapsingHeader::new("Endpoint and authentication")
+                        .id_source("openai-endpoint")
+                        .show(ui, |ui| endpoint_editor(ui, &mut self.config.openai.endpoint));
+
+                    ui.separator();
+                    ui.heading("Anthropic");
+                    ui.label("API Key");
+                    ui.text_edit_singleline(&mut self.config.anthropic.api_key);
+                    ui.label("Base URL");
+                    ui.text_edit_singleline(&mut self.config.anthropic.base_url);
+                    ui.label("Model");
+                    if model_picker(
+                        ui,
+                        "anthropic-models",
+                        &mut self.config.anthropic.model,
+                        self.model_catalog.get(&self.config.anthropic.base_url),
+                        &mut self.model_filter,
+                    ) {
+                        self.refresh_models(ProviderKind::Anthropic, true);
+                    }
+                    ui.label(
+                        RichText::new(
+                            self.config
+                                .model_capabilities(&self.config.anthropic.model)
+                                .describe(),
+                        )
+                        .small()
+                        .weak(),
+                    );
+                    ui.label(
+                        RichText::new("Temperature and max output tokens are shared with OpenAI.")
+                            .small(),
+                    );
+
+                    ui.separator();
+                    ui.heading("Transcription");
+                    ui.checkbox(
+                        &mut self.config.transcription.enabled,
+                        "Enable voice transcription",
+                    );
+                    ui.checkbox(&mut self.config.transcription.realtime, "Realtime mode");
+                    ui.checkbox(
+                        &mut self.config.transcription.auto_submit,
+                        "Send transcripts automatically",
+                    );
+                    ui.label("Model");
+                    ui.text_edit_singleline(&mut self.config.transcription.model);
+                    ui.label("Language");
+                    ui.horizontal(|ui| {
+                        let language = &mut self.config.transcription.language;
+                        egui::ComboBox::from_id_source("transcription-language")
+                            .selected_text(language.label())
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(
+                                    language,
+                                    TranscriptionLanguage::auto(),
+                                    "Auto-detect",
+                                );
+                                for (code, name) in TranscriptionLanguage::COMMON {
+                                    ui.selectable_value(
+                                        language,
+                                        TranscriptionLanguage::new(code),
+                                        format!("{name} ({code})"),
+                                    );
+                                }
+                            });
+                        let custom = ui
+                            .add(
+                                egui::TextEdit::singleline(&mut language.0)
+                                    .desired_width(48.0)
+                                    .hint_text("ISO-639"),
+                            )
+                            .on_hover_text("Any ISO-639-1 code, or \"auto\" to detect it");
+                        if custom.lost_focus() {
+                            *language = TranscriptionLanguage::new(&language.0);
+                        }
                     });
-
-                    ui.separator();
-                    ui.heading("Transcription");
+                    ui.label("Vocabulary");
+                    ui.add(
+                        egui::TextEdit::multiline(&mut self.config.transcription.vocabulary)
+                            .desired_rows(2)
+                            .hint_text("Names and terms to spell correctly"),
+                    );
                     ui.checkbox(
-                        &mut self.config.transcription.enabled,
-                        "Enable voice transcription",
+                        &mut self.config.transcription.use_active_prompt,
+                        "Use the active prompt as vocabulary",
                     );
-                    ui.checkbox(&mut self.config.transcription.realtime, "Realtime mode");
+
+                    ui.separator();
+                    ui.heading("Speech");
+                    ui.checkbox(&mut self.config.speech.enabled, "Read answers aloud");
                     ui.label("Model");
-                    ui.text_edit_singleline(&mut self.config.transcription.model);
-                    ui.label("Language");
-                    egui::ComboBox::from_id_source("transcription-language")
-                        .selected_text(match self.config.transcription.language {
-                            crate::config::TranscriptionLanguage::En => "English",
-                            crate::config::TranscriptionLanguage::Zh => "Chinese",
-                        })
-                        .show_ui(ui, |ui| {
-                            ui.selectable_value(
-                                &mut self.config.transcription.language,
-                                crate::config::TranscriptionLanguage::En,
-                                "English",
-                            );
-                            ui.selectable_value(
-                                &mut self.config.transcription.language,
-                                crate::config::TranscriptionLanguage::Zh,
-                                "Chinese",
-                            );
+                    ui.text_edit_singleline(&mut self.config.speech.model);
+                    ui.horizontal(|ui| {
+                        ui.label("Voice");
+                        egui::ComboBox::from_id_source("speech-voice")
+                            .selected_text(self.config.speech.voice.as_str())
+                            .show_ui(ui, |ui| {
+                                for voice in SpeechSettings::VOICES {
+                                    ui.selectable_value(
+                                        &mut self.config.speech.voice,
+                                        voice.to_string(),
+                                        *voice,
+                                    );
+                                }
+                            });
+                        ui.label("Format");
+                        egui::ComboBox::from_id_source("speech-format")
+                            .selected_text(self.config.speech.format.as_str())
+                            .show_ui(ui, |ui| {
+                                for format in [SpeechFormat::Pcm, SpeechFormat::Wav] {
+                                    ui.selectable_value(
+                                        &mut self.config.speech.format,
+                                        format,
+                                        format.as_str(),
+                                    );
+                                }
+                            });
+                    });
+                    ui.add(
+                        egui::Slider::new(&mut self.config.speech.speed, 0.25..=4.0)
+                            .text("Speed"),
+                    );
+
+                    ui.separator();
+                    ui.heading("Compare");
+                    ui.label("Models asked side by side by the Compare button");
+                    let providers = [ProviderKind::OpenAI, ProviderKind::Anthropic];
+                    let mut remove = None;
+                    for (index, target) in self.config.compare.targets.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            egui::ComboBox::from_id_source(("compare-provider", index))
+                                .selected_text(target.provider.label())
+                                .show_ui(ui, |ui| {
+                                    for provider in providers {
+                                        ui.selectable_value(
+                                            &mut target.provider,
+                                            provider,
+                                            provider.label(),
+                                        );
+                                    }
+                                });
+                            ui.text_edit_singleline(&mut target.model);
+                            if ui.small_button("✖").clicked() {
+                                remove = Some(index);
+                            }
+                        });
+                    }
+                    if let Some(index) = remove {
+                        self.config.compare.targets.remove(index);
+                    }
+                    if ui.button("Add model").clicked() {
+                        let model = self.config.active_llm_config().model;
+                        self.config.compare.targets.push(CompareTarget {
+                            provider: self.config.provider,
+                            model,
                         });
+                    }
 
                     ui.separator();
                     ui.heading("Capture");
@@ -1325,6 +3150,131 @@ impl GhostApp {
                                 "Select region (coming soon)",
                             );
                         });
+                    egui::CollapsingHeader::new("Upload preprocessing")
+                        .id_source("upload-settings")
+                        .show(ui, |ui| upload_editor(ui, &mut self.config.capture.upload));
+
+                    ui.separator();
+                    ui.heading("Tools");
+                    ui.checkbox(
+                        &mut self.config.tools.enabled,
+                        "Let the model call local tools (screenshot, clipboard, time, files)",
+                    );
+                    ui.label(
+                        RichText::new(
+                            "Tools run without asking, and MCP server tools are only offered \
+                             while this is on.",
+                        )
+                        .small(),
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label("Max tool rounds");
+                        ui.add(egui::DragValue::new(&mut self.config.tools.max_rounds).range(1..=20));
+                    });
+                    ui.label("Readable directories");
+                    let mut remove_dir = None;
+                    for (index, dir) in self.config.tools.allowed_dirs.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.text_edit_singleline(dir);
+                            if ui.small_button("✕").clicked() {
+                                remove_dir = Some(index);
+                            }
+                        });
+                    }
+                    if let Some(index) = remove_dir {
+                        self.config.tools.allowed_dirs.remove(index);
+                    }
+                    if ui.button("Add directory").clicked() {
+                        self.config.tools.allowed_dirs.push(String::new());
+                    }
+
+                    ui.separator();
+                    ui.heading("Long conversations");
+                    ui.checkbox(
+                        &mut self.config.compaction.enabled,
+                        "Summarize older messages when the context fills up",
+                    );
+                    ui.horizontal(|ui| {
+                        // Model names do not carry over between providers.
+                        ui.label(format!("{} summary model", self.config.provider.label()));
+                        let model = match self.config.provider {
+                            ProviderKind::OpenAI => &mut self.config.compaction.model,
+                            ProviderKind::Anthropic => &mut self.config.compaction.anthropic_model,
+                        };
+                        ui.add(
+                            egui::TextEdit::singleline(model).hint_text("same as answer model"),
+                        );
+                    });
+                    ui.add(
+                        egui::Slider::new(&mut self.config.compaction.trigger_ratio, 0.3..=1.0)
+                            .text("Trigger at share of context"),
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label("Always keep last");
+                        ui.add(
+                            egui::DragValue::new(&mut self.config.compaction.keep_recent)
+                                .range(0..=50),
+                        );
+                        ui.label("messages");
+                    });
+
+                    ui.separator();
+                    ui.heading("MCP servers");
+                    ui.label(
+                        RichText::new("Changes take effect when settings are saved.").small(),
+                    );
+                    let mut remove_server = None;
+                    for (index, server) in self.config.mcp.servers.iter_mut().enumerate() {
+                        let status = match self.mcp.status(&server.name) {
+                            Some(McpServerStatus::Running { tools, resources }) => {
+                                format!("running · {tools} tools · {resources} resources")
+                            }
+                            Some(McpServerStatus::Starting) => "starting…".to_string(),
+                            Some(McpServerStatus::Failed(err)) => format!("failed: {err}"),
+                            None => "stopped".to_string(),
+                        };
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut server.enabled, "");
+                            ui.text_edit_singleline(&mut server.name);
+                            if ui.small_button("✕").clicked() {
+                                remove_server = Some(index);
+                            }
+                        });
+                        ui.label(RichText::new(status).small());
+                        ui.horizontal(|ui| {
+                            ui.label("Command");
+                            ui.text_edit_singleline(&mut server.command);
+                        });
+                        let mut remove_arg = None;
+                        for (arg_index, arg) in server.args.iter_mut().enumerate() {
+                            ui.horizontal(|ui| {
+                                ui.label("Arg");
+                                ui.text_edit_singleline(arg);
+                                if ui.small_button("✕").clicked() {
+                                    remove_arg = Some(arg_index);
+                                }
+                            });
+                        }
+                        if let Some(arg_index) = remove_arg {
+                            server.args.remove(arg_index);
+                        }
+                        if ui.small_button("Add argument").clicked() {
+                            server.args.push(String::new());
+                        }
+                        ui.add_space(4.0);
+                    }
+                    if let Some(index) = remove_server {
+                        self.config.mcp.servers.remove(index);
+                    }
+                    if ui.button("Add MCP server").clicked() {
+                        self.config.mcp.servers.push(McpServerConfig {
+                            name: format!("server-{}", self.config.mcp.servers.len() + 1),
+                            command: String::new(),
+                            args: Vec::new(),
+                            env: Default::default(),
+                            enabled: true,
+                        });
+                    }
 
                     ui.separator();
                     ui.heading("Hotkeys");
@@ -1347,6 +3297,14 @@ impl GhostApp {
                         ui.label("Capture screenshot");
                         ui.text_edit_singleline(&mut self.config.hotkeys.capture_screenshot);
                     });
+                    ui.horizontal(|ui| {
+                        ui.label("Stop answer");
+                        ui.text_edit_singleline(&mut self.config.hotkeys.stop_answer);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Start/stop recording");
+                        ui.text_edit_singleline(&mut self.config.hotkeys.toggle_recording);
+                    });
 
                     ui.separator();
                     ui.heading("UI");
@@ -1495,7 +3453,7 @@ impl GhostApp {
                         if ui.button("Save Prompt").clicked() {
                             self.save_current_prompt();
                         }
-                        if let Some(_) = self.prompt_editor_selected {
+                        if self.prompt_editor_selected.is_some() {
                             if ui.button("Reload").clicked() {
                                 let selected = self.prompt_editor_selected.clone();
                                 self.load_prompt_into_editor(selected);
@@ -1516,42 +3474,30 @@ impl GhostApp {
                     ui.separator();
                     ui.horizontal(|ui| {
                         if ui.button("Validate API Key").clicked() {
-                            let cfg = self.config.openai.clone();
-                            let tx = self.events_tx.clone();
-                            let client = Arc::clone(&self.openai);
-                            self.runtime.spawn(async move {
-                                match client.validate(&cfg).await {
-                                    Ok(true) => {
-                                        let _ = tx.send(AppEvent::Status {
-                                            text: "OpenAI credentials valid".into(),
-                                            kind: StatusKind::Success,
-                                            duration: Some(Duration::from_secs(3)),
-                                        });
-                                    }
-                                    Ok(false) => {
-                                        let _ = tx.send(AppEvent::Status {
-                                            text: "Validation failed".into(),
-                                            kind: StatusKind::Error,
-                                            duration: None,
-                                        });
-                                    }
-                                    Err(err) => {
-                                        let _ = tx.send(AppEvent::Status {
-                                            text: format!("Validation error: {err}"),
-                                            kind: StatusKind::Error,
-                                            duration: None,
-                                        });
-                                    }
-                                }
-                            });
+                            self.refresh_models(self.config.provider, true);
                         }
                         if ui.button("Save Settings").clicked() {
-                            if let Err(err) = config::save(&self.config) {
+                            let renamed = self.config.mcp.dedupe_names();
+                            let endpoint = &mut self.config.openai.endpoint;
+                            let saved = endpoint.tidy_headers().and_then(|()| {
+                                Self::sync_mcp_servers(&self.runtime, &self.mcp, &self.config);
+                                config::save(&self.config)
+                            });
+                            if let Err(err) = saved {
                                 self.show_status(
                                     format!("Failed to save config: {err}"),
                                     StatusKind::Error,
                                     None,
                                 );
+                            } else if !renamed.is_empty() {
+                                self.show_status(
+                                    format!(
+                                        "Settings saved; duplicate MCP servers renamed to {}",
+                                        renamed.join(", ")
+                                    ),
+                                    StatusKind::Warning,
+                                    None,
+                                );
                             } else {
                                 self.show_status(
                                     "Settings saved",
@@ -1571,6 +3517,8 @@ impl eframe::App for GhostApp {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         self.process_background_events();
         self.process_hotkeys(frame);
+        self.refresh_attachment_uploads(ctx);
+        self.attach_dropped_files(ctx);
 
         // Handle window visibility based on is_hidden state
         ctx.send_viewport_cmd(egui::ViewportCommand::Visible(!self.is_hidden));
@@ -1596,6 +3544,12 @@ impl eframe::App for GhostApp {
         egui::TopBottomPanel::top("hud").show(ctx, |ui| self.render_hud(ui, frame));
 
         egui::CentralPanel::default().show(ctx, |ui| {
+            if self.comparison.is_some() {
+                egui::TopBottomPanel::bottom("comparison")
+                    .resizable(true)
+                    .default_height(320.0)
+                    .show_inside(ui, |ui| self.render_comparison(ui));
+            }
             self.render_conversation(ui);
         });
 
@@ -1617,53 +3571,564 @@ impl eframe::App for GhostApp {
     }
 }
 
+/// One submission: a question for the active model, or the same question
+/// for several models at once.
+enum AnalysisJob {
+    Single(Box<AnalyzeRequest>),
+    Compare(Vec<AnalyzeRequest>),
+}
+
 fn spawn_analyze_worker(
     runtime: &Handle,
-    client: Arc<OpenAIClient>,
-    mut requests: UnboundedReceiver<AnalyzeRequest>,
+    providers: Providers,
+    mut requests: UnboundedReceiver<AnalysisJob>,
     events: UnboundedSender<AppEvent>,
     stream_tx: UnboundedSender<(Uuid, StreamEvent)>,
 ) {
-    let events_clone = events.clone();
     runtime.spawn(async move {
-        while let Some(request) = requests.recv().await {
-            let request_id = request.request_id;
-            let _ = events_clone.send(AppEvent::AnalysisStarted { request_id });
-            match client.analyze_stream(request, stream_tx.clone()).await {
-                Ok(response) => {
-                    let _ = events_clone.send(AppEvent::AnalysisFinished { response });
+        while let Some(job) = requests.recv().await {
+            match job {
+                AnalysisJob::Single(request) => {
+                    run_analysis(&providers, *request, &events, &stream_tx).await;
                 }
-                Err(err) => {
-                    let _ = events_clone.send(AppEvent::AnalysisFailed {
-                        request_id,
-                        error: err.to_string(),
-                    });
+                AnalysisJob::Compare(requests) => {
+                    futures::future::join_all(
+                        requests
+                            .into_iter()
+                            .map(|request| run_analysis(&providers, request, &events, &stream_tx)),
+                    )
+                    .await;
                 }
             }
         }
     });
 }
 
+async fn run_analysis(
+    providers: &Providers,
+    request: AnalyzeRequest,
+    events: &UnboundedSender<AppEvent>,
+    stream_tx: &UnboundedSender<(Uuid, StreamEvent)>,
+) {
+    let request_id = request.request_id;
+    let _ = events.send(AppEvent::AnalysisStarted { request_id });
+    let client = providers.get(request.provider);
+    let cancel = request.cancel.clone();
+    match client.analyze_stream(request, stream_tx.clone()).await {
+        Ok(response) => {
+            let _ = events.send(AppEvent::AnalysisFinished { response });
+        }
+        Err(_) if cancel.is_cancelled() => {
+            let _ = events.send(AppEvent::AnalysisCancelled { request_id });
+        }
+        Err(err) => {
+            let api_error = ApiError::find(&err).cloned();
+            let error = match &api_error {
+                Some(api_error) => api_error.to_string(),
+                None => err.to_string(),
+            };
+            let _ = events.send(AppEvent::AnalysisFailed {
+                request_id,
+                error,
+                api_error,
+            });
+        }
+    }
+}
+
+struct TranscribeRequest {
+    config: OpenAIConfig,
+    recording: RecordingResult,
+    language: TranscriptionLanguage,
+    model: String,
+    prompt: Option<String>,
+}
+
+fn spawn_transcribe_worker(
+    runtime: &Handle,
+    providers: Providers,
+    mut requests: UnboundedReceiver<TranscribeRequest>,
+    events: UnboundedSender<AppEvent>,
+) {
+    runtime.spawn(async move {
+        let client = providers.get(ProviderKind::OpenAI);
+        while let Some(request) = requests.recv().await {
+            let result = client
+                .transcribe(
+                    &request.config,
+                    request.recording,
+                    &request.language,
+                    &request.model,
+                    request.prompt.as_deref(),
+                )
+                .await;
+            let _ = events.send(match result {
+                Ok(transcription) => AppEvent::TranscriptionFinished { transcription },
+                Err(err) => AppEvent::TranscriptionFailed {
+                    error: format!("{err:#}"),
+                },
+            });
+        }
+    });
+}
+
+struct Comparison {
+    /// The question entry, added to the session when the comparison started.
+    question_id: Option<Uuid>,
+    columns: Vec<ComparisonColumn>,
+}
+
+impl Comparison {
+    fn is_running(&self) -> bool {
+        self.columns.iter().any(ComparisonColumn::is_running)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ColumnStatus {
+    Queued,
+    Streaming,
+    Finished,
+    Failed(String),
+}
+
+/// One model's answer in a comparison, rendered like a conversation entry.
+struct ComparisonColumn {
+    request_id: Uuid,
+    label: String,
+    entry: ConversationEntry,
+    status: ColumnStatus,
+    started_at: Option<Instant>,
+    /// Time from the request start to the last token.
+    elapsed: Option<Duration>,
+}
+
+impl ComparisonColumn {
+    fn new(request_id: Uuid, label: String) -> Self {
+        Self {
+            request_id,
+            label,
+            entry: ConversationEntry::new(ConversationRole::Assistant, String::new()),
+            status: ColumnStatus::Queued,
+            started_at: None,
+            elapsed: None,
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        matches!(self.status, ColumnStatus::Queued | ColumnStatus::Streaming)
+    }
+
+    fn start(&mut self) {
+        self.status = ColumnStatus::Streaming;
+        self.started_at = Some(Instant::now());
+    }
+
+    fn finish(&mut self, status: ColumnStatus) {
+        self.status = status;
+        self.elapsed = self.started_at.map(|started| started.elapsed());
+    }
+
+    fn apply_stream_event(&mut self, event: StreamEvent) {
+        match event {
+            StreamEvent::Delta(delta) => self.entry.content.push_str(&delta),
+            StreamEvent::ReasoningDelta(delta) => self
+                .entry
+                .reasoning
+                .get_or_insert_with(String::new)
+                .push_str(&delta),
+            StreamEvent::Done(text) => self.entry.content = text,
+            StreamEvent::ReasoningDone(text) => self.entry.reasoning = Some(text),
+            StreamEvent::WebSearchInProgress => {
+                self.entry.web_search_status = WebSearchStatus::InProgress;
+            }
+            StreamEvent::WebSearchSearching => {
+                self.entry.web_search_status = WebSearchStatus::Searching;
+            }
+            StreamEvent::WebSearchCompleted => {
+                self.entry.web_search_status = WebSearchStatus::Completed;
+            }
+            StreamEvent::Citation(citation) => self.entry.add_citation(citation),
+            StreamEvent::Error(error) => log::warn!("{}: stream error: {error}", self.label),
+            StreamEvent::ToolCall { .. }
+            | StreamEvent::ToolResult { .. }
+            | StreamEvent::Retrying(_) => {}
+        }
+    }
+}
+
+struct SpeechRequest {
+    /// Value of the speech generation when the sentence was queued.
+    generation: u64,
+    config: OpenAIConfig,
+    settings: SpeechSettings,
+    text: String,
+}
+
+/// Synthesizes sentences one at a time so their audio arrives in order.
+fn spawn_speech_worker(
+    runtime: &Handle,
+    providers: Providers,
+    mut requests: UnboundedReceiver<SpeechRequest>,
+    current_generation: Arc<AtomicU64>,
+    events: UnboundedSender<AppEvent>,
+) {
+    runtime.spawn(async move {
+        let client = providers.get(ProviderKind::OpenAI);
+        while let Some(request) = requests.recv().await {
+            // Skip sentences of answers that were stopped meanwhile.
+            if request.generation != current_generation.load(Ordering::SeqCst) {
+                continue;
+            }
+            let result = client
+                .synthesize_speech(&request.config, &request.settings, &request.text)
+                .await
+                .and_then(|audio| speech::decode_speech(&audio, request.settings.format));
+            let _ = events.send(match result {
+                Ok((samples, sample_rate)) => AppEvent::SpeechReady {
+                    generation: request.generation,
+                    samples,
+                    sample_rate,
+                },
+                Err(err) => AppEvent::SpeechFailed {
+                    generation: request.generation,
+                    error: format!("{err:#}"),
+                },
+            });
+        }
+    });
+}
+
+struct VoiceRecording {
+    recorder: AudioRecorder,
+    started_at: Instant,
+    /// Whether a realtime session is transcribing while recording.
+    realtime: bool,
+}
+
+/// An image attached to the next question: a screenshot or a file.
 #[derive(Clone)]
-struct ScreenshotAttachment {
+struct ImageAttachment {
     id: Uuid,
-    png: Vec<u8>,
+    name: String,
+    /// The original image, kept to prepare it again when the upload
+    /// settings change.
+    original: Arc<[u8]>,
     width: u32,
     height: u32,
     preview: Option<egui::ColorImage>,
+    upload: PreparedImage,
+    upload_settings: UploadSettings,
+    /// Settings of a re-encode still running on the runtime.
+    pending_settings: Option<UploadSettings>,
 }
 
-impl ScreenshotAttachment {
-    fn from_capture(capture: CaptureResult) -> Result<Self> {
-        let preview = decode_preview(&capture.png_bytes)?;
+impl ImageAttachment {
+    fn from_capture(capture: CaptureResult, settings: &UploadSettings) -> Result<Self> {
+        Self::new(
+            "Screenshot".to_string(),
+            capture.png_bytes,
+            (capture.width, capture.height),
+            settings,
+        )
+    }
+
+    fn from_file(path: &Path, settings: &UploadSettings) -> Result<Self> {
+        let bytes =
+            fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let size = image::ImageReader::new(std::io::Cursor::new(&bytes))
+            .with_guessed_format()?
+            .into_dimensions()
+            .context("not a supported image (PNG, JPEG or WebP)")?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        Self::new(name, bytes, size, settings)
+    }
+
+    fn new(
+        name: String,
+        original: Vec<u8>,
+        (width, height): (u32, u32),
+        settings: &UploadSettings,
+    ) -> Result<Self> {
+        let preview = decode_preview(&original)?;
+        let upload = prepare_upload(&original, settings)?;
         Ok(Self {
             id: Uuid::new_v4(),
-            png: capture.png_bytes,
-            width: capture.width,
-            height: capture.height,
+            name,
+            original: original.into(),
+            width,
+            height,
             preview,
+            upload,
+            upload_settings: settings.clone(),
+            pending_settings: None,
         })
     }
+
+    fn upload_summary(&self) -> String {
+        format!(
+            "{}x{} → {}x{} {} · {:.0} KB · ≈{} tokens ({} detail)",
+            self.width,
+            self.height,
+            self.upload.width,
+            self.upload.height,
+            self.upload.format.label(),
+            self.upload.bytes.len() as f64 / 1024.0,
+            self.upload.estimated_tokens(),
+            self.upload.detail.as_str(),
+        )
+    }
+}
+
+/// Size, encoding and detail level of uploaded screenshots.
+fn upload_editor(ui: &mut egui::Ui, upload: &mut UploadSettings) {
+    ui.horizontal(|ui| {
+        ui.label("Max long edge");
+        ui.add(
+            egui::DragValue::new(&mut upload.max_long_edge)
+                .range(0..=8192)
+                .speed(16.0)
+                .suffix(" px"),
+        )
+        .on_hover_text("0 keeps the original resolution");
+    });
+    ui.horizontal(|ui| {
+        ui.label("Format");
+        egui::ComboBox::from_id_source("upload-format")
+            .selected_text(upload.format.label())
+            .show_ui(ui, |ui| {
+                for format in [UploadFormat::Jpeg, UploadFormat::Webp, UploadFormat::Png] {
+                    ui.selectable_value(&mut upload.format, format, format.label());
+                }
+            });
+        ui.label("Detail");
+        egui::ComboBox::from_id_source("upload-detail")
+            .selected_text(upload.detail.as_str())
+            .show_ui(ui, |ui| {
+                for detail in [ImageDetail::Auto, ImageDetail::Low, ImageDetail::High] {
+                    ui.selectable_value(&mut upload.detail, detail, detail.as_str());
+                }
+            });
+    });
+    if upload.format == UploadFormat::Jpeg {
+        ui.add(egui::Slider::new(&mut upload.quality, 1..=100).text("JPEG quality"));
+    }
+    ui.checkbox(&mut upload.grayscale, "Grayscale");
+}
+
+/// Reasoning effort, verbosity, web search and service tier, each enabled
+/// only when the model supports it. Lays out inline in the caller's row.
+fn responses_editor(
+    ui: &mut egui::Ui,
+    id_source: &str,
+    options: &mut ResponsesSettings,
+    capabilities: &ModelCapabilities,
+) {
+    ui.add_enabled_ui(capabilities.reasoning, |ui| {
+        ui.label("Effort");
+        egui::ComboBox::from_id_source((id_source, "effort"))
+            .selected_text(options.effort.as_str())
+            .show_ui(ui, |ui| {
+                for effort in ReasoningEffort::ALL {
+                    let enabled = effort != ReasoningEffort::Minimal || capabilities.minimal_effort;
+                    ui.add_enabled_ui(enabled, |ui| {
+                        ui.selectable_value(&mut options.effort, effort, effort.as_str());
+                    });
+                }
+            });
+    });
+    ui.add_enabled_ui(capabilities.verbosity, |ui| {
+        ui.label("Verbosity");
+        egui::ComboBox::from_id_source((id_source, "verbosity"))
+            .selected_text(options.verbosity.as_str())
+            .show_ui(ui, |ui| {
+                for verbosity in Verbosity::ALL {
+                    ui.selectable_value(&mut options.verbosity, verbosity, verbosity.as_str());
+                }
+            });
+    });
+    let minimal = options.effort == ReasoningEffort::Minimal && capabilities.minimal_effort;
+    ui.add_enabled(
+        capabilities.web_search && !minimal,
+        egui::Checkbox::new(&mut options.web_search, "Web search"),
+    )
+    .on_disabled_hover_text("Needs a model with web search and more than minimal effort");
+    ui.label("Tier");
+    egui::ComboBox::from_id_source((id_source, "tier"))
+        .selected_text(options.service_tier.as_str())
+        .show_ui(ui, |ui| {
+            for tier in ServiceTier::ALL {
+                let enabled = tier != ServiceTier::Priority || capabilities.priority_tier;
+                ui.add_enabled_ui(enabled, |ui| {
+                    ui.selectable_value(&mut options.service_tier, tier, tier.as_str());
+                });
+            }
+        });
+}
+
+/// Auth scheme, Azure-style URL layout and extra headers of an endpoint.
+fn endpoint_editor(ui: &mut egui::Ui, endpoint: &mut EndpointSettings) {
+    ui.horizontal(|ui| {
+        ui.label("Auth");
+        egui::ComboBox::from_id_source("endpoint-auth")
+            .selected_text(endpoint.auth.label())
+            .show_ui(ui, |ui| {
+                for auth in [AuthScheme::Bearer, AuthScheme::ApiKey, AuthScheme::None] {
+                    ui.selectable_value(&mut endpoint.auth, auth, auth.label());
+                }
+            });
+        if endpoint.auth == AuthScheme::ApiKey {
+            ui.label("Header");
+            ui.add(egui::TextEdit::singleline(&mut endpoint.api_key_header).desired_width(120.0));
+        }
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("API version");
+        let mut version = endpoint.api_version.clone().unwrap_or_default();
+        if ui
+            .add(
+                egui::TextEdit::singleline(&mut version)
+                    .desired_width(160.0)
+                    .hint_text("e.g. 2024-10-21"),
+            )
+            .changed()
+        {
+            endpoint.api_version = Some(version).filter(|version| !version.trim().is_empty());
+        }
+    });
+
+    ui.label("URL template");
+    ui.horizontal(|ui| {
+        let mut template = endpoint.url_template.clone().unwrap_or_default();
+        if ui
+            .add(
+                egui::TextEdit::singleline(&mut template)
+                    .hint_text("{base_url}/{path}")
+                    .desired_width(280.0),
+            )
+            .on_hover_text("Placeholders: {base_url}, {model} (the deployment on Azure), {path}")
+            .changed()
+        {
+            endpoint.url_template = Some(template).filter(|template| !template.trim().is_empty());
+        }
+        if ui
+            .button("Azure")
+            .on_hover_text("Deployment URLs with an api-key header")
+            .clicked()
+        {
+            endpoint.auth = AuthScheme::ApiKey;
+            endpoint.url_template = Some(EndpointSettings::AZURE_URL_TEMPLATE.to_string());
+        }
+    });
+
+    // Rows are edited in place; blank, unnamed and repeated names are only
+    // checked when the settings are saved.
+    ui.label("Extra headers");
+    let mut remove = None;
+    for (index, (name, value)) in endpoint.headers.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(name)
+                    .desired_width(140.0)
+                    .hint_text("OpenAI-Project"),
+            );
+            ui.add(egui::TextEdit::singleline(value).desired_width(180.0).password(true));
+            if ui.small_button("✖").clicked() {
+                remove = Some(index);
+            }
+        });
+    }
+    if let Some(index) = remove {
+        endpoint.headers.remove(index);
+    }
+    if ui.small_button("Add header").clicked() {
+        endpoint.headers.push((String::new(), String::new()));
+    }
+}
+
+/// Model id field with a searchable list of the models the endpoint reports.
+/// Ids that are not listed can still be typed in. Returns true when the
+/// refresh button was clicked.
+fn model_picker(
+    ui: &mut egui::Ui,
+    id_source: &str,
+    model: &mut String,
+    cached: Option<&CachedModelList>,
+    filter: &mut String,
+) -> bool {
+    let mut refresh = false;
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(model);
+        let popup_id = ui.make_persistent_id(id_source);
+        let browse = ui.button("Browse ▾");
+        if browse.clicked() {
+            ui.memory_mut(|mem| mem.toggle_popup(popup_id));
+        }
+        egui::popup_below_widget(
+            ui,
+            popup_id,
+            &browse,
+            egui::PopupCloseBehavior::CloseOnClickOutside,
+            |ui| {
+                ui.set_min_width(280.0);
+                ui.add(egui::TextEdit::singleline(filter).hint_text("Search models"));
+                let Some(cached) = cached else {
+                    ui.label("No model list yet; press ⟳ to load it.");
+                    return;
+                };
+                let matches = filter_models(&cached.models, filter);
+                if matches.is_empty() {
+                    ui.label("No matching models");
+                }
+                egui::ScrollArea::vertical().max_height(280.0).show(ui, |ui| {
+                    for candidate in matches {
+                        if ui.selectable_label(model == candidate, candidate).clicked() {
+                            *model = candidate.clone();
+                            ui.memory_mut(|mem| mem.close_popup());
+                        }
+                    }
+                });
+            },
+        );
+        refresh = ui
+            .button("⟳")
+            .on_hover_text("Reload the model list from the server")
+            .clicked();
+    });
+    if let Some(cached) = cached {
+        ui.label(
+            RichText::new(format!(
+                "{} models · fetched {}",
+                cached.models.len(),
+                cached
+                    .fetched_at
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M")
+            ))
+            .small()
+            .weak(),
+        );
+    }
+    refresh
+}
+
+/// Markdown shown for a tool call entry; long outputs are truncated for display.
+fn format_tool_entry(name: &str, arguments: &str, output: &str) -> String {
+    const MAX_CHARS: usize = 2_000;
+    let arguments = arguments.trim();
+    let mut shown: String = output.chars().take(MAX_CHARS).collect();
+    if shown.len() < output.len() {
+        shown.push_str("\n…");
+    }
+    if arguments.is_empty() || arguments == "{}" {
+        format!("`{name}()`\n\n```\n{shown}\n```")
+    } else {
+        format!("`{name}({arguments})`\n\n```\n{shown}\n```")
+    }
 }
 
 fn decode_preview(png: &[u8]) -> Result<Option<egui::ColorImage>> {
@@ -1690,9 +4155,14 @@ fn decode_preview(png: &[u8]) -> Result<Option<egui::ColorImage>> {
     )))
 }
 
-struct ScreenshotTexture {
-    id: Uuid,
-    texture: egui::TextureHandle,
+/// A failed answer that can be sent again.
+struct FailedAnswer {
+    request: AnalyzeRequest,
+    kind: ApiErrorKind,
+    /// When a rate-limited request may be retried.
+    retry_at: Option<Instant>,
+    /// Interrupted answer kept from the failed stream, replaced on retry.
+    partial: Option<Uuid>,
 }
 
 #[derive(Clone)]
@@ -1728,9 +4198,54 @@ enum AppEvent {
     AnalysisFinished {
         response: AnalyzeResponse,
     },
+    AnalysisCancelled {
+        request_id: Uuid,
+    },
     AnalysisFailed {
         request_id: Uuid,
         error: String,
+        /// Set when the provider rejected the request.
+        api_error: Option<ApiError>,
+    },
+    HistoryCompacted {
+        session_id: Uuid,
+        summary: String,
+        covered: Vec<Uuid>,
+        /// Model that wrote the summary and what it was billed.
+        model: String,
+        usage: Option<TokenUsage>,
+    },
+    CompactionFailed {
+        error: String,
+    },
+    ModelsLoaded {
+        base_url: String,
+        models: Vec<String>,
+    },
+    Transcript(TranscriptEvent),
+    TranscriptionFinished {
+        transcription: Transcription,
+    },
+    TranscriptionFailed {
+        error: String,
+    },
+    SpeechReady {
+        generation: u64,
+        samples: Vec<f32>,
+        sample_rate: u32,
+    },
+    SpeechFailed {
+        generation: u64,
+        error: String,
+    },
+    UploadPrepared {
+        attachment_id: Uuid,
+        settings: UploadSettings,
+        result: Result<PreparedImage, String>,
+    },
+    /// The screenshot tool hiding the window around a capture.
+    WindowVisibility {
+        visible: bool,
     },
     Status {
         text: String,
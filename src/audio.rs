@@ -5,6 +5,7 @@ use anyhow::{Context, Result};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::{SampleFormat, StreamConfig};
 use parking_lot::Mutex;
+use tokio::sync::mpsc::UnboundedSender;
 
 pub struct AudioRecorder {
     stream: cpal::Stream,
@@ -21,6 +22,16 @@ pub struct RecordingResult {
 
 impl AudioRecorder {
     pub fn start() -> Result<Self> {
+        Self::start_with_sink(None)
+    }
+
+    /// Starts recording and also forwards every captured block of mono
+    /// samples to `sink`, for transcription while the user is still talking.
+    pub fn start_streaming(sink: UnboundedSender<Vec<f32>>) -> Result<Self> {
+        Self::start_with_sink(Some(sink))
+    }
+
+    fn start_with_sink(sink: Option<UnboundedSender<Vec<f32>>>) -> Result<Self> {
         let host = cpal::default_host();
         let device = host
             .default_input_device()
@@ -32,7 +43,13 @@ impl AudioRecorder {
         let sample_format = config.sample_format();
         let buffer: Arc<Mutex<Vec<f32>>> = Arc::new(Mutex::new(Vec::new()));
 
-        let stream = build_stream(&device, &config.into(), sample_format, Arc::clone(&buffer))?;
+        let stream = build_stream(
+            &device,
+            &config.into(),
+            sample_format,
+            Arc::clone(&buffer),
+            sink,
+        )?;
         stream.play().context("failed to begin audio capture")?;
 
         Ok(Self {
@@ -42,6 +59,10 @@ impl AudioRecorder {
         })
     }
 
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
     pub fn stop(self) -> Result<RecordingResult> {
         drop(self.stream);
         let samples = self.buffer.lock().clone();
@@ -55,74 +76,61 @@ impl AudioRecorder {
     }
 }
 
+/// Downmixes interleaved frames to mono, appends them to `buffer` and
+/// forwards them to `sink` when streaming.
+fn input_callback<T: Copy + Send + 'static>(
+    channels: usize,
+    buffer: Arc<Mutex<Vec<f32>>>,
+    sink: Option<UnboundedSender<Vec<f32>>>,
+    to_f32: fn(T) -> f32,
+) -> impl FnMut(&[T], &cpal::InputCallbackInfo) + Send + 'static {
+    move |data: &[T], _| {
+        let mono: Vec<f32> = data
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().map(|sample| to_f32(*sample)).sum::<f32>() / channels as f32)
+            .collect();
+        buffer.lock().extend_from_slice(&mono);
+        if let Some(sink) = &sink {
+            let _ = sink.send(mono);
+        }
+    }
+}
+
 fn build_stream(
     device: &cpal::Device,
     config: &StreamConfig,
     format: SampleFormat,
     buffer: Arc<Mutex<Vec<f32>>>,
+    sink: Option<UnboundedSender<Vec<f32>>>,
 ) -> Result<cpal::Stream> {
     let err_fn = |err| log::error!("audio input stream error: {err}");
     let channels = config.channels as usize;
 
     let stream = match format {
-        SampleFormat::F32 => {
-            let buffer = Arc::clone(&buffer);
-            device.build_input_stream(
-                config,
-                move |data: &[f32], _| {
-                    let mut guard = buffer.lock();
-                    guard.reserve(data.len());
-                    for frame in data.chunks_exact(channels) {
-                        let mut total = 0.0f32;
-                        for sample in frame {
-                            total += sample.clamp(-1.0, 1.0);
-                        }
-                        guard.push(total / channels as f32);
-                    }
-                },
-                err_fn,
-                None,
-            )?
-        }
-        SampleFormat::I16 => {
-            let buffer = Arc::clone(&buffer);
-            device.build_input_stream(
-                config,
-                move |data: &[i16], _| {
-                    let mut guard = buffer.lock();
-                    guard.reserve(data.len());
-                    for frame in data.chunks_exact(channels) {
-                        let mut total = 0.0f32;
-                        for sample in frame {
-                            total += *sample as f32 / i16::MAX as f32;
-                        }
-                        guard.push(total / channels as f32);
-                    }
-                },
-                err_fn,
-                None,
-            )?
-        }
-        SampleFormat::U16 => {
-            let buffer = Arc::clone(&buffer);
-            device.build_input_stream(
-                config,
-                move |data: &[u16], _| {
-                    let mut guard = buffer.lock();
-                    guard.reserve(data.len());
-                    for frame in data.chunks_exact(channels) {
-                        let mut total = 0.0f32;
-                        for sample in frame {
-                            let normalized = (*sample as f32 / u16::MAX as f32) * 2.0 - 1.0;
-                            total += normalized;
-                        }
-                        guard.push(total / channels as f32);
-                    }
-                },
-                err_fn,
-                None,
-            )?
-        }
+        SampleFormat::F32 => device.build_input_stream(
+            config,
+            input_callback(channels, buffer, sink, |sample: f32| {
+                sample.clamp(-1.0, 1.0)
+            }),
+            err_fn,
+            None,
+        )?,
+        SampleFormat::I16 => device.build_input_stream(
+            config,
+            input_callback(channels, buffer, sink, |sample: i16| {
+                sample as f32 / i16::MAX as f32
+            }),
+            err_fn,
+            None,
+        )?,
+        SampleFormat::U16 => device.build_input_stream(
+            config,
+            input_callback(channels, buffer, sink, |sample: u16| {
+                (sample as f32 / u16::MAX as f32) * 2.0 - 1.0
+            }),
+            err_fn,
+            None,
+        )?,
         other => {
             anyhow::bail!("unsupported input sample format: {other:?}");
         }
@@ -131,6 +139,53 @@ fn build_stream(
     Ok(stream)
 }
 
+/// Streaming linear resampler; keeps the last input sample between calls so
+/// consecutive blocks join without clicks.
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    /// Input samples per output sample.
+    step: f64,
+    /// Position of the next output sample, relative to `last`.
+    position: f64,
+    last: Option<f32>,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32) -> Self {
+        Self {
+            step: from_rate as f64 / to_rate as f64,
+            position: 0.0,
+            last: None,
+        }
+    }
+
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let samples: Vec<f32> = self.last.into_iter().chain(input.iter().copied()).collect();
+        if samples.len() < 2 {
+            self.last = samples.last().copied();
+            return Vec::new();
+        }
+        let mut output = Vec::with_capacity((input.len() as f64 / self.step) as usize + 1);
+        while self.position + 1.0 < samples.len() as f64 {
+            let index = self.position as usize;
+            let frac = (self.position - index as f64) as f32;
+            output.push(samples[index] + (samples[index + 1] - samples[index]) * frac);
+            self.position += self.step;
+        }
+        self.position -= (samples.len() - 1) as f64;
+        self.last = samples.last().copied();
+        output
+    }
+}
+
+/// Little-endian signed 16-bit PCM, the format realtime APIs expect.
+pub fn to_pcm16(samples: &[f32]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|sample| ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+        .collect()
+}
+
 fn encode_wav(samples: &[f32], sample_rate: u32) -> Result<Vec<u8>> {
     let mut cursor = Cursor::new(Vec::new());
     {
@@ -149,3 +204,26 @@ fn encode_wav(samples: &[f32], sample_rate: u32) -> Result<Vec<u8>> {
     }
     Ok(cursor.into_inner())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resamples_across_blocks() {
+        let input: Vec<f32> = (0..480).map(|i| i as f32 / 480.0).collect();
+        let mut resampler = Resampler::new(48_000, 24_000);
+        let mut output = resampler.process(&input[..100]);
+        output.extend(resampler.process(&input[100..]));
+
+        assert_eq!(output.len(), 240);
+        for (i, sample) in output.iter().enumerate() {
+            assert!((sample - input[i * 2]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn encodes_little_endian_pcm16() {
+        assert_eq!(to_pcm16(&[0.0, 1.0, -1.0]), [0, 0, 0xff, 0x7f, 0x01, 0x80]);
+    }
+}
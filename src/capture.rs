@@ -1,8 +1,20 @@
+use std::io::Cursor;
+
 use anyhow::{bail, Context, Result};
-use image::{codecs::png::PngEncoder, RgbaImage};
+use base64::{engine::general_purpose, Engine};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{
+    codecs::png::PngEncoder, DynamicImage, ImageEncoder, ImageFormat, ImageReader, RgbaImage,
+};
 use screenshots::Screen;
 
-use crate::config::CaptureMode;
+use crate::config::{CaptureMode, ImageDetail, UploadFormat, UploadSettings};
+use crate::tokens;
+
+/// `detail: low` images are downscaled to 512 px by the API anyway.
+const LOW_DETAIL_LONG_EDGE: u32 = 512;
 
 #[derive(Debug, Clone)]
 pub struct CaptureResult {
@@ -40,7 +52,6 @@ pub fn capture_screen(mode: CaptureMode) -> Result<CaptureResult> {
 
     let mut png_bytes = Vec::new();
     {
-        use image::ImageEncoder;
         let encoder = PngEncoder::new(&mut png_bytes);
         encoder
             .write_image(&img, width, height, image::ColorType::Rgba8.into())
@@ -58,3 +69,159 @@ fn primary_screen() -> Result<Screen> {
     let screens = Screen::all().context("failed to enumerate displays")?;
     screens.into_iter().next().context("no displays detected")
 }
+
+/// A screenshot ready to upload: scaled, encoded and tagged with the detail
+/// level it is sent at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedImage {
+    pub bytes: Vec<u8>,
+    pub format: UploadFormat,
+    pub width: u32,
+    pub height: u32,
+    pub detail: ImageDetail,
+}
+
+impl PreparedImage {
+    pub fn mime_type(&self) -> &'static str {
+        self.format.mime_type()
+    }
+
+    pub fn estimated_tokens(&self) -> usize {
+        tokens::image_tokens(self.width, self.height, self.detail)
+    }
+
+    pub fn data_url(&self) -> String {
+        data_url(self.mime_type(), &self.bytes)
+    }
+
+    /// A PNG sent as is, such as an image returned by an MCP tool.
+    pub fn from_png(bytes: Vec<u8>) -> Result<Self> {
+        let (width, height) = ImageReader::with_format(Cursor::new(&bytes), ImageFormat::Png)
+            .into_dimensions()
+            .context("failed to read PNG dimensions")?;
+        Ok(Self {
+            bytes,
+            format: UploadFormat::Png,
+            width,
+            height,
+            detail: ImageDetail::Auto,
+        })
+    }
+}
+
+/// Inline `data:` URL, as the OpenAI APIs accept for images.
+pub fn data_url(mime_type: &str, bytes: &[u8]) -> String {
+    format!(
+        "data:{mime_type};base64,{}",
+        general_purpose::STANDARD.encode(bytes)
+    )
+}
+
+/// Downscales, optionally converts to grayscale and re-encodes a captured PNG
+/// according to `settings`.
+pub fn prepare_upload(png: &[u8], settings: &UploadSettings) -> Result<PreparedImage> {
+    let mut image = image::load_from_memory(png).context("failed to decode screenshot")?;
+
+    let mut long_edge = settings.max_long_edge;
+    if settings.detail == ImageDetail::Low {
+        long_edge = match long_edge {
+            0 => LOW_DETAIL_LONG_EDGE,
+            edge => edge.min(LOW_DETAIL_LONG_EDGE),
+        };
+    }
+    if long_edge > 0 && image.width().max(image.height()) > long_edge {
+        image = image.resize(long_edge, long_edge, FilterType::Triangle);
+    }
+    // Screenshots are opaque, and JPEG has no alpha channel.
+    let image = if settings.grayscale {
+        DynamicImage::ImageLuma8(image.to_luma8())
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+    };
+
+    let (width, height) = (image.width(), image.height());
+    let (pixels, color) = (image.as_bytes(), image.color().into());
+    let mut bytes = Vec::new();
+    match settings.format {
+        UploadFormat::Png => PngEncoder::new(&mut bytes).write_image(pixels, width, height, color),
+        UploadFormat::Jpeg => {
+            let quality = settings.quality.clamp(1, 100);
+            JpegEncoder::new_with_quality(&mut bytes, quality)
+                .write_image(pixels, width, height, color)
+        }
+        UploadFormat::Webp => {
+            WebPEncoder::new_lossless(&mut bytes).write_image(pixels, width, height, color)
+        }
+    }
+    .with_context(|| format!("failed to encode screenshot as {}", settings.format.label()))?;
+
+    Ok(PreparedImage {
+        bytes,
+        format: settings.format,
+        width,
+        height,
+        detail: settings.detail,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([(x % 256) as u8, (y % 256) as u8, 128, 255])
+        });
+        let mut bytes = Vec::new();
+        PngEncoder::new(&mut bytes)
+            .write_image(&image, width, height, image::ColorType::Rgba8.into())
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn downscales_to_the_long_edge_and_reencodes() {
+        let settings = UploadSettings {
+            max_long_edge: 400,
+            ..UploadSettings::default()
+        };
+        let prepared = prepare_upload(&png(1200, 600), &settings).unwrap();
+
+        assert_eq!((prepared.width, prepared.height), (400, 200));
+        assert_eq!(prepared.mime_type(), "image/jpeg");
+        let decoded = image::load_from_memory(&prepared.bytes).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (400, 200));
+    }
+
+    #[test]
+    fn low_detail_caps_at_512_and_keeps_small_images() {
+        let settings = UploadSettings {
+            detail: ImageDetail::Low,
+            format: UploadFormat::Webp,
+            grayscale: true,
+            ..UploadSettings::default()
+        };
+        let prepared = prepare_upload(&png(1024, 512), &settings).unwrap();
+        assert_eq!((prepared.width, prepared.height), (512, 256));
+        assert_eq!(prepared.estimated_tokens(), 85);
+        let decoded = image::load_from_memory(&prepared.bytes).unwrap();
+        assert!(decoded
+            .to_rgb8()
+            .pixels()
+            .all(|pixel| pixel[0] == pixel[1] && pixel[1] == pixel[2]));
+
+        let small = prepare_upload(&png(300, 200), &UploadSettings::default()).unwrap();
+        assert_eq!((small.width, small.height), (300, 200));
+    }
+
+    #[test]
+    fn keeps_a_png_as_is() {
+        let bytes = png(64, 32);
+        let image = PreparedImage::from_png(bytes.clone()).unwrap();
+
+        assert_eq!((image.width, image.height), (64, 32));
+        assert_eq!(image.bytes, bytes);
+        assert_eq!(image.mime_type(), "image/png");
+        assert!(PreparedImage::from_png(b"not a png".to_vec()).is_err());
+    }
+}
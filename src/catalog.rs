@@ -0,0 +1,113 @@
+//! Model ids served by each endpoint, as reported by `GET /models`.
+//!
+//! The lists are cached in `models.json` under the data directory so the
+//! settings picker is populated immediately on the next start, including for
+//! OpenAI-compatible servers (vLLM, LM Studio, Ollama) that serve their own
+//! model names.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedModelList {
+    pub fetched_at: DateTime<Utc>,
+    pub models: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelCatalog {
+    /// Keyed by base URL without a trailing slash.
+    #[serde(default)]
+    endpoints: BTreeMap<String, CachedModelList>,
+}
+
+impl ModelCatalog {
+    /// Reads the cache, starting empty when it is missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        let Ok(raw) = fs::read_to_string(path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&raw).unwrap_or_else(|err| {
+            log::warn!("ignoring unreadable model cache {}: {err}", path.display());
+            Self::default()
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).context("failed to serialize model cache")?;
+        fs::write(path, json)
+            .with_context(|| format!("failed to write model cache {}", path.display()))
+    }
+
+    pub fn get(&self, base_url: &str) -> Option<&CachedModelList> {
+        self.endpoints.get(endpoint_key(base_url))
+    }
+
+    pub fn insert(&mut self, base_url: &str, mut models: Vec<String>) {
+        models.sort();
+        models.dedup();
+        self.endpoints.insert(
+            endpoint_key(base_url).to_string(),
+            CachedModelList {
+                fetched_at: Utc::now(),
+                models,
+            },
+        );
+    }
+}
+
+pub fn catalog_path() -> Result<PathBuf> {
+    Ok(config::data_dir()?.join("models.json"))
+}
+
+fn endpoint_key(base_url: &str) -> &str {
+    base_url.trim().trim_end_matches('/')
+}
+
+/// Case-insensitive substring filter used by the model picker.
+pub fn filter_models<'a>(models: &'a [String], query: &str) -> Vec<&'a String> {
+    let query = query.trim().to_lowercase();
+    models
+        .iter()
+        .filter(|model| query.is_empty() || model.to_lowercase().contains(&query))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_lists_per_endpoint() {
+        let path = std::env::temp_dir().join(format!("ghost-models-{}.json", uuid::Uuid::new_v4()));
+        let mut catalog = ModelCatalog::default();
+        catalog.insert(
+            "http://localhost:1234/v1/",
+            vec!["qwen2.5".into(), "llama3".into(), "llama3".into()],
+        );
+        catalog.save(&path).unwrap();
+
+        let loaded = ModelCatalog::load(&path);
+        assert_eq!(loaded, catalog);
+        assert_eq!(
+            loaded.get("http://localhost:1234/v1").unwrap().models,
+            ["llama3", "qwen2.5"]
+        );
+        assert!(loaded.get("https://api.openai.com/v1").is_none());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn filters_case_insensitively() {
+        let models = vec!["gpt-4o".to_string(), "GPT-4o-mini".into(), "o3".into()];
+        assert_eq!(filter_models(&models, "4O").len(), 2);
+        assert_eq!(filter_models(&models, "").len(), 3);
+    }
+}
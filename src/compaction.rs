@@ -0,0 +1,195 @@
+//! Rolling summaries that keep long sessions inside the context window.
+//!
+//! Once the request history approaches the token budget, the oldest turns
+//! are summarized (together with any previous summary) by a configurable,
+//! usually cheaper, model. The summary then replaces those turns in request
+//! history through [`SessionManager`](crate::session::SessionManager) while
+//! the session log keeps the full transcript.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::config::{CompactionSettings, OpenAIConfig, ProviderKind};
+use crate::models::ModelCapabilities;
+use crate::provider::{AnalyzeRequest, Provider};
+use crate::session::ConversationEntry;
+use crate::tokens::{count_tokens, is_sendable, PromptEstimate};
+use crate::usage::TokenUsage;
+
+const SUMMARY_PROMPT: &str = "You maintain a running summary of a conversation between a user \
+and an AI assistant. Merge the previous summary, if any, with the new transcript into a single \
+concise summary. Keep facts, decisions, names, numbers, code identifiers and open questions; drop \
+pleasantries. Write in the language of the conversation and reply with the summary only.";
+const SUMMARY_MAX_TOKENS: u32 = 1_024;
+
+pub fn needs_compaction(estimate: &PromptEstimate, settings: &CompactionSettings) -> bool {
+    settings.enabled
+        && (estimate.history_trimmed()
+            || estimate.tokens as f32 > estimate.budget as f32 * settings.trigger_ratio)
+}
+
+/// Picks the oldest request-history entries to fold into the summary: all
+/// but the `keep_recent` newest, capped at half the summary model's context
+/// so the summarization request itself always fits. Anything left over is
+/// picked up by the next round.
+pub fn select_entries(
+    history: &VecDeque<ConversationEntry>,
+    summary_id: Option<Uuid>,
+    settings: &CompactionSettings,
+    summary_model: &str,
+    summary_capabilities: &ModelCapabilities,
+) -> Vec<ConversationEntry> {
+    let candidates: Vec<&ConversationEntry> = history
+        .iter()
+        .filter(|entry| Some(entry.id) != summary_id && is_sendable(entry))
+        .collect();
+    let eligible = candidates.len().saturating_sub(settings.keep_recent);
+
+    let limit = summary_capabilities.context_window / 2;
+    let mut used = 0;
+    let mut selected = Vec::new();
+    for entry in candidates.into_iter().take(eligible) {
+        used += count_tokens(summary_model, &entry.content);
+        if used > limit && !selected.is_empty() {
+            break;
+        }
+        selected.push(entry.clone());
+    }
+    selected
+}
+
+pub fn render_transcript(previous: Option<&str>, entries: &[ConversationEntry]) -> String {
+    let mut transcript = String::new();
+    if let Some(previous) = previous.filter(|text| !text.trim().is_empty()) {
+        transcript.push_str("Previous summary:\n");
+        transcript.push_str(previous.trim());
+        transcript.push_str("\n\n");
+    }
+    transcript.push_str("New transcript:\n");
+    for entry in entries {
+        transcript.push_str(&format!(
+            "{}: {}\n\n",
+            entry.role.label(),
+            entry.content.trim()
+        ));
+    }
+    transcript
+}
+
+/// Asks `summary_model` to merge `previous` and `entries` into one summary.
+/// Returns the summary with the tokens the request was billed for.
+pub async fn summarize(
+    provider: Arc<dyn Provider>,
+    provider_kind: ProviderKind,
+    config: OpenAIConfig,
+    summary_model: &str,
+    capabilities: ModelCapabilities,
+    previous: Option<&str>,
+    entries: &[ConversationEntry],
+) -> Result<(String, Option<TokenUsage>)> {
+    let request = AnalyzeRequest {
+        request_id: Uuid::new_v4(),
+        provider: provider_kind,
+        config: OpenAIConfig {
+            model: summary_model.to_string(),
+            max_output_tokens: Some(SUMMARY_MAX_TOKENS),
+            ..config
+        },
+        capabilities,
+        text_prompt: render_transcript(previous, entries),
+        custom_prompt: Some(SUMMARY_PROMPT.to_string()),
+        images: Vec::new(),
+        history: VecDeque::new(),
+        tools: None,
+        max_tool_rounds: 0,
+        cancel: CancellationToken::new(),
+    };
+    let response = provider.analyze(request).await?;
+    let summary = response.answer;
+    if summary.trim().is_empty() || summary == "<empty response>" {
+        bail!("summary model returned an empty response");
+    }
+    Ok((summary.trim().to_string(), response.usage))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::ConversationRole;
+
+    fn history(count: usize) -> VecDeque<ConversationEntry> {
+        (0..count)
+            .map(|i| ConversationEntry::new(ConversationRole::User, format!("turn {i}")))
+            .collect()
+    }
+
+    #[test]
+    fn triggers_on_ratio_or_trimmed_history() {
+        let settings = CompactionSettings::default();
+        let estimate = PromptEstimate {
+            tokens: 700,
+            budget: 1_000,
+            history_included: 4,
+            history_total: 4,
+        };
+        assert!(!needs_compaction(&estimate, &settings));
+        assert!(needs_compaction(
+            &PromptEstimate {
+                tokens: 900,
+                ..estimate
+            },
+            &settings
+        ));
+        assert!(needs_compaction(
+            &PromptEstimate {
+                history_included: 3,
+                ..estimate
+            },
+            &settings
+        ));
+        let disabled = CompactionSettings {
+            enabled: false,
+            ..settings
+        };
+        assert!(!needs_compaction(
+            &PromptEstimate {
+                tokens: 900,
+                ..estimate
+            },
+            &disabled
+        ));
+    }
+
+    #[test]
+    fn keeps_recent_entries_and_skips_the_pinned_summary() {
+        let mut history = history(10);
+        let summary = ConversationEntry::new(ConversationRole::System, "earlier summary");
+        let summary_id = summary.id;
+        history.push_front(summary);
+        let settings = CompactionSettings::default();
+
+        let selected = select_entries(
+            &history,
+            Some(summary_id),
+            &settings,
+            "gpt-4o-mini",
+            &ModelCapabilities::DEFAULT,
+        );
+
+        assert_eq!(selected.len(), 10 - settings.keep_recent);
+        assert_eq!(selected[0].content, "turn 0");
+        assert!(selected.iter().all(|entry| entry.id != summary_id));
+    }
+
+    #[test]
+    fn transcript_includes_previous_summary() {
+        let entries: Vec<_> = history(2).into_iter().collect();
+        let transcript = render_transcript(Some("they met"), &entries);
+        assert!(transcript.starts_with("Previous summary:\nthey met"));
+        assert!(transcript.contains("You: turn 1"));
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::{BTreeMap, HashSet};
 use std::{fs, path::PathBuf};
 
 use anyhow::{Context, Result};
@@ -5,6 +6,8 @@ use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 use keyring::Entry;
 
+use crate::models::{self, ModelCapabilities, ModelOverride};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenAIConfig {
     pub api_key: String,
@@ -16,8 +19,19 @@ pub struct OpenAIConfig {
     pub temperature: f32,
     #[serde(default)]
     pub max_output_tokens: Option<u32>,
+    #[serde(default)]
+    pub retry: RetrySettings,
+    #[serde(default)]
+    pub timeouts: TimeoutSettings,
+    #[serde(default)]
+    pub responses: ResponsesSettings,
+    /// How URLs and auth headers are built, for Azure OpenAI and gateways.
+    #[serde(default)]
+    pub endpoint: EndpointSettings,
 }
 
+const OPENAI_API_HOST: &str = "api.openai.com";
+
 fn default_base_url() -> String {
     "https://api.openai.com/v1".to_string()
 }
@@ -30,6 +44,25 @@ fn default_temperature() -> f32 {
     0.7
 }
 
+impl OpenAIConfig {
+    /// Whether requests can authenticate: a key is set, or the endpoint
+    /// does not use one.
+    pub fn has_credentials(&self) -> bool {
+        self.endpoint.auth == AuthScheme::None || !self.api_key.trim().is_empty()
+    }
+
+    /// Whether streamed Chat Completions ask for a final usage chunk. Unless
+    /// set explicitly, only api.openai.com is asked, since several compatible
+    /// servers and older Azure API versions reject `stream_options`.
+    pub fn stream_usage(&self) -> bool {
+        self.endpoint.stream_usage.unwrap_or_else(|| {
+            self.endpoint.url_template.is_none()
+                && reqwest::Url::parse(self.base_url.trim())
+                    .is_ok_and(|url| url.host_str() == Some(OPENAI_API_HOST))
+        })
+    }
+}
+
 impl Default for OpenAIConfig {
     fn default() -> Self {
         Self {
@@ -38,6 +71,376 @@ impl Default for OpenAIConfig {
             model: default_model(),
             temperature: default_temperature(),
             max_output_tokens: Some(2048),
+            retry: RetrySettings::default(),
+            timeouts: TimeoutSettings::default(),
+            responses: ResponsesSettings::default(),
+            endpoint: EndpointSettings::default(),
+        }
+    }
+}
+
+/// How the API key is sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthScheme {
+    /// `Authorization: Bearer <key>`, as OpenAI expects.
+    #[default]
+    Bearer,
+    /// The raw key in the `api_key_header` header, as Azure OpenAI expects.
+    ApiKey,
+    /// No key; a gateway authenticates through `headers` instead.
+    None,
+}
+
+impl AuthScheme {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Bearer => "Bearer token",
+            Self::ApiKey => "API key header",
+            Self::None => "None",
+        }
+    }
+}
+
+/// Endpoint layout and authentication beyond `base_url` + path. The
+/// defaults match api.openai.com and OpenAI-compatible servers.
+///
+/// For Azure OpenAI set `auth` to `api_key`, `api_version` to the service
+/// version and `url_template` to `{base_url}/deployments/{model}/{path}`
+/// with `base_url` pointing at `https://<resource>.openai.azure.com/openai`
+/// and the model set to the deployment name. Responses API requests are not
+/// deployment-scoped and skip templates that use `{model}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EndpointSettings {
+    #[serde(default)]
+    pub auth: AuthScheme,
+    #[serde(default = "EndpointSettings::default_api_key_header")]
+    pub api_key_header: String,
+    /// Appended to every request as `?api-version=`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_version: Option<String>,
+    /// Request URL with `{base_url}`, `{model}` and `{path}` placeholders.
+    /// Requests without a model (listing models, Responses) use
+    /// `{base_url}/{path}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url_template: Option<String>,
+    /// Sent with every request, e.g. `OpenAI-Organization`, `OpenAI-Project`
+    /// or a gateway token. Stored in plain text in the config file, as an
+    /// object; kept as rows here so Settings can edit names in place.
+    #[serde(default, with = "header_rows", skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<(String, String)>,
+    /// Send `stream_options.include_usage`; see [`OpenAIConfig::stream_usage`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_usage: Option<bool>,
+}
+
+impl EndpointSettings {
+    pub const AZURE_URL_TEMPLATE: &'static str = "{base_url}/deployments/{model}/{path}";
+
+    fn default_api_key_header() -> String {
+        "api-key".to_string()
+    }
+
+    /// Drops blank header rows and trims names, failing on a value without a
+    /// name or a name used twice. Settings runs it on save.
+    pub fn tidy_headers(&mut self) -> Result<()> {
+        self.headers
+            .retain(|(name, value)| !name.trim().is_empty() || !value.trim().is_empty());
+        let mut seen = HashSet::new();
+        for (name, _) in &mut self.headers {
+            *name = name.trim().to_string();
+            if name.is_empty() {
+                anyhow::bail!("a header value has no name");
+            }
+            if !seen.insert(name.to_ascii_lowercase()) {
+                anyhow::bail!("header {name} is listed twice");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// (De)serializes header rows as a JSON object.
+mod header_rows {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        headers: &[(String, String)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(headers.iter().map(|(name, value)| (name, value)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(String, String)>, D::Error> {
+        Ok(BTreeMap::<String, String>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+impl Default for EndpointSettings {
+    fn default() -> Self {
+        Self {
+            auth: AuthScheme::default(),
+            api_key_header: Self::default_api_key_header(),
+            api_version: None,
+            url_template: None,
+            headers: Vec::new(),
+            stream_usage: None,
+        }
+    }
+}
+
+/// Retry policy for failures that happen before a response body is read
+/// (connection errors, HTTP 429 and 5xx).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetrySettings {
+    #[serde(default = "RetrySettings::default_max_retries")]
+    pub max_retries: u32,
+    #[serde(default = "RetrySettings::default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "RetrySettings::default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+}
+
+impl RetrySettings {
+    fn default_max_retries() -> u32 {
+        3
+    }
+
+    fn default_initial_backoff_ms() -> u64 {
+        500
+    }
+
+    fn default_max_backoff_ms() -> u64 {
+        30_000
+    }
+}
+
+impl Default for RetrySettings {
+    fn default() -> Self {
+        Self {
+            max_retries: Self::default_max_retries(),
+            initial_backoff_ms: Self::default_initial_backoff_ms(),
+            max_backoff_ms: Self::default_max_backoff_ms(),
+        }
+    }
+}
+
+/// How hard a reasoning model thinks before answering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasoningEffort {
+    /// Fastest; gpt-5 only, and without web search.
+    Minimal,
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    pub const ALL: [Self; 4] = [Self::Minimal, Self::Low, Self::Medium, Self::High];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Minimal => "minimal",
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+}
+
+/// How long gpt-5 answers are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verbosity {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl Verbosity {
+    pub const ALL: [Self; 3] = [Self::Low, Self::Medium, Self::High];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+}
+
+/// Processing tier: `flex` is cheaper and slower, `priority` faster and
+/// more expensive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceTier {
+    /// The project's default tier.
+    #[default]
+    Auto,
+    Flex,
+    Priority,
+}
+
+impl ServiceTier {
+    pub const ALL: [Self; 3] = [Self::Auto, Self::Flex, Self::Priority];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Flex => "flex",
+            Self::Priority => "priority",
+        }
+    }
+}
+
+/// Options of models served through the Responses API. Each applies only
+/// where the model's capabilities allow it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponsesSettings {
+    #[serde(default)]
+    pub effort: ReasoningEffort,
+    #[serde(default)]
+    pub verbosity: Verbosity,
+    #[serde(default = "ResponsesSettings::default_web_search")]
+    pub web_search: bool,
+    #[serde(default)]
+    pub service_tier: ServiceTier,
+}
+
+impl ResponsesSettings {
+    fn default_web_search() -> bool {
+        true
+    }
+}
+
+impl Default for ResponsesSettings {
+    fn default() -> Self {
+        Self {
+            effort: ReasoningEffort::default(),
+            verbosity: Verbosity::default(),
+            web_search: Self::default_web_search(),
+            service_tier: ServiceTier::default(),
+        }
+    }
+}
+
+/// How long a request may wait on the network, in seconds; `0` means no
+/// limit. There is no limit on a whole answer, so long reasoning streams run
+/// to completion as long as data keeps arriving.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeoutSettings {
+    #[serde(default = "TimeoutSettings::default_connect_secs")]
+    pub connect_secs: u64,
+    /// Wait from sending a request to the first chunk of a streamed body,
+    /// headers included. Non-streamed answers must arrive within it. Running
+    /// out is reported rather than retried, since the request may already be
+    /// billed.
+    #[serde(default = "TimeoutSettings::default_first_byte_secs")]
+    pub first_byte_secs: u64,
+    /// Longest gap between two chunks of a streamed body before the stream
+    /// counts as stalled.
+    #[serde(default = "TimeoutSettings::default_idle_secs")]
+    pub idle_secs: u64,
+}
+
+impl TimeoutSettings {
+    fn default_connect_secs() -> u64 {
+        10
+    }
+
+    fn default_first_byte_secs() -> u64 {
+        120
+    }
+
+    fn default_idle_secs() -> u64 {
+        60
+    }
+}
+
+impl Default for TimeoutSettings {
+    fn default() -> Self {
+        Self {
+            connect_secs: Self::default_connect_secs(),
+            first_byte_secs: Self::default_first_byte_secs(),
+            idle_secs: Self::default_idle_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    #[default]
+    OpenAI,
+    Anthropic,
+}
+
+impl ProviderKind {
+    pub fn label(&self) -> &str {
+        match self {
+            ProviderKind::OpenAI => "OpenAI",
+            ProviderKind::Anthropic => "Anthropic",
+        }
+    }
+}
+
+/// One model asked in a side-by-side comparison, using the credentials of
+/// its provider.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompareTarget {
+    #[serde(default)]
+    pub provider: ProviderKind,
+    pub model: String,
+}
+
+impl CompareTarget {
+    pub fn label(&self) -> String {
+        format!("{} · {}", self.provider.label(), self.model)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompareSettings {
+    /// Models asked by "Compare"; at least two are needed.
+    #[serde(default)]
+    pub targets: Vec<CompareTarget>,
+}
+
+/// Credentials and model for the native Anthropic Messages API. Sampling
+/// parameters (temperature, max output tokens) are shared with `OpenAIConfig`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnthropicConfig {
+    #[serde(default)]
+    pub api_key: String,
+    #[serde(default = "AnthropicConfig::default_base_url")]
+    pub base_url: String,
+    #[serde(default = "AnthropicConfig::default_model")]
+    pub model: String,
+}
+
+impl AnthropicConfig {
+    fn default_base_url() -> String {
+        "https://api.anthropic.com/v1".to_string()
+    }
+
+    fn default_model() -> String {
+        "claude-sonnet-4-5".to_string()
+    }
+}
+
+impl Default for AnthropicConfig {
+    fn default() -> Self {
+        Self {
+            api_key: String::new(),
+            base_url: Self::default_base_url(),
+            model: Self::default_model(),
         }
     }
 }
@@ -50,22 +453,19 @@ pub struct CaptureSettings {
     pub hide_before_capture: bool,
     #[serde(default = "CaptureSettings::default_capture_mode")]
     pub mode: CaptureMode,
+    #[serde(default)]
+    pub upload: UploadSettings,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum CaptureMode {
+    #[default]
     ActiveMonitor,
     Primary,
     Region,
 }
 
-impl Default for CaptureMode {
-    fn default() -> Self {
-        CaptureMode::ActiveMonitor
-    }
-}
-
 impl CaptureSettings {
     fn default_attach_screenshots() -> bool {
         true
@@ -86,20 +486,156 @@ impl Default for CaptureSettings {
             attach_screenshots: Self::default_attach_screenshots(),
             hide_before_capture: Self::default_hide_before_capture(),
             mode: Self::default_capture_mode(),
+            upload: UploadSettings::default(),
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Encoding of screenshots sent to the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
-pub enum TranscriptionLanguage {
-    En,
-    Zh,
+pub enum UploadFormat {
+    Png,
+    #[default]
+    Jpeg,
+    /// Lossless; usually smaller than PNG for screenshots.
+    Webp,
+}
+
+impl UploadFormat {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Png => "PNG",
+            Self::Jpeg => "JPEG",
+            Self::Webp => "WebP",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Webp => "image/webp",
+        }
+    }
+}
+
+/// OpenAI's image `detail`: `low` costs a flat 85 tokens at 512 px, `high`
+/// tiles the image, `auto` lets the model choose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageDetail {
+    #[default]
+    Auto,
+    Low,
+    High,
+}
+
+impl ImageDetail {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Low => "low",
+            Self::High => "high",
+        }
+    }
+}
+
+/// Preprocessing applied to screenshots before they are uploaded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadSettings {
+    /// Longest side in pixels; larger screenshots are scaled down. `0`
+    /// keeps the original size.
+    #[serde(default = "UploadSettings::default_max_long_edge")]
+    pub max_long_edge: u32,
+    #[serde(default)]
+    pub format: UploadFormat,
+    /// JPEG quality from 1 to 100.
+    #[serde(default = "UploadSettings::default_quality")]
+    pub quality: u8,
+    #[serde(default)]
+    pub detail: ImageDetail,
+    #[serde(default)]
+    pub grayscale: bool,
+}
+
+impl UploadSettings {
+    fn default_max_long_edge() -> u32 {
+        2048
+    }
+
+    fn default_quality() -> u8 {
+        85
+    }
+}
+
+impl Default for UploadSettings {
+    fn default() -> Self {
+        Self {
+            max_long_edge: Self::default_max_long_edge(),
+            format: UploadFormat::default(),
+            quality: Self::default_quality(),
+            detail: ImageDetail::default(),
+            grayscale: false,
+        }
+    }
+}
+
+/// Spoken-language hint for transcription: an ISO-639-1 code such as `en`
+/// or `zh`, or `auto` to let the model detect it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TranscriptionLanguage(pub String);
+
+impl TranscriptionLanguage {
+    pub const AUTO: &'static str = "auto";
+    /// Offered in Settings; any other code can be typed in.
+    pub const COMMON: &'static [(&'static str, &'static str)] = &[
+        ("en", "English"),
+        ("zh", "Chinese"),
+        ("ja", "Japanese"),
+        ("ko", "Korean"),
+        ("es", "Spanish"),
+        ("fr", "French"),
+        ("de", "German"),
+        ("pt", "Portuguese"),
+        ("it", "Italian"),
+        ("ru", "Russian"),
+        ("hi", "Hindi"),
+        ("ar", "Arabic"),
+    ];
+
+    pub fn new(code: impl AsRef<str>) -> Self {
+        Self(code.as_ref().trim().to_ascii_lowercase())
+    }
+
+    pub fn auto() -> Self {
+        Self::new(Self::AUTO)
+    }
+
+    /// Code sent to the transcription APIs; `None` asks the model to detect
+    /// the language. Values that are not ISO-639 codes also auto-detect.
+    pub fn code(&self) -> Option<&str> {
+        let code = self.0.trim();
+        (matches!(code.len(), 2 | 3) && code.chars().all(|c| c.is_ascii_alphabetic()))
+            .then_some(code)
+    }
+
+    pub fn label(&self) -> String {
+        match self.code() {
+            None => "Auto-detect".to_string(),
+            Some(code) => Self::COMMON
+                .iter()
+                .find(|(common, _)| common.eq_ignore_ascii_case(code))
+                .map(|(_, name)| format!("{name} ({code})"))
+                .unwrap_or_else(|| code.to_string()),
+        }
+    }
 }
 
 impl Default for TranscriptionLanguage {
     fn default() -> Self {
-        TranscriptionLanguage::En
+        Self::new("en")
     }
 }
 
@@ -117,6 +653,17 @@ pub struct TranscriptionSettings {
     pub language: TranscriptionLanguage,
     #[serde(default = "default_transcription_model")]
     pub model: String,
+    /// Names and jargon passed as the transcription prompt so they are
+    /// spelled correctly.
+    #[serde(default)]
+    pub vocabulary: String,
+    /// Also pass the active system prompt as vocabulary, ahead of the terms.
+    #[serde(default)]
+    pub use_active_prompt: bool,
+    /// Send the transcript as soon as it arrives instead of leaving it in the
+    /// Ask box for editing.
+    #[serde(default)]
+    pub auto_submit: bool,
 }
 
 impl Default for TranscriptionSettings {
@@ -124,8 +671,78 @@ impl Default for TranscriptionSettings {
         Self {
             enabled: true,
             realtime: true,
-            language: TranscriptionLanguage::En,
+            language: TranscriptionLanguage::default(),
             model: default_transcription_model(),
+            vocabulary: String::new(),
+            use_active_prompt: false,
+            auto_submit: false,
+        }
+    }
+}
+
+/// Audio encoding requested from the speech endpoint. Only uncompressed
+/// formats are offered since playback does not decode MP3/Opus/AAC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeechFormat {
+    /// Raw 24 kHz mono 16-bit little-endian samples; lowest latency.
+    #[default]
+    Pcm,
+    Wav,
+}
+
+impl SpeechFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pcm => "pcm",
+            Self::Wav => "wav",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeechSettings {
+    /// Read answers aloud while they stream in.
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "SpeechSettings::default_model")]
+    pub model: String,
+    #[serde(default = "SpeechSettings::default_voice")]
+    pub voice: String,
+    #[serde(default)]
+    pub format: SpeechFormat,
+    /// Playback speed between 0.25 and 4.0.
+    #[serde(default = "SpeechSettings::default_speed")]
+    pub speed: f32,
+}
+
+impl SpeechSettings {
+    /// Voices offered in Settings.
+    pub const VOICES: &'static [&'static str] = &[
+        "alloy", "ash", "ballad", "coral", "echo", "fable", "nova", "onyx", "sage", "shimmer",
+    ];
+
+    fn default_model() -> String {
+        "gpt-4o-mini-tts".to_string()
+    }
+
+    fn default_voice() -> String {
+        "alloy".to_string()
+    }
+
+    fn default_speed() -> f32 {
+        1.0
+    }
+}
+
+impl Default for SpeechSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: Self::default_model(),
+            voice: Self::default_voice(),
+            format: SpeechFormat::default(),
+            speed: Self::default_speed(),
         }
     }
 }
@@ -140,6 +757,10 @@ pub struct HotkeyConfig {
     pub clear_session: String,
     #[serde(default = "HotkeyConfig::default_capture")]
     pub capture_screenshot: String,
+    #[serde(default = "HotkeyConfig::default_stop_answer")]
+    pub stop_answer: String,
+    #[serde(default = "HotkeyConfig::default_toggle_recording")]
+    pub toggle_recording: String,
 }
 
 impl HotkeyConfig {
@@ -158,6 +779,14 @@ impl HotkeyConfig {
     fn default_capture() -> String {
         "Ctrl+Shift+S".into()
     }
+
+    fn default_stop_answer() -> String {
+        "Ctrl+Shift+X".into()
+    }
+
+    fn default_toggle_recording() -> String {
+        "Ctrl+Shift+M".into()
+    }
 }
 
 impl Default for HotkeyConfig {
@@ -167,11 +796,13 @@ impl Default for HotkeyConfig {
             toggle_hide: Self::default_toggle_hide(),
             clear_session: Self::default_clear_session(),
             capture_screenshot: Self::default_capture(),
+            stop_answer: Self::default_stop_answer(),
+            toggle_recording: Self::default_toggle_recording(),
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PromptSettings {
     #[serde(default)]
     pub default_prompt_name: Option<String>,
@@ -179,29 +810,15 @@ pub struct PromptSettings {
     pub active_prompt_name: Option<String>,
 }
 
-impl Default for PromptSettings {
-    fn default() -> Self {
-        Self {
-            default_prompt_name: None,
-            active_prompt_name: None,
-        }
-    }
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ThemeVariant {
     Light,
+    #[default]
     Dark,
     System,
 }
 
-impl Default for ThemeVariant {
-    fn default() -> Self {
-        ThemeVariant::Dark
-    }
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UiSettings {
     #[serde(default = "UiSettings::default_opacity")]
@@ -228,31 +845,212 @@ impl Default for UiSettings {
     }
 }
 
+/// Local tools offered to models that support function calling.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolSettings {
+    /// Off until the user opts in: tools read the clipboard and the screen
+    /// without asking.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Directories the `read_file` tool may read from; empty disables it.
+    #[serde(default)]
+    pub allowed_dirs: Vec<String>,
+    /// Upper bound on model/tool round trips for a single question.
+    #[serde(default = "ToolSettings::default_max_rounds")]
+    pub max_rounds: u32,
+}
+
+impl ToolSettings {
+    fn default_max_rounds() -> u32 {
+        5
+    }
+}
+
+impl Default for ToolSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            allowed_dirs: Vec::new(),
+            max_rounds: Self::default_max_rounds(),
+        }
+    }
+}
+
+/// A Model Context Protocol server launched as a child process over stdio.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct McpServerConfig {
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Extra environment variables, e.g. tokens the server needs.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default = "McpServerConfig::default_enabled")]
+    pub enabled: bool,
+}
+
+impl McpServerConfig {
+    fn default_enabled() -> bool {
+        true
+    }
+}
+
+/// Rolling summaries that replace old turns once history nears the budget.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompactionSettings {
+    #[serde(default = "CompactionSettings::default_enabled")]
+    pub enabled: bool,
+    /// OpenAI model used for summaries; empty means the answer model.
+    #[serde(default)]
+    pub model: String,
+    /// Anthropic model used for summaries; empty means the answer model.
+    #[serde(default)]
+    pub anthropic_model: String,
+    /// Fraction of the prompt budget that triggers compaction.
+    #[serde(default = "CompactionSettings::default_trigger_ratio")]
+    pub trigger_ratio: f32,
+    /// Most recent entries always sent verbatim.
+    #[serde(default = "CompactionSettings::default_keep_recent")]
+    pub keep_recent: usize,
+}
+
+impl CompactionSettings {
+    fn default_enabled() -> bool {
+        true
+    }
+
+    fn default_trigger_ratio() -> f32 {
+        0.8
+    }
+
+    fn default_keep_recent() -> usize {
+        6
+    }
+
+    /// The summary model set for `provider`, or `answer_model` when there is
+    /// none.
+    pub fn summary_model(&self, provider: ProviderKind, answer_model: &str) -> String {
+        let model = match provider {
+            ProviderKind::OpenAI => &self.model,
+            ProviderKind::Anthropic => &self.anthropic_model,
+        };
+        match model.trim() {
+            "" => answer_model.to_string(),
+            model => model.to_string(),
+        }
+    }
+}
+
+impl Default for CompactionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: Self::default_enabled(),
+            model: String::new(),
+            anthropic_model: String::new(),
+            trigger_ratio: Self::default_trigger_ratio(),
+            keep_recent: Self::default_keep_recent(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct McpSettings {
+    #[serde(default)]
+    pub servers: Vec<McpServerConfig>,
+}
+
+impl McpSettings {
+    /// Renames servers whose name an earlier server already uses, since
+    /// running servers are told apart by name. Returns the new names.
+    pub fn dedupe_names(&mut self) -> Vec<String> {
+        let mut taken: HashSet<String> =
+            self.servers.iter().map(|server| server.name.clone()).collect();
+        let mut seen = HashSet::new();
+        let mut renamed = Vec::new();
+        for server in &mut self.servers {
+            if seen.insert(server.name.clone()) {
+                continue;
+            }
+            let name = (2..)
+                .map(|n| format!("{}-{n}", server.name))
+                .find(|name| !taken.contains(name))
+                .unwrap_or_default();
+            log::warn!("renaming duplicate MCP server '{}' to '{name}'", server.name);
+            taken.insert(name.clone());
+            seen.insert(name.clone());
+            server.name = name.clone();
+            renamed.push(name);
+        }
+        renamed
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AppConfig {
+    #[serde(default)]
+    pub provider: ProviderKind,
     #[serde(default)]
     pub openai: OpenAIConfig,
     #[serde(default)]
+    pub anthropic: AnthropicConfig,
+    #[serde(default)]
     pub capture: CaptureSettings,
     #[serde(default)]
     pub transcription: TranscriptionSettings,
     #[serde(default)]
+    pub speech: SpeechSettings,
+    #[serde(default)]
     pub hotkeys: HotkeyConfig,
     #[serde(default)]
     pub prompts: PromptSettings,
     #[serde(default)]
     pub ui: UiSettings,
+    #[serde(default)]
+    pub tools: ToolSettings,
+    #[serde(default)]
+    pub mcp: McpSettings,
+    #[serde(default)]
+    pub compaction: CompactionSettings,
+    #[serde(default)]
+    pub compare: CompareSettings,
+    /// Corrections to the built-in model registry keyed by model-name prefix.
+    #[serde(default)]
+    pub models: BTreeMap<String, ModelOverride>,
 }
 
-impl Default for AppConfig {
-    fn default() -> Self {
-        Self {
-            openai: OpenAIConfig::default(),
-            capture: CaptureSettings::default(),
-            transcription: TranscriptionSettings::default(),
-            hotkeys: HotkeyConfig::default(),
-            prompts: PromptSettings::default(),
-            ui: UiSettings::default(),
+impl AppConfig {
+    pub fn model_capabilities(&self, model: &str) -> ModelCapabilities {
+        models::capabilities(model, &self.models)
+    }
+
+    /// Resolves the connection settings for the selected provider. Requests
+    /// always carry an `OpenAIConfig`; for Anthropic the credentials, endpoint
+    /// and model are swapped in while sampling parameters stay shared.
+    pub fn active_llm_config(&self) -> OpenAIConfig {
+        self.llm_config(self.provider)
+    }
+
+    /// Connection settings for one comparison column: the provider's
+    /// credentials with the target's model.
+    pub fn compare_config(&self, target: &CompareTarget) -> OpenAIConfig {
+        OpenAIConfig {
+            model: target.model.clone(),
+            ..self.llm_config(target.provider)
+        }
+    }
+
+    pub fn llm_config(&self, provider: ProviderKind) -> OpenAIConfig {
+        match provider {
+            ProviderKind::OpenAI => self.openai.clone(),
+            ProviderKind::Anthropic => OpenAIConfig {
+                api_key: self.anthropic.api_key.clone(),
+                base_url: self.anthropic.base_url.clone(),
+                model: self.anthropic.model.clone(),
+                // The OpenAI endpoint layout does not apply to Anthropic.
+                endpoint: EndpointSettings::default(),
+                ..self.openai.clone()
+            },
         }
     }
 }
@@ -311,27 +1109,41 @@ pub fn load_or_default() -> Result<AppConfig> {
             .with_context(|| format!("failed to parse config at {}", path.display()))?
     };
 
-    // Load API key from keyring if not present in config
+    cfg.mcp.dedupe_names();
+
+    // Load API keys from keyring if not present in config
     if cfg.openai.api_key.trim().is_empty() {
-        if let Some(api_key) = load_api_key_from_keyring() {
+        if let Some(api_key) = load_api_key_from_keyring(OPENAI_KEYRING_USER) {
             cfg.openai.api_key = api_key;
         }
     }
+    if cfg.anthropic.api_key.trim().is_empty() {
+        if let Some(api_key) = load_api_key_from_keyring(ANTHROPIC_KEYRING_USER) {
+            cfg.anthropic.api_key = api_key;
+        }
+    }
 
     Ok(cfg)
 }
 
 pub fn save(cfg: &AppConfig) -> Result<()> {
-    // Save API key to keyring if available
+    // Save API keys to keyring if available
     if !cfg.openai.api_key.trim().is_empty() {
-        if let Err(err) = save_api_key_to_keyring(&cfg.openai.api_key) {
+        if let Err(err) = save_api_key_to_keyring(OPENAI_KEYRING_USER, &cfg.openai.api_key) {
             log::warn!("Failed to save API key to keyring: {err}");
         }
     }
+    if !cfg.anthropic.api_key.trim().is_empty() {
+        if let Err(err) = save_api_key_to_keyring(ANTHROPIC_KEYRING_USER, &cfg.anthropic.api_key)
+        {
+            log::warn!("Failed to save Anthropic API key to keyring: {err}");
+        }
+    }
 
-    // Save config without API key (for security)
+    // Save config without API keys (for security)
     let mut safe_cfg = cfg.clone();
     safe_cfg.openai.api_key = String::new();
+    safe_cfg.anthropic.api_key = String::new();
 
     let path = config_path()?;
     let json = serde_json::to_string_pretty(&safe_cfg).context("failed to serialize config")?;
@@ -340,20 +1152,23 @@ pub fn save(cfg: &AppConfig) -> Result<()> {
     Ok(())
 }
 
-fn get_keyring_entry() -> Result<Entry> {
-    Entry::new("ghost-ai", "openai-api-key")
+const OPENAI_KEYRING_USER: &str = "openai-api-key";
+const ANTHROPIC_KEYRING_USER: &str = "anthropic-api-key";
+
+fn get_keyring_entry(user: &str) -> Result<Entry> {
+    Entry::new("ghost-ai", user)
         .context("failed to create keyring entry")
 }
 
-fn save_api_key_to_keyring(api_key: &str) -> Result<()> {
-    let entry = get_keyring_entry()?;
+fn save_api_key_to_keyring(user: &str, api_key: &str) -> Result<()> {
+    let entry = get_keyring_entry(user)?;
     entry
         .set_password(api_key)
         .context("failed to save API key to keyring")?;
     Ok(())
 }
 
-fn load_api_key_from_keyring() -> Option<String> {
-    let entry = get_keyring_entry().ok()?;
+fn load_api_key_from_keyring(user: &str) -> Option<String> {
+    let entry = get_keyring_entry(user).ok()?;
     entry.get_password().ok()
 }
@@ -0,0 +1,362 @@
+//! Typed failures of provider API calls.
+//!
+//! Error responses from OpenAI-compatible and Anthropic endpoints are parsed
+//! into an [`ApiError`] whose [`ApiErrorKind`] tells the UI what the user can
+//! do about it. The error travels inside `anyhow::Error`; callers recover it
+//! with [`ApiError::find`].
+
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::{Response, StatusCode};
+use serde::Deserialize;
+
+use crate::retry::retry_delay_from_headers;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiErrorKind {
+    /// Missing, invalid or revoked key, or no access to the resource.
+    Auth,
+    /// Billing limit reached or no credit left.
+    Quota,
+    RateLimit,
+    ContextLengthExceeded,
+    ContentFilter,
+    ModelNotFound,
+    /// The base URL or request path does not exist.
+    EndpointNotFound,
+    Server,
+    /// The request never got a response: DNS, TLS or refused.
+    Network,
+    /// No response, or a streamed answer that stopped arriving, within the
+    /// configured timeouts.
+    Timeout,
+    /// Any other rejected request.
+    InvalidRequest,
+}
+
+impl ApiErrorKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Auth => "Authentication failed",
+            Self::Quota => "Quota exceeded",
+            Self::RateLimit => "Rate limited",
+            Self::ContextLengthExceeded => "Context length exceeded",
+            Self::ContentFilter => "Blocked by the content filter",
+            Self::ModelNotFound => "Model not found",
+            Self::EndpointNotFound => "Endpoint not found",
+            Self::Server => "Server error",
+            Self::Network => "Network error",
+            Self::Timeout => "Timed out",
+            Self::InvalidRequest => "Invalid request",
+        }
+    }
+
+    /// What the user can do about it.
+    pub fn hint(self) -> &'static str {
+        match self {
+            Self::Auth => "Check the API key in Settings.",
+            Self::Quota => "Check the plan and billing details of the account.",
+            Self::RateLimit => "Wait a moment before retrying.",
+            Self::ContextLengthExceeded => "Trim the history and retry.",
+            Self::ContentFilter => "Rephrase the question or remove the attachments.",
+            Self::ModelNotFound => "Pick another model in Settings.",
+            Self::EndpointNotFound => "Check the base URL and API flavor in Settings.",
+            Self::Server => "The service is having trouble; try again shortly.",
+            Self::Network => "Check the connection and the base URL.",
+            Self::Timeout => "Retry, or raise the timeouts in Settings.",
+            Self::InvalidRequest => "Check the model settings.",
+        }
+    }
+
+    /// Classifies an error by its HTTP status, if any, and the `type`/`code`
+    /// fields of the error body, falling back to well-known message texts.
+    /// A bare 404 is blamed on the model only when the message mentions one;
+    /// otherwise the URL itself is wrong.
+    pub fn classify(
+        status: Option<StatusCode>,
+        error_type: Option<&str>,
+        code: Option<&str>,
+        message: &str,
+    ) -> Self {
+        let tags = [error_type, code];
+        let tagged = |names: &[&str]| tags.iter().flatten().any(|tag| names.contains(tag));
+        let message = message.to_ascii_lowercase();
+
+        if tagged(&[
+            "insufficient_quota",
+            "billing_hard_limit_reached",
+            "billing_not_active",
+        ]) || status == Some(StatusCode::PAYMENT_REQUIRED)
+        {
+            Self::Quota
+        } else if tagged(&[
+            "invalid_api_key",
+            "authentication_error",
+            "permission_error",
+        ]) || status == Some(StatusCode::UNAUTHORIZED)
+            || status == Some(StatusCode::FORBIDDEN)
+        {
+            Self::Auth
+        } else if tagged(&[
+            "context_length_exceeded",
+            "string_above_max_length",
+            "request_too_large",
+        ]) || status == Some(StatusCode::PAYLOAD_TOO_LARGE)
+            || message.contains("maximum context length")
+            || message.contains("prompt is too long")
+        {
+            Self::ContextLengthExceeded
+        } else if tagged(&["content_filter", "content_policy_violation"]) {
+            Self::ContentFilter
+        } else if tagged(&["rate_limit_exceeded", "rate_limit_error"])
+            || status == Some(StatusCode::TOO_MANY_REQUESTS)
+        {
+            Self::RateLimit
+        } else if tagged(&["model_not_found", "DeploymentNotFound", "not_found_error"])
+            || (status == Some(StatusCode::NOT_FOUND) && message.contains("model"))
+        {
+            Self::ModelNotFound
+        } else if status == Some(StatusCode::NOT_FOUND) {
+            Self::EndpointNotFound
+        } else if tagged(&["overloaded_error", "api_error", "server_error"])
+            || status.is_some_and(|status| status.is_server_error() || status.as_u16() == 529)
+        {
+            Self::Server
+        } else {
+            Self::InvalidRequest
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub kind: ApiErrorKind,
+    /// `None` when no response arrived.
+    pub status: Option<StatusCode>,
+    /// The server's explanation, or the raw body when it has none.
+    pub message: String,
+    /// Server-suggested wait before retrying.
+    pub retry_after: Option<Duration>,
+}
+
+impl ApiError {
+    /// Consumes an unsuccessful response.
+    pub async fn from_response(res: Response) -> Self {
+        let status = res.status();
+        let retry_after = retry_delay_from_headers(res.headers());
+        let body = res.text().await.unwrap_or_default();
+        Self::from_body(status, &body, retry_after)
+    }
+
+    pub fn from_body(status: StatusCode, body: &str, retry_after: Option<Duration>) -> Self {
+        let detail = serde_json::from_str::<ErrorEnvelope>(body)
+            .ok()
+            .map(|envelope| envelope.error)
+            .unwrap_or_default();
+        let message = detail
+            .message
+            .filter(|message| !message.trim().is_empty())
+            .unwrap_or_else(|| body.trim().to_string());
+        let kind = ApiErrorKind::classify(
+            Some(status),
+            detail.kind.as_deref(),
+            detail.code.as_deref(),
+            &message,
+        );
+        Self {
+            kind,
+            status: Some(status),
+            message,
+            retry_after,
+        }
+    }
+
+    /// A request that failed before any response arrived, or whose body
+    /// could not be read.
+    pub fn network(err: reqwest::Error) -> Self {
+        let kind = if err.is_timeout() {
+            ApiErrorKind::Timeout
+        } else {
+            ApiErrorKind::Network
+        };
+        let mut message = err.to_string();
+        let mut source = std::error::Error::source(&err);
+        while let Some(cause) = source {
+            message.push_str(&format!(": {cause}"));
+            source = cause.source();
+        }
+        Self {
+            kind,
+            status: None,
+            message,
+            retry_after: None,
+        }
+    }
+
+    /// An error reported inside a successful response, such as a failed
+    /// event on a stream.
+    pub fn from_stream_error(code: Option<&str>, message: &str) -> Self {
+        Self {
+            kind: ApiErrorKind::classify(None, None, code, message),
+            status: None,
+            message: message.to_string(),
+            retry_after: None,
+        }
+    }
+
+    pub fn timeout(message: impl Into<String>) -> Self {
+        Self {
+            kind: ApiErrorKind::Timeout,
+            status: None,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    /// The `ApiError` anywhere in `err`'s chain.
+    pub fn find(err: &anyhow::Error) -> Option<&ApiError> {
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<ApiError>())
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            Some(status) => write!(
+                f,
+                "{} (HTTP {}): {}",
+                self.kind.label(),
+                status.as_u16(),
+                self.message
+            ),
+            None => write!(f, "{}: {}", self.kind.label(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// `{"error": {...}}`, shared by OpenAI, Azure and Anthropic.
+#[derive(Debug, Deserialize)]
+struct ErrorEnvelope {
+    #[serde(default)]
+    error: ErrorDetail,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ErrorDetail {
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+    #[serde(default, deserialize_with = "code_as_string")]
+    code: Option<String>,
+}
+
+/// Some gateways send numeric codes.
+fn code_as_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(
+        match Option::<serde_json::Value>::deserialize(deserializer)? {
+            Some(serde_json::Value::String(code)) => Some(code),
+            Some(serde_json::Value::Null) | None => None,
+            Some(other) => Some(other.to_string()),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn kind(status: u16, body: serde_json::Value) -> ApiErrorKind {
+        let status = StatusCode::from_u16(status).unwrap();
+        ApiError::from_body(status, &body.to_string(), None).kind
+    }
+
+    #[test]
+    fn classifies_openai_and_anthropic_error_bodies() {
+        let cases = [
+            (
+                429,
+                json!({ "error": { "message": "You exceeded your current quota",
+                                   "type": "insufficient_quota", "code": "insufficient_quota" } }),
+                ApiErrorKind::Quota,
+            ),
+            (
+                429,
+                json!({ "error": { "message": "Slow down", "code": "rate_limit_exceeded" } }),
+                ApiErrorKind::RateLimit,
+            ),
+            (
+                400,
+                json!({ "error": { "message": "This model's maximum context length is 128000",
+                                   "code": "context_length_exceeded" } }),
+                ApiErrorKind::ContextLengthExceeded,
+            ),
+            (
+                400,
+                json!({ "type": "error", "error": { "type": "invalid_request_error",
+                        "message": "prompt is too long: 210000 tokens > 200000 maximum" } }),
+                ApiErrorKind::ContextLengthExceeded,
+            ),
+            (
+                400,
+                json!({ "error": { "message": "filtered", "code": "content_filter" } }),
+                ApiErrorKind::ContentFilter,
+            ),
+            (
+                404,
+                json!({ "error": { "message": "The model `gpt-9` does not exist",
+                                   "code": "model_not_found" } }),
+                ApiErrorKind::ModelNotFound,
+            ),
+            (
+                404,
+                json!({ "error": { "message": "The model 'gpt-9' does not exist" } }),
+                ApiErrorKind::ModelNotFound,
+            ),
+            (
+                401,
+                json!({ "type": "error", "error": { "type": "authentication_error",
+                                                    "message": "invalid x-api-key" } }),
+                ApiErrorKind::Auth,
+            ),
+            (
+                529,
+                json!({ "type": "error", "error": { "type": "overloaded_error" } }),
+                ApiErrorKind::Server,
+            ),
+            (
+                400,
+                json!({ "error": { "message": "bad", "code": 400 } }),
+                ApiErrorKind::InvalidRequest,
+            ),
+        ];
+        for (status, body, expected) in cases {
+            assert_eq!(kind(status, body.clone()), expected, "{status} {body}");
+        }
+    }
+
+    #[test]
+    fn falls_back_to_the_raw_body() {
+        let error = ApiError::from_body(StatusCode::BAD_GATEWAY, "upstream down\n", None);
+        assert_eq!(error.kind, ApiErrorKind::Server);
+        assert_eq!(error.to_string(), "Server error (HTTP 502): upstream down");
+
+        let wrapped = anyhow::Error::new(error).context("request failed");
+        assert_eq!(ApiError::find(&wrapped).unwrap().kind, ApiErrorKind::Server);
+    }
+
+    #[test]
+    fn blames_a_bare_404_on_the_url() {
+        let error = ApiError::from_body(StatusCode::NOT_FOUND, "404 page not found", None);
+        assert_eq!(error.kind, ApiErrorKind::EndpointNotFound);
+    }
+}
@@ -16,6 +16,8 @@ pub enum HotkeyAction {
     ToggleHidden,
     ClearSession,
     CaptureScreenshot,
+    StopAnswer,
+    ToggleRecording,
 }
 
 #[derive(Clone, Debug)]
@@ -233,10 +235,7 @@ fn parse_token(token: &str) -> Option<String> {
 }
 
 pub fn parse_combo(text: &str) -> Option<KeyCombo> {
-    let tokens: Vec<String> = text
-        .split(['+', '-'])
-        .filter_map(|part| parse_token(part))
-        .collect();
+    let tokens: Vec<String> = text.split(['+', '-']).filter_map(parse_token).collect();
     if tokens.is_empty() {
         None
     } else {
@@ -271,6 +270,18 @@ pub fn bindings_from_config(cfg: &HotkeyConfig) -> Vec<HotkeyBinding> {
             combo,
         });
     }
+    if let Some(combo) = parse_combo(&cfg.stop_answer) {
+        bindings.push(HotkeyBinding {
+            action: HotkeyAction::StopAnswer,
+            combo,
+        });
+    }
+    if let Some(combo) = parse_combo(&cfg.toggle_recording) {
+        bindings.push(HotkeyBinding {
+            action: HotkeyAction::ToggleRecording,
+            combo,
+        });
+    }
 
     bindings
 }
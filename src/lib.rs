@@ -1,13 +1,29 @@
+pub mod anthropic;
 pub mod app;
 pub mod audio;
 pub mod capture;
+pub mod catalog;
+pub mod compaction;
 pub mod config;
+pub mod error;
 pub mod hotkeys;
 pub mod logging;
+pub mod mcp;
+pub mod models;
 pub mod openai;
+pub mod provider;
+pub mod realtime;
+pub mod responses;
+pub mod retry;
 pub mod session;
+pub mod speech;
+pub mod sse;
+pub mod timeouts;
+pub mod tokens;
+pub mod tools;
+pub mod usage;
 
-pub use config::{AppConfig, OpenAIConfig};
+pub use config::{AnthropicConfig, AppConfig, OpenAIConfig, ProviderKind};
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,711 @@
+//! Minimal Model Context Protocol client for servers spoken to over stdio.
+//!
+//! Each configured server is spawned as a child process exchanging
+//! newline-delimited JSON-RPC 2.0 messages. Its tools, plus a resource reader
+//! when it publishes resources, are exposed to the model through the regular
+//! [`ToolRegistry`](crate::tools::ToolRegistry).
+
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::capture::PreparedImage;
+use crate::config::McpServerConfig;
+use crate::tools::{Tool, ToolDefinition, ToolOutput};
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// OpenAI function names must match `^[a-zA-Z0-9_-]{1,64}$`.
+const MAX_TOOL_NAME_LEN: usize = 64;
+
+type PendingMap = HashMap<u64, oneshot::Sender<Result<Value>>>;
+
+/// A running MCP server and the JSON-RPC plumbing to talk to it.
+pub struct McpClient {
+    name: String,
+    stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+    pending: Arc<Mutex<PendingMap>>,
+    next_id: AtomicU64,
+    reader: JoinHandle<()>,
+    _child: Child,
+}
+
+impl McpClient {
+    /// Spawns the server and performs the `initialize` handshake.
+    pub async fn spawn(config: &McpServerConfig) -> Result<Self> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("failed to start MCP server '{}'", config.name))?;
+        let stdin = child.stdin.take().context("MCP server stdin unavailable")?;
+        let stdout = child
+            .stdout
+            .take()
+            .context("MCP server stdout unavailable")?;
+
+        let stdin = Arc::new(tokio::sync::Mutex::new(stdin));
+        let pending: Arc<Mutex<PendingMap>> = Arc::default();
+        let reader = tokio::spawn(read_messages(
+            config.name.clone(),
+            stdout,
+            stdin.clone(),
+            pending.clone(),
+        ));
+
+        let client = Self {
+            name: config.name.clone(),
+            stdin,
+            pending,
+            next_id: AtomicU64::new(1),
+            reader,
+            _child: child,
+        };
+        client
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {
+                        "name": "ghost-ai",
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                }),
+            )
+            .await
+            .with_context(|| format!("MCP server '{}' failed to initialize", config.name))?;
+        client
+            .notify("notifications/initialized", json!({}))
+            .await?;
+        Ok(client)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub async fn list_tools(&self) -> Result<Vec<McpToolInfo>> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = cursor.map_or_else(|| json!({}), |cursor| json!({ "cursor": cursor }));
+            let page: ToolsPage = serde_json::from_value(self.request("tools/list", params).await?)
+                .context("invalid tools/list response")?;
+            tools.extend(page.tools);
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(tools),
+            }
+        }
+    }
+
+    pub async fn list_resources(&self) -> Result<Vec<McpResourceInfo>> {
+        let mut resources = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = cursor.map_or_else(|| json!({}), |cursor| json!({ "cursor": cursor }));
+            let page: ResourcesPage =
+                serde_json::from_value(self.request("resources/list", params).await?)
+                    .context("invalid resources/list response")?;
+            resources.extend(page.resources);
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(resources),
+            }
+        }
+    }
+
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<ToolOutput> {
+        let result: CallToolResult = serde_json::from_value(
+            self.request(
+                "tools/call",
+                json!({ "name": name, "arguments": arguments }),
+            )
+            .await?,
+        )
+        .context("invalid tools/call response")?;
+
+        let mut output = ToolOutput::default();
+        let mut texts = Vec::new();
+        for content in result.content {
+            match content {
+                McpContent::Text { text } => texts.push(text),
+                McpContent::Image { data, mime_type } if mime_type == "image/png" => {
+                    let png = general_purpose::STANDARD
+                        .decode(data)
+                        .context("invalid base64 image from MCP server")?;
+                    output.image = Some(PreparedImage::from_png(png)?);
+                }
+                McpContent::Image { mime_type, .. } => {
+                    texts.push(format!("[{mime_type} image omitted]"));
+                }
+                McpContent::Resource { resource } => texts.extend(resource.text),
+                McpContent::Other => {}
+            }
+        }
+        output.text = texts.join("\n");
+        if result.is_error {
+            bail!("{}", output.text);
+        }
+        Ok(output)
+    }
+
+    pub async fn read_resource(&self, uri: &str) -> Result<String> {
+        let result: ReadResourceResult = serde_json::from_value(
+            self.request("resources/read", json!({ "uri": uri }))
+                .await?,
+        )
+        .context("invalid resources/read response")?;
+        let texts: Vec<String> = result
+            .contents
+            .into_iter()
+            .map(|content| match content.text {
+                Some(text) => text,
+                None => format!(
+                    "[binary {} content omitted]",
+                    content
+                        .mime_type
+                        .as_deref()
+                        .unwrap_or("application/octet-stream")
+                ),
+            })
+            .collect();
+        Ok(texts.join("\n"))
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().insert(id, tx);
+
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if let Err(err) = write_message(&self.stdin, &message).await {
+            self.pending.lock().remove(&id);
+            return Err(err);
+        }
+
+        match tokio::time::timeout(REQUEST_TIMEOUT, rx).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(anyhow!("MCP server '{}' exited", self.name)),
+            Err(_) => {
+                self.pending.lock().remove(&id);
+                Err(anyhow!(
+                    "MCP server '{}' did not answer {method}",
+                    self.name
+                ))
+            }
+        }
+    }
+
+    async fn notify(&self, method: &str, params: Value) -> Result<()> {
+        write_message(
+            &self.stdin,
+            &json!({ "jsonrpc": "2.0", "method": method, "params": params }),
+        )
+        .await
+    }
+}
+
+impl Drop for McpClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+async fn write_message(stdin: &tokio::sync::Mutex<ChildStdin>, message: &Value) -> Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    let mut stdin = stdin.lock().await;
+    stdin.write_all(&line).await?;
+    stdin.flush().await?;
+    Ok(())
+}
+
+/// Routes responses to their waiting requests and answers server-initiated
+/// requests. Lines that are not JSON-RPC are logged and skipped.
+async fn read_messages(
+    server: String,
+    stdout: ChildStdout,
+    stdin: Arc<tokio::sync::Mutex<ChildStdin>>,
+    pending: Arc<Mutex<PendingMap>>,
+) {
+    let mut lines = BufReader::new(stdout).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let message: RpcMessage = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(_) => {
+                log::debug!("ignoring non JSON-RPC output from MCP server '{server}': {line}");
+                continue;
+            }
+        };
+        match (message.id, message.method) {
+            (Some(id), Some(method)) => {
+                let reply = if method == "ping" {
+                    json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+                } else {
+                    json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32601, "message": format!("method not found: {method}") },
+                    })
+                };
+                if let Err(err) = write_message(&stdin, &reply).await {
+                    log::warn!("failed to answer MCP server '{server}': {err}");
+                }
+            }
+            (Some(id), None) => {
+                let Some(id) = id.as_u64() else { continue };
+                let Some(tx) = pending.lock().remove(&id) else {
+                    continue;
+                };
+                let result = match message.error {
+                    Some(error) => Err(anyhow!("MCP error {}: {}", error.code, error.message)),
+                    None => Ok(message.result.unwrap_or(Value::Null)),
+                };
+                let _ = tx.send(result);
+            }
+            (None, _) => {}
+        }
+    }
+    log::info!("MCP server '{server}' closed its output");
+    pending.lock().clear();
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcMessage {
+    id: Option<Value>,
+    method: Option<String>,
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpToolInfo {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub input_schema: Option<Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpResourceInfo {
+    pub uri: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ToolsPage {
+    tools: Vec<McpToolInfo>,
+    next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourcesPage {
+    resources: Vec<McpResourceInfo>,
+    next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CallToolResult {
+    #[serde(default)]
+    content: Vec<McpContent>,
+    #[serde(default)]
+    is_error: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum McpContent {
+    Text {
+        text: String,
+    },
+    Image {
+        data: String,
+        #[serde(rename = "mimeType")]
+        mime_type: String,
+    },
+    Resource {
+        resource: EmbeddedResource,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddedResource {
+    text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReadResourceResult {
+    contents: Vec<ResourceContents>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceContents {
+    text: Option<String>,
+    mime_type: Option<String>,
+}
+
+/// Builds function names for the tools of one server that are unique across
+/// servers and valid for the OpenAI API. Names that are too long are cut
+/// short and end in a hash of the full name, so two long names sharing a
+/// prefix stay distinct. Tools whose names sanitize to the same function
+/// name, such as `tickets.get` and `tickets_get`, would shadow each other in
+/// the registry, so they end in a hash too. A name listed more than once,
+/// like a server tool called `read_resource` next to the resource tool
+/// appended after it, is hashed with its repeat count.
+fn exposed_tool_names(server: &str, tools: &[&str]) -> Vec<String> {
+    let sanitize = |value: &str| -> String {
+        value
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    };
+    let names: Vec<String> = tools
+        .iter()
+        .map(|tool| format!("{}__{}", sanitize(server), sanitize(tool)))
+        .collect();
+    names
+        .iter()
+        .zip(tools)
+        .enumerate()
+        .map(|(index, (name, tool))| {
+            let collides = names.iter().filter(|other| *other == name).count() > 1;
+            if name.len() <= MAX_TOOL_NAME_LEN && !collides {
+                return name.clone();
+            }
+            let repeats = tools[..index].iter().filter(|other| *other == tool).count();
+            let key = match repeats {
+                0 => format!("{server}\0{tool}"),
+                repeats => format!("{server}\0{tool}\0{repeats}"),
+            };
+            let suffix = format!("_{:08x}", fnv1a(&key));
+            let mut name = name.clone();
+            name.truncate(name.len().min(MAX_TOOL_NAME_LEN - suffix.len()));
+            name.push_str(&suffix);
+            name
+        })
+        .collect()
+}
+
+/// 32-bit FNV-1a, stable across builds unlike the std hasher.
+fn fnv1a(value: &str) -> u32 {
+    value.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+struct McpTool {
+    client: Arc<McpClient>,
+    info: McpToolInfo,
+    exposed_name: String,
+}
+
+#[async_trait]
+impl Tool for McpTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.exposed_name.clone(),
+            description: format!(
+                "[{}] {}",
+                self.client.name(),
+                self.info.description.clone().unwrap_or_default()
+            ),
+            parameters: self
+                .info
+                .input_schema
+                .clone()
+                .unwrap_or_else(|| json!({ "type": "object", "properties": {} })),
+        }
+    }
+
+    async fn call(&self, arguments: Value) -> Result<ToolOutput> {
+        self.client.call_tool(&self.info.name, arguments).await
+    }
+}
+
+/// Exposes a server's resources as a single `read_resource` tool whose
+/// description lists what is available.
+struct McpResourceTool {
+    client: Arc<McpClient>,
+    resources: Vec<McpResourceInfo>,
+    exposed_name: String,
+}
+
+#[derive(Deserialize)]
+struct ReadResourceArgs {
+    uri: String,
+}
+
+#[async_trait]
+impl Tool for McpResourceTool {
+    fn definition(&self) -> ToolDefinition {
+        let listing: Vec<String> = self
+            .resources
+            .iter()
+            .map(|resource| match &resource.description {
+                Some(description) => format!("{} ({}): {description}", resource.uri, resource.name),
+                None => format!("{} ({})", resource.uri, resource.name),
+            })
+            .collect();
+        ToolDefinition {
+            name: self.exposed_name.clone(),
+            description: format!(
+                "[{}] Reads a resource by URI. Available resources:\n{}",
+                self.client.name(),
+                listing.join("\n")
+            ),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "uri": { "type": "string", "description": "Resource URI to read" }
+                },
+                "required": ["uri"]
+            }),
+        }
+    }
+
+    async fn call(&self, arguments: Value) -> Result<ToolOutput> {
+        let args: ReadResourceArgs =
+            serde_json::from_value(arguments).context("expected a 'uri' string argument")?;
+        Ok(ToolOutput::text(
+            self.client.read_resource(&args.uri).await?,
+        ))
+    }
+}
+
+/// Connection state of a configured server, shown in Settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum McpServerStatus {
+    Starting,
+    Running { tools: usize, resources: usize },
+    Failed(String),
+}
+
+struct McpServer {
+    config: McpServerConfig,
+    status: McpServerStatus,
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+/// Keeps the enabled MCP servers running and collects their tools.
+#[derive(Default)]
+pub struct McpManager {
+    servers: Mutex<HashMap<String, McpServer>>,
+}
+
+impl McpManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts enabled servers that are not running yet and stops servers
+    /// that were disabled, removed or reconfigured.
+    pub async fn sync(&self, configs: &[McpServerConfig]) {
+        let wanted: Vec<&McpServerConfig> = configs
+            .iter()
+            .filter(|config| config.enabled && !config.command.trim().is_empty())
+            .collect();
+
+        let to_start: Vec<McpServerConfig> = {
+            let mut servers = self.servers.lock();
+            servers.retain(|name, server| {
+                wanted
+                    .iter()
+                    .any(|config| &config.name == name && **config == server.config)
+            });
+            let to_start: Vec<McpServerConfig> = wanted
+                .iter()
+                .filter(|config| !servers.contains_key(&config.name))
+                .map(|config| (*config).clone())
+                .collect();
+            for config in &to_start {
+                servers.insert(
+                    config.name.clone(),
+                    McpServer {
+                        config: config.clone(),
+                        status: McpServerStatus::Starting,
+                        tools: Vec::new(),
+                    },
+                );
+            }
+            to_start
+        };
+
+        for config in to_start {
+            let (status, tools) = match connect(&config).await {
+                Ok((tools, resources)) => (
+                    McpServerStatus::Running {
+                        tools: tools.len() - usize::from(resources > 0),
+                        resources,
+                    },
+                    tools,
+                ),
+                Err(err) => {
+                    log::warn!("MCP server '{}' unavailable: {err:#}", config.name);
+                    (McpServerStatus::Failed(format!("{err:#}")), Vec::new())
+                }
+            };
+            // The server may have been reconfigured while it was starting.
+            if let Some(server) = self.servers.lock().get_mut(&config.name) {
+                if server.config == config {
+                    server.status = status;
+                    server.tools = tools;
+                }
+            }
+        }
+    }
+
+    pub fn status(&self, name: &str) -> Option<McpServerStatus> {
+        self.servers
+            .lock()
+            .get(name)
+            .map(|server| server.status.clone())
+    }
+
+    /// Tools from every running server, ready to add to a registry.
+    pub fn tools(&self) -> Vec<Arc<dyn Tool>> {
+        self.servers
+            .lock()
+            .values()
+            .flat_map(|server| server.tools.iter().cloned())
+            .collect()
+    }
+}
+
+/// Spawns a server and wraps its tools; also returns the resource count.
+async fn connect(config: &McpServerConfig) -> Result<(Vec<Arc<dyn Tool>>, usize)> {
+    let client = Arc::new(McpClient::spawn(config).await?);
+    let infos = client.list_tools().await?;
+    // Resources are optional; servers without them answer with an error.
+    let resources = client.list_resources().await.unwrap_or_default();
+    let resource_count = resources.len();
+
+    let mut names: Vec<&str> = infos.iter().map(|info| info.name.as_str()).collect();
+    if !resources.is_empty() {
+        names.push("read_resource");
+    }
+    let mut exposed_names = exposed_tool_names(&config.name, &names).into_iter();
+
+    let mut tools: Vec<Arc<dyn Tool>> = infos
+        .into_iter()
+        .zip(exposed_names.by_ref())
+        .map(|(info, exposed_name)| {
+            Arc::new(McpTool {
+                exposed_name,
+                client: client.clone(),
+                info,
+            }) as Arc<dyn Tool>
+        })
+        .collect();
+    if let Some(exposed_name) = exposed_names.next() {
+        tools.push(Arc::new(McpResourceTool {
+            exposed_name,
+            client: client.clone(),
+            resources,
+        }));
+    }
+    Ok((tools, resource_count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exposed_tool_name(server: &str, tool: &str) -> String {
+        exposed_tool_names(server, &[tool]).remove(0)
+    }
+
+    #[test]
+    fn exposed_names_are_valid_function_names() {
+        assert_eq!(exposed_tool_name("docs", "search"), "docs__search");
+        assert_eq!(
+            exposed_tool_name("my docs", "tickets.get"),
+            "my_docs__tickets_get"
+        );
+        assert_eq!(
+            exposed_tool_name(&"s".repeat(80), "x").len(),
+            MAX_TOOL_NAME_LEN
+        );
+    }
+
+    #[test]
+    fn long_names_sharing_a_prefix_stay_distinct() {
+        let server = "documentation-server";
+        let first = exposed_tool_name(server, &format!("{}_first", "lookup".repeat(8)));
+        let second = exposed_tool_name(server, &format!("{}_second", "lookup".repeat(8)));
+        assert_ne!(first, second);
+        assert_eq!(first.len(), MAX_TOOL_NAME_LEN);
+        assert_eq!(second.len(), MAX_TOOL_NAME_LEN);
+        assert!(first.starts_with("documentation-server__lookup"));
+    }
+
+    #[test]
+    fn colliding_names_end_in_distinct_hashes() {
+        let names = exposed_tool_names(
+            "tickets",
+            &[
+                "tickets.get",
+                "tickets_get",
+                "search",
+                "read_resource",
+                "read_resource",
+            ],
+        );
+
+        assert_eq!(names[2], "tickets__search");
+        assert!(names[0].starts_with("tickets__tickets_get_"));
+        assert!(names[1].starts_with("tickets__tickets_get_"));
+        assert_ne!(names[0], names[1]);
+        assert!(names[4].starts_with("tickets__read_resource_"));
+        assert_ne!(names[3], names[4]);
+        // The first of two equal names keeps the hash of a lone long name.
+        assert_eq!(
+            names[3],
+            format!(
+                "tickets__read_resource_{:08x}",
+                fnv1a("tickets\0read_resource")
+            )
+        );
+    }
+}
@@ -0,0 +1,451 @@
+//! What each model supports, so request builders and the UI stop guessing
+//! from model names.
+//!
+//! Built-in entries cover the common OpenAI and Anthropic models and match by
+//! the longest model-name prefix. Entries in the config's `models` map are
+//! layered on top, shortest prefix first, so `"gpt-5"` can set a default for
+//! the family while `"gpt-5-nano"` refines it.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::usage::ModelPrice;
+
+/// OpenAI endpoint a model is served from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiFlavor {
+    #[default]
+    ChatCompletions,
+    Responses,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelCapabilities {
+    pub api: ApiFlavor,
+    /// Accepts image input.
+    pub vision: bool,
+    /// Thinks before answering; such models reject `temperature` and accept a
+    /// reasoning effort.
+    pub reasoning: bool,
+    /// Accepts the `minimal` reasoning effort.
+    pub minimal_effort: bool,
+    /// Accepts `text.verbosity` other than `medium`.
+    pub verbosity: bool,
+    /// Can use the hosted web search tool.
+    pub web_search: bool,
+    /// Can be served on the `priority` service tier.
+    pub priority_tier: bool,
+    /// Prompt plus output, in tokens.
+    pub context_window: usize,
+    /// Upper limit on `max_output_tokens`, when known.
+    pub max_output_tokens: Option<u32>,
+    pub price: Option<ModelPrice>,
+}
+
+impl ModelCapabilities {
+    /// What unknown models are assumed to support: plain chat completions
+    /// with images and a conservative context window.
+    pub const DEFAULT: Self = Self {
+        api: ApiFlavor::ChatCompletions,
+        vision: true,
+        reasoning: false,
+        minimal_effort: false,
+        verbosity: false,
+        web_search: false,
+        priority_tier: false,
+        context_window: 128_000,
+        max_output_tokens: None,
+        price: None,
+    };
+
+    /// Caps a configured output limit at what the model can produce.
+    pub fn clamp_output_tokens(&self, requested: Option<u32>) -> Option<u32> {
+        match (requested, self.max_output_tokens) {
+            (Some(requested), Some(max)) => Some(requested.min(max)),
+            (requested, _) => requested,
+        }
+    }
+
+    /// Short human-readable list of features, for the settings window.
+    pub fn describe(&self) -> String {
+        let mut parts = vec![match self.api {
+            ApiFlavor::ChatCompletions => "Chat Completions".to_string(),
+            ApiFlavor::Responses => "Responses API".to_string(),
+        }];
+        for (supported, name) in [
+            (self.vision, "vision"),
+            (self.reasoning, "reasoning"),
+            (self.web_search, "web search"),
+        ] {
+            if supported {
+                parts.push(name.to_string());
+            }
+        }
+        parts.push(format!("{}k context", self.context_window / 1_000));
+        if let Some(max) = self.max_output_tokens {
+            parts.push(format!("{max} max output"));
+        }
+        parts.join(" · ")
+    }
+
+    fn apply(&mut self, overrides: &ModelOverride) {
+        let ModelOverride {
+            api,
+            vision,
+            reasoning,
+            minimal_effort,
+            verbosity,
+            web_search,
+            priority_tier,
+            context_window,
+            max_output_tokens,
+            price,
+        } = *overrides;
+        self.api = api.unwrap_or(self.api);
+        self.vision = vision.unwrap_or(self.vision);
+        self.reasoning = reasoning.unwrap_or(self.reasoning);
+        self.minimal_effort = minimal_effort.unwrap_or(self.minimal_effort);
+        self.verbosity = verbosity.unwrap_or(self.verbosity);
+        self.web_search = web_search.unwrap_or(self.web_search);
+        self.priority_tier = priority_tier.unwrap_or(self.priority_tier);
+        self.context_window = context_window.unwrap_or(self.context_window);
+        self.max_output_tokens = max_output_tokens.or(self.max_output_tokens);
+        self.price = price.or(self.price);
+    }
+}
+
+/// User-provided corrections to the built-in table; unset fields keep the
+/// built-in value.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api: Option<ApiFlavor>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vision: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimal_effort: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verbosity: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub web_search: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority_tier: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price: Option<ModelPrice>,
+}
+
+const CHAT: ModelCapabilities = ModelCapabilities::DEFAULT;
+const TEXT_ONLY: ModelCapabilities = ModelCapabilities {
+    vision: false,
+    ..CHAT
+};
+const REASONING: ModelCapabilities = ModelCapabilities {
+    reasoning: true,
+    context_window: 200_000,
+    max_output_tokens: Some(100_000),
+    ..CHAT
+};
+const GPT_5: ModelCapabilities = ModelCapabilities {
+    api: ApiFlavor::Responses,
+    reasoning: true,
+    minimal_effort: true,
+    verbosity: true,
+    web_search: true,
+    priority_tier: true,
+    context_window: 400_000,
+    max_output_tokens: Some(128_000),
+    ..CHAT
+};
+const CLAUDE: ModelCapabilities = ModelCapabilities {
+    context_window: 200_000,
+    max_output_tokens: Some(8_192),
+    ..CHAT
+};
+
+/// Published limits and list prices. Anthropic models ignore `api`; the
+/// provider setting picks their endpoint.
+const BUILTIN_MODELS: &[(&str, ModelCapabilities)] = &[
+    (
+        "gpt-5",
+        ModelCapabilities {
+            price: Some(ModelPrice::new(1.25, 0.125, 10.0)),
+            ..GPT_5
+        },
+    ),
+    (
+        "gpt-5-mini",
+        ModelCapabilities {
+            price: Some(ModelPrice::new(0.25, 0.025, 2.0)),
+            ..GPT_5
+        },
+    ),
+    (
+        "gpt-5-nano",
+        ModelCapabilities {
+            web_search: false,
+            priority_tier: false,
+            price: Some(ModelPrice::new(0.05, 0.005, 0.4)),
+            ..GPT_5
+        },
+    ),
+    (
+        "gpt-4.1",
+        ModelCapabilities {
+            context_window: 1_047_576,
+            max_output_tokens: Some(32_768),
+            price: Some(ModelPrice::new(2.0, 0.5, 8.0)),
+            ..CHAT
+        },
+    ),
+    (
+        "gpt-4.1-mini",
+        ModelCapabilities {
+            context_window: 1_047_576,
+            max_output_tokens: Some(32_768),
+            price: Some(ModelPrice::new(0.4, 0.1, 1.6)),
+            ..CHAT
+        },
+    ),
+    (
+        "gpt-4.1-nano",
+        ModelCapabilities {
+            context_window: 1_047_576,
+            max_output_tokens: Some(32_768),
+            price: Some(ModelPrice::new(0.1, 0.025, 0.4)),
+            ..CHAT
+        },
+    ),
+    (
+        "gpt-4o",
+        ModelCapabilities {
+            max_output_tokens: Some(16_384),
+            price: Some(ModelPrice::new(2.5, 1.25, 10.0)),
+            ..CHAT
+        },
+    ),
+    (
+        "gpt-4o-mini",
+        ModelCapabilities {
+            max_output_tokens: Some(16_384),
+            price: Some(ModelPrice::new(0.15, 0.075, 0.6)),
+            ..CHAT
+        },
+    ),
+    (
+        "chatgpt-4o",
+        ModelCapabilities {
+            max_output_tokens: Some(16_384),
+            ..CHAT
+        },
+    ),
+    (
+        "gpt-4-turbo",
+        ModelCapabilities {
+            max_output_tokens: Some(4_096),
+            ..CHAT
+        },
+    ),
+    (
+        "gpt-4-32k",
+        ModelCapabilities {
+            context_window: 32_768,
+            max_output_tokens: Some(4_096),
+            ..TEXT_ONLY
+        },
+    ),
+    (
+        "gpt-4",
+        ModelCapabilities {
+            context_window: 8_192,
+            max_output_tokens: Some(8_192),
+            ..TEXT_ONLY
+        },
+    ),
+    (
+        "gpt-3.5-turbo",
+        ModelCapabilities {
+            context_window: 16_385,
+            max_output_tokens: Some(4_096),
+            ..TEXT_ONLY
+        },
+    ),
+    (
+        "o1",
+        ModelCapabilities {
+            price: Some(ModelPrice::new(15.0, 7.5, 60.0)),
+            ..REASONING
+        },
+    ),
+    (
+        "o1-mini",
+        ModelCapabilities {
+            vision: false,
+            context_window: 128_000,
+            max_output_tokens: Some(65_536),
+            ..REASONING
+        },
+    ),
+    (
+        "o3",
+        ModelCapabilities {
+            price: Some(ModelPrice::new(2.0, 0.5, 8.0)),
+            ..REASONING
+        },
+    ),
+    (
+        "o3-mini",
+        ModelCapabilities {
+            vision: false,
+            price: Some(ModelPrice::new(1.1, 0.55, 4.4)),
+            ..REASONING
+        },
+    ),
+    (
+        "o4-mini",
+        ModelCapabilities {
+            api: ApiFlavor::Responses,
+            price: Some(ModelPrice::new(1.1, 0.275, 4.4)),
+            ..REASONING
+        },
+    ),
+    ("claude", CLAUDE),
+    (
+        "claude-opus-4",
+        ModelCapabilities {
+            reasoning: true,
+            max_output_tokens: Some(32_000),
+            price: Some(ModelPrice::new(15.0, 1.5, 75.0)),
+            ..CLAUDE
+        },
+    ),
+    (
+        "claude-sonnet-4",
+        ModelCapabilities {
+            reasoning: true,
+            max_output_tokens: Some(64_000),
+            price: Some(ModelPrice::new(3.0, 0.3, 15.0)),
+            ..CLAUDE
+        },
+    ),
+    (
+        "claude-haiku-4",
+        ModelCapabilities {
+            reasoning: true,
+            max_output_tokens: Some(64_000),
+            price: Some(ModelPrice::new(1.0, 0.1, 5.0)),
+            ..CLAUDE
+        },
+    ),
+    (
+        "claude-3-5-haiku",
+        ModelCapabilities {
+            price: Some(ModelPrice::new(0.8, 0.08, 4.0)),
+            ..CLAUDE
+        },
+    ),
+];
+
+/// Resolves what `model` supports: the longest matching built-in entry (or
+/// [`ModelCapabilities::DEFAULT`]) with every matching override applied on
+/// top, shortest prefix first.
+pub fn capabilities(model: &str, overrides: &BTreeMap<String, ModelOverride>) -> ModelCapabilities {
+    let mut capabilities = BUILTIN_MODELS
+        .iter()
+        .filter(|(prefix, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, capabilities)| *capabilities)
+        .unwrap_or(ModelCapabilities::DEFAULT);
+
+    let mut matching: Vec<(&String, &ModelOverride)> = overrides
+        .iter()
+        .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+        .collect();
+    matching.sort_by_key(|(prefix, _)| prefix.len());
+    for (_, overrides) in matching {
+        capabilities.apply(overrides);
+    }
+    capabilities
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_longest_builtin_prefix() {
+        let none = BTreeMap::new();
+        assert_eq!(capabilities("gpt-4o-mini", &none).context_window, 128_000);
+        assert_eq!(capabilities("gpt-4-0613", &none).context_window, 8_192);
+        assert_eq!(
+            capabilities("gpt-4.1-mini", &none).context_window,
+            1_047_576
+        );
+        assert_eq!(
+            capabilities("o1-mini-2024-09-12", &none).context_window,
+            128_000
+        );
+        assert_eq!(
+            capabilities("gpt-5-2025-08-07", &none).api,
+            ApiFlavor::Responses
+        );
+        assert!(!capabilities("gpt-5-nano", &none).web_search);
+        assert!(!capabilities("gpt-3.5-turbo", &none).vision);
+        assert_eq!(
+            capabilities("gpt-4o-mini-2024-07-18", &none)
+                .price
+                .unwrap()
+                .input,
+            0.15
+        );
+        assert_eq!(capabilities("llama3:8b", &none), ModelCapabilities::DEFAULT);
+    }
+
+    #[test]
+    fn overrides_layer_over_builtins() {
+        let mut overrides = BTreeMap::new();
+        overrides.insert(
+            "gpt-4o".to_string(),
+            ModelOverride {
+                price: Some(ModelPrice::new(1.0, 0.5, 2.0)),
+                context_window: Some(64_000),
+                ..ModelOverride::default()
+            },
+        );
+        overrides.insert(
+            "gpt-4o-mini".to_string(),
+            ModelOverride {
+                context_window: Some(32_000),
+                ..ModelOverride::default()
+            },
+        );
+        overrides.insert(
+            "llama3".to_string(),
+            ModelOverride {
+                vision: Some(false),
+                ..ModelOverride::default()
+            },
+        );
+
+        let mini = capabilities("gpt-4o-mini", &overrides);
+        assert_eq!(mini.context_window, 32_000);
+        assert_eq!(mini.price.unwrap().input, 1.0);
+        assert_eq!(mini.max_output_tokens, Some(16_384));
+        assert!(!capabilities("llama3:8b", &overrides).vision);
+    }
+
+    #[test]
+    fn clamps_output_tokens_to_model_limit() {
+        let capabilities = capabilities("gpt-4-turbo", &BTreeMap::new());
+        assert_eq!(capabilities.clamp_output_tokens(Some(8_000)), Some(4_096));
+        assert_eq!(capabilities.clamp_output_tokens(Some(100)), Some(100));
+        assert_eq!(capabilities.clamp_output_tokens(None), None);
+    }
+}
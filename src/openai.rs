@@ -1,188 +1,323 @@
-use std::collections::VecDeque;
-use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::{anyhow, Context, Result};
-use base64::{engine::general_purpose, Engine};
-use futures::StreamExt;
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 use reqwest::multipart::{Form, Part};
-use reqwest::Client;
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::UnboundedSender;
 use uuid::Uuid;
 
 use crate::audio::RecordingResult;
-use crate::config::{OpenAIConfig, TranscriptionLanguage};
-use crate::session::{ConversationEntry, ConversationRole};
+use crate::capture::{data_url, PreparedImage};
+use crate::config::{
+    AuthScheme, ImageDetail, OpenAIConfig, SpeechSettings, TranscriptionLanguage,
+};
+use crate::error::ApiError;
+use crate::models::ApiFlavor;
+use crate::provider::{
+    AnalyzeRequest, AnalyzeResponse, Provider, StreamEvent, TranscriptSegment, Transcription,
+    REQUEST_CANCELLED,
+};
+use crate::responses::{
+    build_responses_payload, Annotation, FunctionCall, OutputItem, ResponseError, ResponseObject,
+    ResponsesEvent, ResponsesPayload, ResponsesUsage,
+};
+use crate::retry::RetryPolicy;
+use crate::session::{Citation, ConversationRole};
+use crate::sse::SseStream;
+use crate::timeouts::{HttpClients, Timeouts};
+use crate::tokens::{budget_history, PromptParts};
+use crate::tools::{ToolDefinition, ToolRegistry};
+use crate::usage::TokenUsage;
 
 const CHAT_COMPLETIONS_PATH: &str = "chat/completions";
+const RESPONSES_PATH: &str = "responses";
 const AUDIO_TRANSCRIPTIONS_PATH: &str = "audio/transcriptions";
-
-#[derive(Debug, Clone)]
-pub struct AnalyzeRequest {
-    pub request_id: Uuid,
-    pub config: OpenAIConfig,
-    pub text_prompt: String,
-    pub custom_prompt: Option<String>,
-    pub screenshot_png: Option<Vec<u8>>,
-    pub history: VecDeque<ConversationEntry>,
-}
-
-#[derive(Debug, Clone)]
-pub struct AnalyzeResponse {
-    pub request_id: Uuid,
-    pub answer: String,
-    pub model: String,
-}
-
-#[derive(Debug, Clone)]
-pub enum StreamEvent {
-    Delta(String),
-    ReasoningDelta(String),
-    Done(String),
-    ReasoningDone(String),
-    WebSearchInProgress,
-    WebSearchSearching,
-    WebSearchCompleted,
-    Error(String),
-}
+const AUDIO_SPEECH_PATH: &str = "audio/speech";
+const MODELS_PATH: &str = "models";
 
 pub struct OpenAIClient {
-    http: Client,
+    http: HttpClients,
 }
 
 impl OpenAIClient {
     pub fn new() -> Result<Self> {
-        let http = Client::builder()
-            .connect_timeout(Duration::from_secs(10))
-            .timeout(Duration::from_secs(120))
-            .build()?;
-        Ok(Self { http })
+        Ok(Self {
+            http: HttpClients::default(),
+        })
     }
 
-    pub async fn analyze_stream(
+    /// Streams one Chat Completions response, forwarding text deltas and
+    /// collecting any tool calls the model makes. `separate` starts the
+    /// streamed text with a blank line when earlier rounds produced text.
+    async fn stream_chat_round(
         &self,
-        request: AnalyzeRequest,
-        stream_tx: UnboundedSender<(Uuid, StreamEvent)>,
-    ) -> Result<AnalyzeResponse> {
+        url: &str,
+        headers: &HeaderMap,
+        payload: &ChatCompletionPayload,
+        request: &AnalyzeRequest,
+        separate: bool,
+        stream_tx: &UnboundedSender<(Uuid, StreamEvent)>,
+    ) -> Result<ChatRound> {
         let request_id = request.request_id;
-        let model = request.config.model.clone();
+        let cancel = &request.cancel;
+
+        let timeouts = Timeouts::from(&request.config.timeouts);
+        let http = self.http.get(&timeouts)?;
+        let policy = timeouts.apply_to(RetryPolicy::from(&request.config.retry));
+        let mut sent = Instant::now();
+        let res = cancel
+            .run_until_cancelled(policy.send(
+                || {
+                    sent = Instant::now();
+                    http.post(url).headers(headers.clone()).json(payload)
+                },
+                |notice| {
+                    let _ = stream_tx.send((request_id, StreamEvent::Retrying(notice)));
+                },
+            ))
+            .await
+            .ok_or_else(|| anyhow!(REQUEST_CANCELLED))?
+            .context("failed to send chat completion request")?;
 
-        // Check if we should use Responses API for advanced models
-        if is_responses_api_model(&model) {
-            return self.analyze_stream_responses_api(request, stream_tx).await;
+        if !res.status().is_success() {
+            let error = ApiError::from_response(res).await;
+            let _ = stream_tx.send((request_id, StreamEvent::Error(error.to_string())));
+            return Err(error.into());
         }
 
-        let url = build_endpoint(&request.config.base_url, CHAT_COMPLETIONS_PATH)?;
-        let mut headers = auth_headers(&request.config.api_key)?;
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        let mut payload = build_chat_payload(&request)?;
-        payload.stream = true;
+        let mut events = SseStream::new(timeouts.watch_body(sent, res.bytes_stream()));
+        let mut round = ChatRound::default();
 
-        let res = self
-            .http
-            .post(url)
-            .headers(headers)
-            .json(&payload)
-            .send()
-            .await
-            .context("failed to send chat completion request")?;
+        while let Some(event) = cancel.run_until_cancelled(events.next_event()).await.flatten() {
+            let event = event.context("failed to read chunk from stream")?;
+            if event.is_done() {
+                round.finished = true;
+                break;
+            }
 
-        if !res.status().is_success() {
-            let status = res.status();
-            let body = res.text().await.unwrap_or_default();
-            let error = format!("OpenAI request failed with status {status}: {body}");
-            let _ = stream_tx.send((request_id, StreamEvent::Error(error.clone())));
-            return Err(anyhow!(error));
+            match serde_json::from_str::<StreamChunk>(&event.data) {
+                Ok(chunk) => {
+                    // With `include_usage` the last chunk has usage and no choices.
+                    if let Some(usage) = chunk.usage {
+                        round.usage = Some(usage.into());
+                    }
+                    let Some(choice) = chunk.choices.into_iter().next() else {
+                        continue;
+                    };
+                    if let Some(reason) = choice.finish_reason.as_deref() {
+                        if reason == "length" {
+                            log::warn!("chat completion hit the output token limit");
+                            round.incomplete = true;
+                        }
+                        round.finished = true;
+                    }
+                    if let Some(content) = choice.delta.content {
+                        if separate && round.text.is_empty() && !content.is_empty() {
+                            let _ = stream_tx.send((request_id, StreamEvent::Delta("\n\n".into())));
+                        }
+                        round.text.push_str(&content);
+                        let _ = stream_tx.send((request_id, StreamEvent::Delta(content)));
+                    }
+                    for delta in choice.delta.tool_calls.unwrap_or_default() {
+                        round.merge_tool_call(delta);
+                    }
+                }
+                Err(err) => {
+                    log::warn!("failed to parse stream chunk: {err}");
+                }
+            }
         }
 
-        let mut stream = res.bytes_stream();
-        let mut full_text = String::new();
-        let mut buffer = String::new();
+        Ok(round)
+    }
+
+    /// Streams one Responses API response. Text, reasoning, usage and
+    /// citations are forwarded and added to `answer`; function calls are
+    /// collected for the caller to run.
+    async fn stream_responses_round(
+        &self,
+        url: &str,
+        headers: &HeaderMap,
+        payload: &ResponsesPayload,
+        request: &AnalyzeRequest,
+        answer: &mut ResponsesAnswer,
+        stream_tx: &UnboundedSender<(Uuid, StreamEvent)>,
+    ) -> Result<ResponsesRound> {
+        let request_id = request.request_id;
+        let cancel = &request.cancel;
+        let model = &request.config.model;
+
+        let timeouts = Timeouts::from(&request.config.timeouts);
+        let http = self.http.get(&timeouts)?;
+        let policy = timeouts.apply_to(RetryPolicy::from(&request.config.retry));
+        let mut sent = Instant::now();
+        let res = cancel
+            .run_until_cancelled(policy.send(
+                || {
+                    sent = Instant::now();
+                    http.post(url).headers(headers.clone()).json(payload)
+                },
+                |notice| {
+                    let _ = stream_tx.send((request_id, StreamEvent::Retrying(notice)));
+                },
+            ))
+            .await
+            .ok_or_else(|| anyhow!(REQUEST_CANCELLED))?
+            .context("failed to send responses API request")?;
 
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.context("failed to read chunk from stream")?;
-            let text = String::from_utf8_lossy(&chunk);
-            buffer.push_str(&text);
+        if !res.status().is_success() {
+            let error = ApiError::from_response(res).await;
+            let _ = stream_tx.send((request_id, StreamEvent::Error(error.to_string())));
+            return Err(error.into());
+        }
 
-            while let Some(line_end) = buffer.find('\n') {
-                let line = buffer[..line_end].trim().to_string();
-                buffer = buffer[line_end + 1..].to_string();
+        let mut events = SseStream::new(timeouts.watch_body(sent, res.bytes_stream()));
+        let mut round = ResponsesRound::default();
 
-                if line.is_empty() || !line.starts_with("data: ") {
+        while let Some(event) = cancel.run_until_cancelled(events.next_event()).await.flatten() {
+            let event = event.context("failed to read chunk from responses stream")?;
+            if event.is_done() {
+                break;
+            }
+            let parsed = match serde_json::from_str::<ResponsesEvent>(&event.data) {
+                Ok(parsed) => parsed,
+                Err(err) => {
+                    log::warn!("failed to parse {} event: {err}", event.event);
                     continue;
                 }
-
-                let data = &line[6..];
-                if data == "[DONE]" {
-                    break;
+            };
+
+            match parsed {
+                ResponsesEvent::OutputTextDelta { delta } => {
+                    // Separate the text of this round from that of earlier ones.
+                    if round.text.is_empty() && !answer.text.is_empty() {
+                        answer.text.push_str("\n\n");
+                        let _ = stream_tx.send((request_id, StreamEvent::Delta("\n\n".into())));
+                    }
+                    round.text.push_str(&delta);
+                    answer.text.push_str(&delta);
+                    let _ = stream_tx.send((request_id, StreamEvent::Delta(delta)));
                 }
-
-                match serde_json::from_str::<StreamChunk>(data) {
-                    Ok(chunk) => {
-                        if let Some(choice) = chunk.choices.first() {
-                            if let Some(content) = &choice.delta.content {
-                                full_text.push_str(content);
-                                let _ = stream_tx
-                                    .send((request_id, StreamEvent::Delta(content.clone())));
-                            }
-                        }
+                ResponsesEvent::ReasoningSummaryPartAdded {} => {
+                    // Separate the paragraphs of a multi-part summary.
+                    if !answer.reasoning.is_empty() {
+                        answer.reasoning.push_str("\n\n");
+                        let delta = StreamEvent::ReasoningDelta("\n\n".into());
+                        let _ = stream_tx.send((request_id, delta));
                     }
-                    Err(err) => {
-                        log::warn!("failed to parse stream chunk: {err}");
+                }
+                ResponsesEvent::ReasoningSummaryTextDelta { delta } => {
+                    answer.reasoning.push_str(&delta);
+                    let _ = stream_tx.send((request_id, StreamEvent::ReasoningDelta(delta)));
+                }
+                ResponsesEvent::WebSearchInProgress {} => {
+                    let _ = stream_tx.send((request_id, StreamEvent::WebSearchInProgress));
+                }
+                ResponsesEvent::WebSearchSearching {} => {
+                    let _ = stream_tx.send((request_id, StreamEvent::WebSearchSearching));
+                }
+                ResponsesEvent::WebSearchCompleted {} => {
+                    let _ = stream_tx.send((request_id, StreamEvent::WebSearchCompleted));
+                }
+                ResponsesEvent::AnnotationAdded { annotation } => {
+                    if let Some(citation) = annotation.citation() {
+                        answer.citations.push(citation.clone());
+                        let _ = stream_tx.send((request_id, StreamEvent::Citation(citation)));
+                    }
+                }
+                ResponsesEvent::ContentPartDone { part } => {
+                    for citation in part.annotations.into_iter().filter_map(Annotation::citation) {
+                        answer.citations.push(citation.clone());
+                        let _ = stream_tx.send((request_id, StreamEvent::Citation(citation)));
                     }
                 }
+                ResponsesEvent::OutputItemDone { item } => {
+                    if let OutputItem::FunctionCall(call) = item {
+                        round.function_calls.push(call);
+                    }
+                }
+                ResponsesEvent::Completed { response } => {
+                    answer.add_usage(response.usage);
+                    round.finished = true;
+                    break;
+                }
+                ResponsesEvent::Incomplete { response } => {
+                    let reason = response
+                        .incomplete_details
+                        .map_or_else(|| "unknown".to_string(), |details| details.reason);
+                    log::warn!("response for {model} is incomplete: {reason}");
+                    answer.add_usage(response.usage);
+                    round.incomplete = true;
+                    round.finished = true;
+                    break;
+                }
+                ResponsesEvent::Failed { response } => {
+                    let error = response.error.unwrap_or_else(|| ResponseError {
+                        code: None,
+                        message: "the response failed without details".to_string(),
+                    });
+                    let error = ApiError::from_stream_error(error.code.as_deref(), &error.message);
+                    let _ = stream_tx.send((request_id, StreamEvent::Error(error.to_string())));
+                    return Err(error.into());
+                }
+                ResponsesEvent::Error(error) => {
+                    let error = ApiError::from_stream_error(error.code.as_deref(), &error.message);
+                    let _ = stream_tx.send((request_id, StreamEvent::Error(error.to_string())));
+                    return Err(error.into());
+                }
+                ResponsesEvent::Other => {}
             }
         }
 
-        let _ = stream_tx.send((request_id, StreamEvent::Done(full_text.clone())));
-
-        Ok(AnalyzeResponse {
-            request_id,
-            answer: full_text,
-            model,
-        })
+        Ok(round)
     }
 
-    pub async fn analyze(&self, request: AnalyzeRequest) -> Result<AnalyzeResponse> {
-        let url = build_endpoint(&request.config.base_url, CHAT_COMPLETIONS_PATH)?;
-        let mut headers = auth_headers(&request.config.api_key)?;
+    /// Sends one non-streamed Responses API request. Tool calls are not
+    /// run; this serves one-shot requests such as history summaries.
+    async fn analyze_responses_api(&self, request: AnalyzeRequest) -> Result<AnalyzeResponse> {
+        let url = endpoint_url(&request.config, RESPONSES_PATH, None)?;
+        let mut headers = auth_headers(&request.config)?;
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        let payload = build_chat_payload(&request)?;
-
-        let res = self
-            .http
-            .post(url)
-            .headers(headers)
-            .json(&payload)
-            .send()
+        let mut payload = build_responses_payload(&request);
+        payload.stream = false;
+
+        let timeouts = Timeouts::from(&request.config.timeouts);
+        let http = self.http.get(&timeouts)?;
+        let policy = RetryPolicy::from(&request.config.retry);
+        let res = policy
+            .send(
+                || timeouts.limit(http.post(&url).headers(headers.clone()).json(&payload)),
+                |_| {},
+            )
             .await
-            .context("failed to send chat completion request")?;
+            .context("failed to send responses API request")?;
 
         if !res.status().is_success() {
-            let status = res.status();
-            let body = res.text().await.unwrap_or_default();
-            return Err(anyhow!(
-                "OpenAI request failed with status {status}: {body}",
-                status = status
-            ));
+            return Err(ApiError::from_response(res).await.into());
         }
 
-        let parsed: ChatCompletionResponse = res
+        let parsed: ResponseObject = res
             .json()
             .await
-            .context("failed to decode chat completion response")?;
+            .context("failed to decode responses API response")?;
+        if let Some(details) = &parsed.incomplete_details {
+            let model = &request.config.model;
+            log::warn!("response for {model} is incomplete: {}", details.reason);
+        }
 
-        let answer = parsed
-            .choices
-            .iter()
-            .find_map(|choice| choice.message.text())
+        let answer = Some(parsed.output_text())
+            .filter(|text| !text.is_empty())
             .unwrap_or_else(|| "<empty response>".to_string());
-
         Ok(AnalyzeResponse {
             request_id: request.request_id,
             answer,
             model: parsed.model.unwrap_or(request.config.model),
+            interrupted: parsed.incomplete_details.is_some(),
+            usage: parsed.usage.map(TokenUsage::from),
+            citations: Vec::new(),
         })
     }
 
@@ -191,187 +326,498 @@ impl OpenAIClient {
         request: AnalyzeRequest,
         stream_tx: UnboundedSender<(Uuid, StreamEvent)>,
     ) -> Result<AnalyzeResponse> {
-        let url = build_endpoint(&request.config.base_url, "responses")?;
-        let mut headers = auth_headers(&request.config.api_key)?;
+        // The model travels in the body; on Azure, Responses is not scoped to
+        // a deployment like the other paths.
+        let url = endpoint_url(&request.config, RESPONSES_PATH, None)?;
+        let mut headers = auth_headers(&request.config)?;
         headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
 
-        let payload = build_responses_payload(&request)?;
+        let mut payload = build_responses_payload(&request);
         let request_id = request.request_id;
+        let cancel = request.cancel.clone();
         let model = request.config.model.clone();
+        let tools = request.tools.clone().filter(|tools| !tools.is_empty());
+
+        let mut answer = ResponsesAnswer::default();
+        let mut rounds = 0;
+        let interrupted = loop {
+            if tools.is_some() {
+                // Out of tool rounds: answer from what the tools returned.
+                payload.allow_tool_calls(rounds < request.max_tool_rounds);
+            }
+            let round = self
+                .stream_responses_round(&url, &headers, &payload, &request, &mut answer, &stream_tx)
+                .await?;
+            // `finished` is set by `completed`, `incomplete` or `failed`; a
+            // stream that ends without one was cut off, e.g. by a dropped
+            // connection or a proxy.
+            if !round.finished && !cancel.is_cancelled() {
+                log::warn!("response stream for {model} ended before the response finished");
+            }
+            if round.incomplete || !round.finished {
+                break true;
+            }
 
-        let res = self
-            .http
-            .post(url)
-            .headers(headers)
-            .json(&payload)
-            .send()
-            .await
-            .context("failed to send responses API request")?;
+            let Some(tools) = tools.as_ref() else {
+                break false;
+            };
+            if round.function_calls.is_empty() {
+                break false;
+            }
+            if rounds >= request.max_tool_rounds {
+                log::warn!("model called tools after {rounds} rounds despite tool_choice none");
+                break false;
+            }
+            rounds += 1;
+
+            payload.push_function_calls(&round.text, &round.function_calls);
+            let mut images = Vec::new();
+            for call in round.function_calls {
+                let Some(output) = run_tool_call(
+                    &request,
+                    tools,
+                    call.name,
+                    call.arguments,
+                    &mut images,
+                    &stream_tx,
+                )
+                .await
+                else {
+                    break;
+                };
+                payload.push_function_output(call.call_id, output);
+            }
+            if cancel.is_cancelled() {
+                break true;
+            }
+            payload.push_images(&images);
+        };
 
-        if !res.status().is_success() {
-            let status = res.status();
-            let body = res.text().await.unwrap_or_default();
-            let error = format!("OpenAI Responses API failed with status {status}: {body}");
-            let _ = stream_tx.send((request_id, StreamEvent::Error(error.clone())));
-            return Err(anyhow!(error));
+        if !answer.reasoning.is_empty() {
+            let _ = stream_tx.send((request_id, StreamEvent::ReasoningDone(answer.reasoning)));
         }
+        let _ = stream_tx.send((request_id, StreamEvent::Done(answer.text.clone())));
 
-        let mut stream = res.bytes_stream();
-        let mut answer_text = String::new();
-        let mut reasoning_text = String::new();
-        let mut buffer = String::new();
-        let mut current_event_type: Option<String> = None;
+        Ok(AnalyzeResponse {
+            request_id,
+            answer: answer.text,
+            model,
+            interrupted,
+            usage: answer.usage,
+            citations: answer.citations,
+        })
+    }
+}
 
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.context("failed to read chunk from responses stream")?;
-            let text = String::from_utf8_lossy(&chunk);
-            buffer.push_str(&text);
+/// Runs one tool call the model asked for, reporting it and its output on
+/// the stream. Images it captured are added to `images` for models that
+/// accept them. Returns `None` when the request was cancelled meanwhile.
+pub(crate) async fn run_tool_call(
+    request: &AnalyzeRequest,
+    tools: &ToolRegistry,
+    name: String,
+    arguments: String,
+    images: &mut Vec<PreparedImage>,
+    stream_tx: &UnboundedSender<(Uuid, StreamEvent)>,
+) -> Option<String> {
+    let request_id = request.request_id;
+    let _ = stream_tx.send((
+        request_id,
+        StreamEvent::ToolCall {
+            name: name.clone(),
+            arguments: arguments.clone(),
+        },
+    ));
+    let output = match request
+        .cancel
+        .run_until_cancelled(tools.call(&name, &arguments))
+        .await?
+    {
+        Ok(output) => {
+            if request.capabilities.vision {
+                images.extend(output.image);
+            }
+            output.text
+        }
+        Err(err) => format!("Error: {err:#}"),
+    };
+    let _ = stream_tx.send((
+        request_id,
+        StreamEvent::ToolResult {
+            name,
+            arguments,
+            output: output.clone(),
+        },
+    ));
+    Some(output)
+}
 
-            while let Some(line_end) = buffer.find('\n') {
-                let line = buffer[..line_end].trim().to_string();
-                buffer = buffer[line_end + 1..].to_string();
+#[async_trait]
+impl Provider for OpenAIClient {
+    async fn analyze_stream(
+        &self,
+        request: AnalyzeRequest,
+        stream_tx: UnboundedSender<(Uuid, StreamEvent)>,
+    ) -> Result<AnalyzeResponse> {
+        let request_id = request.request_id;
+        let cancel = request.cancel.clone();
+        let model = request.config.model.clone();
 
-                if line.is_empty() {
-                    continue;
-                }
+        if request.capabilities.api == ApiFlavor::Responses {
+            return self.analyze_stream_responses_api(request, stream_tx).await;
+        }
 
-                if line.starts_with("event: ") {
-                    current_event_type = Some(line[7..].to_string());
-                    continue;
-                }
+        let url = endpoint_url(
+            &request.config,
+            CHAT_COMPLETIONS_PATH,
+            Some(&request.config.model),
+        )?;
+        let mut headers = auth_headers(&request.config)?;
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let mut payload = build_chat_payload(&request)?;
+        payload.stream = true;
+        if request.config.stream_usage() {
+            payload.stream_options = Some(StreamOptions {
+                include_usage: true,
+            });
+        }
+        let tools = request.tools.clone().filter(|tools| !tools.is_empty());
+        if let Some(tools) = &tools {
+            payload.tools = tools.definitions().into_iter().map(ChatTool::from).collect();
+        }
 
-                if !line.starts_with("data: ") {
-                    continue;
-                }
+        let mut full_text = String::new();
+        let mut usage: Option<TokenUsage> = None;
+        let mut rounds = 0;
+        let interrupted = loop {
+            if tools.is_some() {
+                // Out of tool rounds: answer from what the tools returned.
+                payload.tool_choice = (rounds >= request.max_tool_rounds).then_some("none");
+            }
+            let separate = !full_text.is_empty();
+            let round = self
+                .stream_chat_round(&url, &headers, &payload, &request, separate, &stream_tx)
+                .await?;
+            if let Some(round_usage) = round.usage {
+                *usage.get_or_insert_with(TokenUsage::default) += round_usage;
+            }
+            if !round.text.is_empty() && separate {
+                full_text.push_str("\n\n");
+            }
+            full_text.push_str(&round.text);
+            // A `finish_reason` or `[DONE]` ends every complete stream; one
+            // without either was cut off, e.g. by a dropped connection.
+            if !round.finished && !cancel.is_cancelled() {
+                log::warn!("chat completion stream for {model} ended before it finished");
+            }
+            if round.incomplete || !round.finished {
+                break true;
+            }
 
-                let data = &line[6..];
-                if data == "[DONE]" {
-                    break;
-                }
+            let Some(tools) = tools.as_ref() else {
+                break false;
+            };
+            if round.tool_calls.is_empty() {
+                break false;
+            }
+            if rounds >= request.max_tool_rounds {
+                log::warn!("model called tools after {rounds} rounds despite tool_choice none");
+                break false;
+            }
+            rounds += 1;
 
-                if let Some(ref event_type) = current_event_type {
-                    match event_type.as_str() {
-                        "response.output_text.delta" => {
-                            if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(data) {
-                                if let Some(delta) = parsed["delta"].as_str() {
-                                    answer_text.push_str(delta);
-                                    let _ = stream_tx.send((request_id, StreamEvent::Delta(delta.to_string())));
-                                }
-                            }
-                        }
-                        "response.reasoning_summary_text.delta" => {
-                            if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(data) {
-                                if let Some(delta) = parsed["delta"].as_str() {
-                                    reasoning_text.push_str(delta);
-                                    let _ = stream_tx.send((request_id, StreamEvent::ReasoningDelta(delta.to_string())));
-                                }
-                            }
-                        }
-                        "response.web_search_call.in_progress" => {
-                            let _ = stream_tx.send((request_id, StreamEvent::WebSearchInProgress));
-                        }
-                        "response.web_search_call.searching" => {
-                            let _ = stream_tx.send((request_id, StreamEvent::WebSearchSearching));
-                        }
-                        "response.web_search_call.completed" => {
-                            let _ = stream_tx.send((request_id, StreamEvent::WebSearchCompleted));
-                        }
-                        _ => {}
-                    }
-                }
+            let mut assistant = ChatMessage::new("assistant", Vec::new());
+            if !round.text.is_empty() {
+                assistant.content.push(MessageContent::text(round.text));
             }
-        }
+            assistant.tool_calls = Some(round.tool_calls.clone());
+            payload.messages.push(assistant);
+
+            let mut images = Vec::new();
+            for call in round.tool_calls {
+                let (name, arguments) = (call.function.name, call.function.arguments);
+                let Some(output) =
+                    run_tool_call(&request, tools, name, arguments, &mut images, &stream_tx).await
+                else {
+                    break;
+                };
+                let mut message = ChatMessage::new("tool", vec![MessageContent::text(output)]);
+                message.tool_call_id = Some(call.id);
+                payload.messages.push(message);
+            }
+            if cancel.is_cancelled() {
+                break true;
+            }
+            // Tool messages are text only, so captured images follow as a user turn.
+            if !images.is_empty() {
+                payload.messages.push(ChatMessage::new(
+                    "user",
+                    images.iter().map(MessageContent::attachment).collect(),
+                ));
+            }
+        };
 
-        if !reasoning_text.is_empty() {
-            let _ = stream_tx.send((request_id, StreamEvent::ReasoningDone(reasoning_text.clone())));
-        }
-        let _ = stream_tx.send((request_id, StreamEvent::Done(answer_text.clone())));
+        let _ = stream_tx.send((request_id, StreamEvent::Done(full_text.clone())));
 
         Ok(AnalyzeResponse {
             request_id,
-            answer: answer_text,
+            answer: full_text,
             model,
+            interrupted,
+            usage,
+            citations: Vec::new(),
         })
     }
 
-    pub async fn validate(&self, config: &OpenAIConfig) -> Result<bool> {
-        if config.api_key.trim().is_empty() {
-            return Ok(false);
+    async fn analyze(&self, request: AnalyzeRequest) -> Result<AnalyzeResponse> {
+        if request.capabilities.api == ApiFlavor::Responses {
+            return self.analyze_responses_api(request).await;
+        }
+
+        let url = endpoint_url(
+            &request.config,
+            CHAT_COMPLETIONS_PATH,
+            Some(&request.config.model),
+        )?;
+        let mut headers = auth_headers(&request.config)?;
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let payload = build_chat_payload(&request)?;
+
+        let timeouts = Timeouts::from(&request.config.timeouts);
+        let http = self.http.get(&timeouts)?;
+        let policy = RetryPolicy::from(&request.config.retry);
+        let res = policy
+            .send(
+                || timeouts.limit(http.post(&url).headers(headers.clone()).json(&payload)),
+                |_| {},
+            )
+            .await
+            .context("failed to send chat completion request")?;
+
+        if !res.status().is_success() {
+            return Err(ApiError::from_response(res).await.into());
         }
-        let url = build_endpoint(&config.base_url, "models")?;
-        let headers = auth_headers(&config.api_key)?;
-        let res = self
-            .http
-            .get(url)
-            .headers(headers)
+
+        let parsed: ChatCompletionResponse = res
+            .json()
+            .await
+            .context("failed to decode chat completion response")?;
+
+        let answer = parsed
+            .choices
+            .iter()
+            .find_map(|choice| choice.message.text())
+            .unwrap_or_else(|| "<empty response>".to_string());
+
+        Ok(AnalyzeResponse {
+            request_id: request.request_id,
+            answer,
+            model: parsed.model.unwrap_or(request.config.model),
+            interrupted: false,
+            usage: parsed.usage.map(TokenUsage::from),
+            citations: Vec::new(),
+        })
+    }
+
+    async fn list_models(&self, config: &OpenAIConfig) -> Result<Vec<String>> {
+        let url = endpoint_url(config, MODELS_PATH, None)?;
+        // Local OpenAI-compatible servers usually list models without a key.
+        let headers = endpoint_headers(config)?;
+        let timeouts = Timeouts::from(&config.timeouts);
+        let res = timeouts
+            .limit(self.http.get(&timeouts)?.get(url).headers(headers))
             .send()
             .await
+            .map_err(ApiError::network)
             .context("failed to send model list request")?;
-        Ok(res.status().is_success())
+
+        if !res.status().is_success() {
+            return Err(ApiError::from_response(res).await.into());
+        }
+
+        let parsed: ModelListResponse = res
+            .json()
+            .await
+            .context("failed to decode model list response")?;
+        let mut ids: Vec<String> = parsed.into_entries().into_iter().map(|model| model.id).collect();
+        ids.sort();
+        Ok(ids)
     }
 
-    pub async fn transcribe(
+    async fn transcribe(
         &self,
         config: &OpenAIConfig,
         recording: RecordingResult,
-        language: TranscriptionLanguage,
+        language: &TranscriptionLanguage,
         model: &str,
-    ) -> Result<String> {
-        let url = build_endpoint(&config.base_url, AUDIO_TRANSCRIPTIONS_PATH)?;
-        let headers = auth_headers(&config.api_key)?;
-        let file_part = Part::bytes(recording.wav_bytes)
-            .file_name(format!("recording-{}.wav", recording.sample_rate))
-            .mime_str("audio/wav")?;
-        let language_code = match language {
-            TranscriptionLanguage::En => "en",
-            TranscriptionLanguage::Zh => "zh",
+        prompt: Option<&str>,
+    ) -> Result<Transcription> {
+        let url = endpoint_url(config, AUDIO_TRANSCRIPTIONS_PATH, Some(model))?;
+        let headers = auth_headers(config)?;
+        // Multipart forms are consumed on send, so each attempt rebuilds one.
+        let build_form = || {
+            let file_part = Part::bytes(recording.wav_bytes.clone())
+                .file_name(format!("recording-{}.wav", recording.sample_rate))
+                .mime_str("audio/wav")
+                .expect("static MIME type is valid");
+            let mut form = Form::new()
+                .part("file", file_part)
+                .text("model", model.to_string())
+                .text("response_format", transcription_format(model));
+            if let Some(code) = language.code() {
+                form = form.text("language", code.to_string());
+            }
+            if let Some(prompt) = prompt.filter(|prompt| !prompt.trim().is_empty()) {
+                form = form.text("prompt", prompt.to_string());
+            }
+            form
         };
-        let form = Form::new()
-            .part("file", file_part)
-            .text("model", model.to_string())
-            .text("language", language_code.to_string());
-
-        let res = self
-            .http
-            .post(url)
-            .headers(headers)
-            .multipart(form)
-            .send()
+
+        let timeouts = Timeouts::from(&config.timeouts);
+        let http = self.http.get(&timeouts)?;
+        let policy = RetryPolicy::from(&config.retry);
+        let res = policy
+            .send(
+                || timeouts.limit(http.post(&url).headers(headers.clone()).multipart(build_form())),
+                |_| {},
+            )
             .await
             .context("failed to send transcription request")?;
 
         if !res.status().is_success() {
-            let status = res.status();
-            let body = res.text().await.unwrap_or_default();
-            return Err(anyhow!(
-                "transcription failed with status {status}: {body}",
-                status = status
-            ));
+            return Err(ApiError::from_response(res).await.into());
         }
 
         let parsed: TranscriptionResponse = res
             .json()
             .await
             .context("failed to parse transcription response")?;
-        Ok(parsed.text.unwrap_or_default())
+        Ok(parsed.into())
+    }
+
+    async fn synthesize_speech(
+        &self,
+        config: &OpenAIConfig,
+        settings: &SpeechSettings,
+        text: &str,
+    ) -> Result<Vec<u8>> {
+        let url = endpoint_url(config, AUDIO_SPEECH_PATH, Some(&settings.model))?;
+        let headers = auth_headers(config)?;
+        let payload = SpeechPayload {
+            model: &settings.model,
+            input: text,
+            voice: &settings.voice,
+            response_format: settings.format.as_str(),
+            speed: settings.speed.clamp(0.25, 4.0),
+        };
+
+        let timeouts = Timeouts::from(&config.timeouts);
+        let http = self.http.get(&timeouts)?;
+        let policy = RetryPolicy::from(&config.retry);
+        let res = policy
+            .send(
+                || timeouts.limit(http.post(&url).headers(headers.clone()).json(&payload)),
+                |_| {},
+            )
+            .await
+            .context("failed to send speech request")?;
+
+        if !res.status().is_success() {
+            return Err(ApiError::from_response(res).await.into());
+        }
+
+        let audio = res
+            .bytes()
+            .await
+            .context("failed to read speech audio")?;
+        Ok(audio.to_vec())
     }
 }
 
-fn build_endpoint(base: &str, path: &str) -> Result<String> {
+pub(crate) fn build_endpoint(base: &str, path: &str) -> Result<String> {
     let trimmed = base.trim_end_matches('/');
     Ok(format!("{trimmed}/{path}"))
 }
 
-fn auth_headers(api_key: &str) -> Result<HeaderMap> {
-    if api_key.trim().is_empty() {
+/// Request URL for `path` following `config.endpoint`: the URL template when
+/// the request targets a model, plus the `api-version` query parameter.
+pub(crate) fn endpoint_url(
+    config: &OpenAIConfig,
+    path: &str,
+    model: Option<&str>,
+) -> Result<String> {
+    let endpoint = &config.endpoint;
+    let base = config.base_url.trim().trim_end_matches('/');
+    let template = endpoint
+        .url_template
+        .as_deref()
+        .map(str::trim)
+        .filter(|template| !template.is_empty())
+        .filter(|template| model.is_some() || !template.contains("{model}"));
+    let url = match template {
+        Some(template) => template
+            .replace("{base_url}", base)
+            .replace("{model}", model.unwrap_or_default())
+            .replace("{path}", path),
+        None => build_endpoint(base, path)?,
+    };
+    let mut url = Url::parse(&url).with_context(|| format!("invalid endpoint URL {url}"))?;
+    if let Some(version) = endpoint
+        .api_version
+        .as_deref()
+        .map(str::trim)
+        .filter(|version| !version.is_empty())
+    {
+        url.query_pairs_mut().append_pair("api-version", version);
+    }
+    Ok(url.into())
+}
+
+/// Credentials and extra headers for `config`; fails without an API key
+/// unless the endpoint authenticates some other way.
+pub(crate) fn auth_headers(config: &OpenAIConfig) -> Result<HeaderMap> {
+    if !config.has_credentials() {
         anyhow::bail!("missing OpenAI API key");
     }
+    endpoint_headers(config)
+}
+
+/// Like [`auth_headers`], but sends no credentials when the key is empty.
+pub(crate) fn endpoint_headers(config: &OpenAIConfig) -> Result<HeaderMap> {
     let mut headers = HeaderMap::new();
-    let bearer = format!("Bearer {api_key}");
-    headers.insert(
-        AUTHORIZATION,
-        HeaderValue::from_str(&bearer).context("invalid API key for Authorization header")?,
-    );
+    for (name, value) in &config.endpoint.headers {
+        if name.trim().is_empty() {
+            continue;
+        }
+        headers.insert(
+            HeaderName::from_bytes(name.trim().as_bytes())
+                .with_context(|| format!("invalid header name {name:?}"))?,
+            HeaderValue::from_str(value.trim())
+                .with_context(|| format!("invalid value for header {name}"))?,
+        );
+    }
+    let api_key = config.api_key.trim();
+    if api_key.is_empty() {
+        return Ok(headers);
+    }
+    match config.endpoint.auth {
+        AuthScheme::Bearer => {
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {api_key}"))
+                    .context("invalid API key for Authorization header")?,
+            );
+        }
+        AuthScheme::ApiKey => {
+            let name = config.endpoint.api_key_header.trim();
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| format!("invalid API key header name {name:?}"))?,
+                HeaderValue::from_str(api_key).context("invalid API key for header")?,
+            );
+        }
+        AuthScheme::None => {}
+    }
     Ok(headers)
 }
 
@@ -380,56 +826,43 @@ fn build_chat_payload(request: &AnalyzeRequest) -> Result<ChatCompletionPayload>
 
     if let Some(prompt) = request.custom_prompt.as_ref() {
         if !prompt.trim().is_empty() {
-            messages.push(ChatMessage {
-                role: "system".into(),
-                content: vec![MessageContent::Text(TextContent {
-                    text: prompt.trim().to_string(),
-                })],
-            });
+            messages.push(ChatMessage::new(
+                "system",
+                vec![MessageContent::text(prompt.trim())],
+            ));
         }
     }
 
-    for entry in request.history.iter().rev().take(20).rev() {
+    for entry in budget_history(&PromptParts::from_request(request)).entries {
         let role = match entry.role {
             ConversationRole::System => "system",
             ConversationRole::User => "user",
             ConversationRole::Assistant | ConversationRole::Reasoning => "assistant",
             ConversationRole::Error => "system",
+            // Tool results only make sense next to the call that produced them.
+            ConversationRole::Tool => continue,
         };
-        messages.push(ChatMessage {
-            role: role.to_string(),
-            content: vec![MessageContent::Text(TextContent {
-                text: entry.content.clone(),
-            })],
-        });
-    }
-
-    let mut user_content = vec![MessageContent::Text(TextContent {
-        text: request.text_prompt.trim().to_string(),
-    })];
-
-    if let Some(png) = request.screenshot_png.as_ref() {
-        let base64 = general_purpose::STANDARD.encode(png);
-        let data_url = format!("data:image/png;base64,{base64}");
-        user_content.push(MessageContent::Image(ImageContent {
-            image_url: ImageUrl {
-                url: data_url,
-                detail: Some("auto".to_string()),
-            },
-        }));
+        messages.push(ChatMessage::new(
+            role,
+            vec![MessageContent::text(entry.content.clone())],
+        ));
     }
 
-    messages.push(ChatMessage {
-        role: "user".into(),
-        content: user_content,
-    });
+    messages.push(ChatMessage::new("user", user_content(request)));
 
+    let capabilities = &request.capabilities;
+    let max_tokens = capabilities.clamp_output_tokens(request.config.max_output_tokens);
     Ok(ChatCompletionPayload {
         model: request.config.model.clone(),
         messages,
-        temperature: request.config.temperature,
-        max_tokens: request.config.max_output_tokens,
+        // Reasoning models reject sampling parameters and the legacy limit.
+        temperature: (!capabilities.reasoning).then_some(request.config.temperature),
+        max_tokens: max_tokens.filter(|_| !capabilities.reasoning),
+        max_completion_tokens: max_tokens.filter(|_| capabilities.reasoning),
         stream: false,
+        stream_options: None,
+        tools: Vec::new(),
+        tool_choice: None,
     })
 }
 
@@ -439,15 +872,46 @@ struct ChatCompletionPayload {
     messages: Vec<ChatMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
     max_tokens: Option<u32>,
-    temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
     #[serde(default)]
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ChatTool>,
+    /// `none` asks for a final answer once the tool round limit is reached.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+struct StreamOptions {
+    include_usage: bool,
 }
 
 #[derive(Debug, Serialize)]
 struct ChatMessage {
     role: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     content: Vec<MessageContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<Vec<ChatToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    fn new(role: &str, content: Vec<MessageContent>) -> Self {
+        Self {
+            role: role.to_string(),
+            content,
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -459,6 +923,25 @@ enum MessageContent {
     Image(ImageContent),
 }
 
+impl MessageContent {
+    fn text(text: impl Into<String>) -> Self {
+        MessageContent::Text(TextContent { text: text.into() })
+    }
+
+    fn attachment(image: &PreparedImage) -> Self {
+        Self::image(image.mime_type(), &image.bytes, image.detail)
+    }
+
+    fn image(mime_type: &str, bytes: &[u8], detail: ImageDetail) -> Self {
+        MessageContent::Image(ImageContent {
+            image_url: ImageUrl {
+                url: data_url(mime_type, bytes),
+                detail: Some(detail.as_str().to_string()),
+            },
+        })
+    }
+}
+
 #[derive(Debug, Serialize)]
 struct TextContent {
     text: String,
@@ -476,11 +959,156 @@ struct ImageUrl {
     detail: Option<String>,
 }
 
+#[derive(Debug, Serialize)]
+struct ChatTool {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: ChatFunction,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatFunction {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+}
+
+impl From<ToolDefinition> for ChatTool {
+    fn from(definition: ToolDefinition) -> Self {
+        Self {
+            kind: "function",
+            function: ChatFunction {
+                name: definition.name,
+                description: definition.description,
+                parameters: definition.parameters,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct ChatToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: ChatToolCallFunction,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+struct ChatToolCallFunction {
+    name: String,
+    arguments: String,
+}
+
+/// What one Responses API round produced besides the streamed answer.
+#[derive(Debug, Default)]
+struct ResponsesRound {
+    text: String,
+    function_calls: Vec<FunctionCall>,
+    /// Ended early, e.g. at `max_output_tokens`.
+    incomplete: bool,
+    /// A terminal event arrived.
+    finished: bool,
+}
+
+/// The answer built up over the rounds of a Responses API request.
+#[derive(Debug, Default)]
+struct ResponsesAnswer {
+    text: String,
+    reasoning: String,
+    /// Summed over tool-call rounds.
+    usage: Option<TokenUsage>,
+    citations: Vec<Citation>,
+}
+
+impl ResponsesAnswer {
+    fn add_usage(&mut self, usage: Option<ResponsesUsage>) {
+        if let Some(usage) = usage {
+            *self.usage.get_or_insert_with(TokenUsage::default) += TokenUsage::from(usage);
+        }
+    }
+}
+
+/// Output of one streamed Chat Completions response.
+#[derive(Debug, Default)]
+struct ChatRound {
+    text: String,
+    tool_calls: Vec<ChatToolCall>,
+    usage: Option<TokenUsage>,
+    /// Stopped at the output token limit.
+    incomplete: bool,
+    /// A `finish_reason` or `[DONE]` arrived.
+    finished: bool,
+}
+
+impl ChatRound {
+    /// Tool calls arrive in fragments keyed by `index`: the first carries the
+    /// id and name, later ones append to the JSON arguments.
+    fn merge_tool_call(&mut self, delta: ToolCallDelta) {
+        while self.tool_calls.len() <= delta.index {
+            self.tool_calls.push(ChatToolCall {
+                kind: "function",
+                ..ChatToolCall::default()
+            });
+        }
+        let call = &mut self.tool_calls[delta.index];
+        if let Some(id) = delta.id {
+            call.id = id;
+        }
+        if let Some(function) = delta.function {
+            if let Some(name) = function.name {
+                call.function.name.push_str(&name);
+            }
+            if let Some(arguments) = function.arguments {
+                call.function.arguments.push_str(&arguments);
+            }
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct ChatCompletionResponse {
-    pub id: Option<String>,
     pub model: Option<String>,
     pub choices: Vec<ChatCompletionChoice>,
+    #[serde(default)]
+    pub usage: Option<ChatUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    #[serde(default)]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+    #[serde(default)]
+    pub completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PromptTokensDetails {
+    #[serde(default)]
+    pub cached_tokens: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletionTokensDetails {
+    #[serde(default)]
+    pub reasoning_tokens: u64,
+}
+
+impl From<ChatUsage> for TokenUsage {
+    fn from(usage: ChatUsage) -> Self {
+        Self {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            reasoning_tokens: usage
+                .completion_tokens_details
+                .map_or(0, |details| details.reasoning_tokens),
+            cached_tokens: usage
+                .prompt_tokens_details
+                .map_or(0, |details| details.cached_tokens),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -490,15 +1118,27 @@ struct ChatCompletionChoice {
 
 #[derive(Debug, Deserialize)]
 struct ChatCompletionMessage {
-    pub content: Vec<ChatCompletionContent>,
+    #[serde(default)]
+    pub content: Option<ChatCompletionMessageContent>,
+}
+
+/// OpenAI returns a plain string; some compatible servers return parts.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ChatCompletionMessageContent {
+    Text(String),
+    Parts(Vec<ChatCompletionContent>),
 }
 
 impl ChatCompletionMessage {
     fn text(&self) -> Option<String> {
-        self.content.iter().find_map(|item| match item {
-            ChatCompletionContent::Text { text } => Some(text.clone()),
-            _ => None,
-        })
+        match self.content.as_ref()? {
+            ChatCompletionMessageContent::Text(text) => Some(text.clone()),
+            ChatCompletionMessageContent::Parts(parts) => parts.iter().find_map(|item| match item {
+                ChatCompletionContent::Text { text } => Some(text.clone()),
+                _ => None,
+            }),
+        }
     }
 }
 
@@ -511,113 +1151,340 @@ enum ChatCompletionContent {
     Other,
 }
 
+#[derive(Debug, Serialize)]
+struct SpeechPayload<'a> {
+    model: &'a str,
+    input: &'a str,
+    voice: &'a str,
+    response_format: &'a str,
+    speed: f32,
+}
+
 #[derive(Debug, Deserialize)]
 struct TranscriptionResponse {
     pub text: Option<String>,
+    /// The remaining fields only come with `verbose_json`.
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub segments: Vec<TranscriptSegment>,
+}
+
+impl From<TranscriptionResponse> for Transcription {
+    fn from(response: TranscriptionResponse) -> Self {
+        Self {
+            text: response.text.unwrap_or_default(),
+            language: response.language,
+            duration: response.duration,
+            segments: response.segments,
+        }
+    }
+}
+
+/// Whisper returns timed segments with `verbose_json`; the GPT-4o transcribe
+/// models only support `json` and `text`.
+fn transcription_format(model: &str) -> &'static str {
+    if model.starts_with("whisper") {
+        "verbose_json"
+    } else {
+        "json"
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ModelListResponse {
+    /// `{"object": "list", "data": [...]}` as served by OpenAI and most
+    /// compatible servers.
+    Wrapped { data: Vec<ModelListEntry> },
+    /// A bare array, as some proxies return.
+    Bare(Vec<ModelListEntry>),
+}
+
+impl ModelListResponse {
+    fn into_entries(self) -> Vec<ModelListEntry> {
+        match self {
+            Self::Wrapped { data } | Self::Bare(data) => data,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelListEntry {
+    pub id: String,
 }
 
 #[derive(Debug, Deserialize)]
 struct StreamChunk {
+    #[serde(default)]
     pub choices: Vec<StreamChoice>,
+    #[serde(default)]
+    pub usage: Option<ChatUsage>,
 }
 
 #[derive(Debug, Deserialize)]
 struct StreamChoice {
     pub delta: StreamDelta,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct StreamDelta {
     pub content: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
 }
 
-fn is_responses_api_model(model: &str) -> bool {
-    model.starts_with("gpt-5") || model.starts_with("o4-")
+#[derive(Debug, Clone, Deserialize)]
+struct ToolCallDelta {
+    pub index: usize,
+    pub id: Option<String>,
+    pub function: Option<FunctionDelta>,
 }
 
-fn build_responses_payload(request: &AnalyzeRequest) -> Result<ResponsesPayload> {
-    let mut messages: Vec<ChatMessage> = Vec::new();
+#[derive(Debug, Clone, Deserialize)]
+struct FunctionDelta {
+    pub name: Option<String>,
+    pub arguments: Option<String>,
+}
 
-    if let Some(prompt) = request.custom_prompt.as_ref() {
-        if !prompt.trim().is_empty() {
-            messages.push(ChatMessage {
-                role: "system".into(),
-                content: vec![MessageContent::Text(TextContent {
-                    text: prompt.trim().to_string(),
-                })],
-            });
+/// The question plus one part per attached image.
+fn user_content(request: &AnalyzeRequest) -> Vec<MessageContent> {
+    let mut content = vec![MessageContent::text(request.text_prompt.trim())];
+    content.extend(sendable_images(request).iter().map(MessageContent::attachment));
+    content
+}
+
+/// The attached images, or none for models without image input.
+pub(crate) fn sendable_images(request: &AnalyzeRequest) -> &[PreparedImage] {
+    if !request.capabilities.vision && !request.images.is_empty() {
+        log::warn!(
+            "{} does not accept images; sending the question without the attachments",
+            request.config.model
+        );
+        return &[];
+    }
+    &request.images
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EndpointSettings;
+
+    fn image(format: crate::config::UploadFormat) -> PreparedImage {
+        PreparedImage {
+            bytes: vec![1, 2, 3],
+            format,
+            width: 1,
+            height: 1,
+            detail: ImageDetail::Low,
         }
     }
 
-    for entry in request.history.iter().rev().take(20).rev() {
-        let role = match entry.role {
-            crate::session::ConversationRole::System => "system",
-            crate::session::ConversationRole::User => "user",
-            crate::session::ConversationRole::Assistant | crate::session::ConversationRole::Reasoning => "assistant",
-            crate::session::ConversationRole::Error => "system",
+    fn request(model: &str) -> AnalyzeRequest {
+        let config = OpenAIConfig {
+            model: model.to_string(),
+            ..OpenAIConfig::default()
         };
-        messages.push(ChatMessage {
-            role: role.to_string(),
-            content: vec![MessageContent::Text(TextContent {
-                text: entry.content.clone(),
-            })],
-        });
+        AnalyzeRequest {
+            request_id: Uuid::new_v4(),
+            provider: crate::config::ProviderKind::OpenAI,
+            capabilities: crate::models::capabilities(model, &Default::default()),
+            config,
+            text_prompt: "what is this?".into(),
+            custom_prompt: None,
+            images: vec![
+                image(crate::config::UploadFormat::Jpeg),
+                image(crate::config::UploadFormat::Png),
+            ],
+            history: Default::default(),
+            tools: None,
+            max_tool_rounds: 0,
+            cancel: Default::default(),
+        }
     }
 
-    let mut user_content = vec![MessageContent::Text(TextContent {
-        text: request.text_prompt.trim().to_string(),
-    })];
+    #[test]
+    fn chat_payload_follows_model_capabilities() {
+        let payload = serde_json::to_value(build_chat_payload(&request("gpt-4o")).unwrap()).unwrap();
+        assert_eq!(payload["max_tokens"], 2048);
+        assert!(payload.get("temperature").is_some());
+        let content = payload["messages"][0]["content"].as_array().unwrap();
+        assert_eq!(content.len(), 3);
+        assert_eq!(content[1]["image_url"]["url"], "data:image/jpeg;base64,AQID");
+        assert_eq!(content[1]["image_url"]["detail"], "low");
+        assert!(content[2]["image_url"]["url"]
+            .as_str()
+            .unwrap()
+            .starts_with("data:image/png;"));
+
+        let payload =
+            serde_json::to_value(build_chat_payload(&request("o3-mini")).unwrap()).unwrap();
+        assert_eq!(payload["max_completion_tokens"], 2048);
+        assert!(payload.get("max_tokens").is_none());
+        assert!(payload.get("temperature").is_none());
+        // o3-mini has no image input, so the attachments are left out.
+        assert_eq!(payload["messages"][0]["content"].as_array().unwrap().len(), 1);
+    }
 
-    if let Some(png) = request.screenshot_png.as_ref() {
-        let base64 = general_purpose::STANDARD.encode(png);
-        let data_url = format!("data:image/png;base64,{base64}");
-        user_content.push(MessageContent::Image(ImageContent {
-            image_url: ImageUrl {
-                url: data_url,
-                detail: Some("auto".to_string()),
-            },
-        }));
+    #[test]
+    fn merges_streamed_tool_call_fragments() {
+        let chunks = [
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"read_file","arguments":""}}]}}]}"#,
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"path\":"}}]}}]}"#,
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":1,"id":"call_2","function":{"name":"current_time","arguments":"{}"}}]}}]}"#,
+            r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"a.txt\"}"}}]}}]}"#,
+        ];
+        let mut round = ChatRound::default();
+        for chunk in chunks {
+            let chunk: StreamChunk = serde_json::from_str(chunk).unwrap();
+            for delta in chunk.choices[0].delta.tool_calls.clone().unwrap_or_default() {
+                round.merge_tool_call(delta);
+            }
+        }
+
+        assert_eq!(round.tool_calls.len(), 2);
+        assert_eq!(round.tool_calls[0].id, "call_1");
+        assert_eq!(round.tool_calls[0].function.name, "read_file");
+        assert_eq!(round.tool_calls[0].function.arguments, r#"{"path":"a.txt"}"#);
+        assert_eq!(round.tool_calls[1].function.name, "current_time");
     }
 
-    messages.push(ChatMessage {
-        role: "user".into(),
-        content: user_content,
-    });
+    #[test]
+    fn parses_wrapped_and_bare_model_lists() {
+        let wrapped: ModelListResponse = serde_json::from_str(
+            r#"{"object":"list","data":[{"id":"gpt-4o","object":"model","owned_by":"openai"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(wrapped.into_entries()[0].id, "gpt-4o");
+        let bare: ModelListResponse =
+            serde_json::from_str(r#"[{"id":"qwen2.5-7b-instruct"}]"#).unwrap();
+        assert_eq!(bare.into_entries()[0].id, "qwen2.5-7b-instruct");
+    }
 
-    let mut modalities = vec!["text".to_string()];
-    if request.config.model.starts_with("gpt-5") {
-        modalities.push("web_search".to_string());
+    #[test]
+    fn builds_azure_deployment_urls_and_headers() {
+        let mut config = OpenAIConfig {
+            api_key: "secret".into(),
+            base_url: "https://res.openai.azure.com/openai/".into(),
+            model: "my-gpt4o".into(),
+            ..OpenAIConfig::default()
+        };
+        assert_eq!(
+            endpoint_url(&config, CHAT_COMPLETIONS_PATH, Some("my-gpt4o")).unwrap(),
+            "https://res.openai.azure.com/openai/chat/completions"
+        );
+        assert_eq!(auth_headers(&config).unwrap()["authorization"], "Bearer secret");
+
+        config.endpoint.auth = AuthScheme::ApiKey;
+        config.endpoint.api_version = Some("2024-10-21".into());
+        config.endpoint.url_template = Some(EndpointSettings::AZURE_URL_TEMPLATE.into());
+        config
+            .endpoint
+            .headers
+            .push(("OpenAI-Project".into(), "proj_1".into()));
+        assert_eq!(
+            endpoint_url(&config, CHAT_COMPLETIONS_PATH, Some("my-gpt4o")).unwrap(),
+            "https://res.openai.azure.com/openai/deployments/my-gpt4o/chat/completions?api-version=2024-10-21"
+        );
+        // Listing models is not scoped to a deployment, and neither is the
+        // Responses API.
+        assert_eq!(
+            endpoint_url(&config, MODELS_PATH, None).unwrap(),
+            "https://res.openai.azure.com/openai/models?api-version=2024-10-21"
+        );
+        assert_eq!(
+            endpoint_url(&config, RESPONSES_PATH, None).unwrap(),
+            "https://res.openai.azure.com/openai/responses?api-version=2024-10-21"
+        );
+        let headers = auth_headers(&config).unwrap();
+        assert_eq!(headers["api-key"], "secret");
+        assert_eq!(headers["openai-project"], "proj_1");
+        assert!(!headers.contains_key(AUTHORIZATION));
+
+        config.api_key.clear();
+        assert!(auth_headers(&config).is_err());
+        config.endpoint.auth = AuthScheme::None;
+        assert_eq!(auth_headers(&config).unwrap().len(), 1);
     }
 
-    Ok(ResponsesPayload {
-        model: request.config.model.clone(),
-        messages,
-        temperature: request.config.temperature,
-        max_tokens: request.config.max_output_tokens,
-        modalities,
-        reasoning_effort: if request.config.model.starts_with("gpt-5") {
-            Some("high".to_string())
-        } else {
-            None
-        },
-        service_tier: if request.config.model.starts_with("gpt-5") {
-            Some("priority".to_string())
-        } else {
-            None
-        },
-    })
-}
+    #[test]
+    fn asks_only_openai_for_stream_usage() {
+        let mut config = OpenAIConfig::default();
+        assert!(config.stream_usage());
+        config.base_url = "http://localhost:11434/v1".into();
+        assert!(!config.stream_usage());
+        config.endpoint.stream_usage = Some(true);
+        assert!(config.stream_usage());
+
+        // A URL template means a gateway in front of the API.
+        let mut templated = OpenAIConfig::default();
+        templated.endpoint.url_template = Some("{base_url}/{model}/{path}".into());
+        assert!(!templated.stream_usage());
+    }
 
-#[derive(Debug, Serialize)]
-struct ResponsesPayload {
-    model: String,
-    messages: Vec<ChatMessage>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    max_tokens: Option<u32>,
-    temperature: f32,
-    modalities: Vec<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    reasoning_effort: Option<String>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    service_tier: Option<String>,
+    #[test]
+    fn parses_verbose_transcription_segments() {
+        let body = r#"{
+            "task": "transcribe",
+            "language": "english",
+            "duration": 4.2,
+            "text": "Deploy the canary. Then watch p99.",
+            "segments": [
+                {"id": 0, "seek": 0, "start": 0.0, "end": 1.8, "text": " Deploy the canary.", "avg_logprob": -0.2},
+                {"id": 1, "seek": 0, "start": 1.8, "end": 4.2, "text": " Then watch p99.", "avg_logprob": -0.3}
+            ]
+        }"#;
+        let transcription: Transcription =
+            serde_json::from_str::<TranscriptionResponse>(body).unwrap().into();
+        assert_eq!(transcription.language.as_deref(), Some("english"));
+        assert_eq!(transcription.segments.len(), 2);
+        assert_eq!(transcription.segments[1].start, 1.8);
+        assert_eq!(transcription.segments[1].text, " Then watch p99.");
+
+        let plain: Transcription = serde_json::from_str::<TranscriptionResponse>(r#"{"text":"hi"}"#)
+            .unwrap()
+            .into();
+        assert_eq!(plain.text, "hi");
+        assert!(plain.segments.is_empty());
+    }
+
+    #[test]
+    fn parses_string_and_array_message_content() {
+        let plain: ChatCompletionMessage =
+            serde_json::from_str(r#"{"role":"assistant","content":"hi"}"#).unwrap();
+        assert_eq!(plain.text().as_deref(), Some("hi"));
+        let parts: ChatCompletionMessage =
+            serde_json::from_str(r#"{"content":[{"type":"text","text":"hey"}]}"#).unwrap();
+        assert_eq!(parts.text().as_deref(), Some("hey"));
+        let empty: ChatCompletionMessage = serde_json::from_str(r#"{"content":null}"#).unwrap();
+        assert_eq!(empty.text(), None);
+    }
+
+    #[test]
+    fn serializes_tool_call_messages() {
+        let mut assistant = ChatMessage::new("assistant", Vec::new());
+        assistant.tool_calls = Some(vec![ChatToolCall {
+            id: "call_1".into(),
+            kind: "function",
+            function: ChatToolCallFunction {
+                name: "current_time".into(),
+                arguments: "{}".into(),
+            },
+        }]);
+        let mut tool = ChatMessage::new("tool", vec![MessageContent::text("12:00")]);
+        tool.tool_call_id = Some("call_1".into());
+
+        let assistant = serde_json::to_value(&assistant).unwrap();
+        assert!(assistant.get("content").is_none());
+        assert_eq!(assistant["tool_calls"][0]["type"], "function");
+        assert_eq!(assistant["tool_calls"][0]["function"]["name"], "current_time");
+        let tool = serde_json::to_value(&tool).unwrap();
+        assert_eq!(tool["tool_call_id"], "call_1");
+        assert_eq!(tool["content"][0]["text"], "12:00");
+    }
 }
@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::anthropic::AnthropicClient;
+use crate::audio::RecordingResult;
+use crate::capture::PreparedImage;
+use crate::config::{OpenAIConfig, ProviderKind, SpeechSettings, TranscriptionLanguage};
+use crate::models::ModelCapabilities;
+use crate::openai::OpenAIClient;
+use crate::retry::RetryNotice;
+use crate::session::{Citation, ConversationEntry};
+use crate::tools::ToolRegistry;
+use crate::usage::TokenUsage;
+
+/// Error message returned when a request is cancelled before streaming starts.
+pub const REQUEST_CANCELLED: &str = "request cancelled";
+
+#[derive(Debug, Clone)]
+pub struct AnalyzeRequest {
+    pub request_id: Uuid,
+    pub provider: ProviderKind,
+    pub config: OpenAIConfig,
+    /// What `config.model` supports, resolved from the model registry.
+    pub capabilities: ModelCapabilities,
+    pub text_prompt: String,
+    pub custom_prompt: Option<String>,
+    /// Attached images, already scaled and encoded for upload, in the
+    /// order they are sent.
+    pub images: Vec<PreparedImage>,
+    pub history: VecDeque<ConversationEntry>,
+    /// Tools the model may call; `None` disables function calling.
+    pub tools: Option<Arc<ToolRegistry>>,
+    /// Maximum model/tool round trips before the answer is cut off.
+    pub max_tool_rounds: u32,
+    /// Cancelling stops the stream and returns the partial answer.
+    pub cancel: CancellationToken,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnalyzeResponse {
+    pub request_id: Uuid,
+    pub answer: String,
+    pub model: String,
+    /// True when the answer is incomplete: stopped by the user, cut off at
+    /// the output token limit, or a stream that ended before its terminal
+    /// event.
+    pub interrupted: bool,
+    /// Tokens billed for the answer, summed over tool-call rounds.
+    pub usage: Option<TokenUsage>,
+    /// Web search sources, also streamed as [`StreamEvent::Citation`]. They
+    /// may repeat; [`ConversationEntry::add_citation`] drops duplicates.
+    pub citations: Vec<Citation>,
+}
+
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    Delta(String),
+    ReasoningDelta(String),
+    Done(String),
+    ReasoningDone(String),
+    WebSearchInProgress,
+    WebSearchSearching,
+    WebSearchCompleted,
+    /// A source the answer cites, possibly repeated.
+    Citation(Citation),
+    /// The model asked for a tool call, which is now running.
+    ToolCall {
+        name: String,
+        arguments: String,
+    },
+    /// A tool call finished; `output` is what was sent back to the model.
+    ToolResult {
+        name: String,
+        arguments: String,
+        output: String,
+    },
+    /// The request failed before streaming started and will be retried.
+    Retrying(RetryNotice),
+    Error(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Transcription {
+    pub text: String,
+    /// Detected language as reported by the API (a name such as `english`).
+    pub language: Option<String>,
+    /// Audio length in seconds.
+    pub duration: Option<f64>,
+    /// Timed segments; empty when the model only returns plain text.
+    pub segments: Vec<TranscriptSegment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TranscriptSegment {
+    /// Seconds from the start of the recording.
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+/// A vendor backend able to answer questions about the conversation.
+///
+/// Implementations are stateless apart from their HTTP client; everything
+/// request specific (credentials, model, sampling) travels in the arguments.
+#[async_trait]
+pub trait Provider: Send + Sync {
+    async fn analyze_stream(
+        &self,
+        request: AnalyzeRequest,
+        stream_tx: UnboundedSender<(Uuid, StreamEvent)>,
+    ) -> Result<AnalyzeResponse>;
+
+    async fn analyze(&self, request: AnalyzeRequest) -> Result<AnalyzeResponse>;
+
+    /// Model ids served by the endpoint; also serves as the credential check.
+    async fn list_models(&self, config: &OpenAIConfig) -> Result<Vec<String>>;
+
+    /// Transcribes a finished recording. `prompt` carries vocabulary that
+    /// should be spelled as given.
+    async fn transcribe(
+        &self,
+        config: &OpenAIConfig,
+        recording: RecordingResult,
+        language: &TranscriptionLanguage,
+        model: &str,
+        prompt: Option<&str>,
+    ) -> Result<Transcription>;
+
+    /// Reads `text` aloud, returning audio encoded as `settings.format`.
+    async fn synthesize_speech(
+        &self,
+        config: &OpenAIConfig,
+        settings: &SpeechSettings,
+        text: &str,
+    ) -> Result<Vec<u8>>;
+}
+
+/// One shared client per supported vendor, looked up per request.
+#[derive(Clone)]
+pub struct Providers {
+    openai: Arc<OpenAIClient>,
+    anthropic: Arc<AnthropicClient>,
+}
+
+impl Providers {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            openai: Arc::new(OpenAIClient::new()?),
+            anthropic: Arc::new(AnthropicClient::new()?),
+        })
+    }
+
+    pub fn get(&self, kind: ProviderKind) -> Arc<dyn Provider> {
+        match kind {
+            ProviderKind::OpenAI => self.openai.clone(),
+            ProviderKind::Anthropic => self.anthropic.clone(),
+        }
+    }
+}
@@ -0,0 +1,313 @@
+//! Live transcription over the OpenAI Realtime WebSocket API.
+//!
+//! Microphone blocks are resampled to 24 kHz PCM16 and appended to the
+//! server's input buffer as they arrive. Server-side voice activity detection
+//! splits the speech into utterances, each producing partial deltas and a
+//! final transcript. When the audio channel closes the remaining buffer is
+//! committed and the session waits for the last transcripts before closing.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose, Engine as _};
+use futures::{SinkExt, StreamExt};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::time::{sleep_until, Instant};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::audio::{to_pcm16, Resampler};
+use crate::config::{OpenAIConfig, TranscriptionSettings};
+use crate::openai;
+
+/// Sample rate of `pcm16` audio in the Realtime API.
+pub const REALTIME_SAMPLE_RATE: u32 = 24_000;
+/// How long to wait for the final transcripts after the audio ends.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+const COMMIT_EMPTY: &str = "input_audio_buffer_commit_empty";
+const REALTIME_PATH: &str = "realtime";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscriptEvent {
+    /// More text for the utterance `item_id`.
+    Delta { item_id: String, text: String },
+    /// Final text for the utterance `item_id`; supersedes its deltas.
+    Completed { item_id: String, text: String },
+    /// The server rejected something; the session keeps going.
+    Error(String),
+}
+
+/// Streams audio from `audio` (mono, `input_sample_rate`) until the channel
+/// closes, forwarding transcript events to `events` as they arrive. `prompt`
+/// biases the spelling of domain terms. Returns the final transcripts of all
+/// utterances joined in the order they were spoken, which is not necessarily
+/// the order their transcripts complete in.
+pub async fn stream_transcription(
+    config: &OpenAIConfig,
+    settings: &TranscriptionSettings,
+    prompt: Option<&str>,
+    input_sample_rate: u32,
+    mut audio: UnboundedReceiver<Vec<f32>>,
+    events: UnboundedSender<TranscriptEvent>,
+) -> Result<String> {
+    let mut request = realtime_url(config)?
+        .into_client_request()
+        .context("invalid realtime URL")?;
+    let headers = request.headers_mut();
+    headers.extend(openai::endpoint_headers(config)?);
+    headers.insert("OpenAI-Beta", HeaderValue::from_static("realtime=v1"));
+
+    let (socket, _) = tokio_tungstenite::connect_async(request)
+        .await
+        .context("failed to connect to the realtime API")?;
+    let (mut outgoing, mut incoming) = socket.split();
+
+    let mut transcription = json!({ "model": settings.model });
+    if let Some(code) = settings.language.code() {
+        transcription["language"] = json!(code);
+    }
+    if let Some(prompt) = prompt.filter(|prompt| !prompt.trim().is_empty()) {
+        transcription["prompt"] = json!(prompt);
+    }
+    let session_update = json!({
+        "type": "transcription_session.update",
+        "session": {
+            "input_audio_format": "pcm16",
+            "input_audio_transcription": transcription,
+            "turn_detection": { "type": "server_vad", "silence_duration_ms": 500 },
+        },
+    });
+    outgoing
+        .send(Message::text(session_update.to_string()))
+        .await
+        .context("failed to configure the transcription session")?;
+
+    let mut resampler = Resampler::new(input_sample_rate, REALTIME_SAMPLE_RATE);
+    let mut transcript = Utterances::default();
+    // Utterances committed but not yet transcribed.
+    let mut pending: HashSet<String> = HashSet::new();
+    let mut drain_deadline: Option<Instant> = None;
+    let mut awaiting_commit = false;
+
+    loop {
+        if drain_deadline.is_some() && !awaiting_commit && pending.is_empty() {
+            break;
+        }
+        tokio::select! {
+            block = audio.recv(), if drain_deadline.is_none() => match block {
+                Some(samples) => {
+                    let pcm = to_pcm16(&resampler.process(&samples));
+                    if pcm.is_empty() {
+                        continue;
+                    }
+                    let append = json!({
+                        "type": "input_audio_buffer.append",
+                        "audio": general_purpose::STANDARD.encode(pcm),
+                    });
+                    outgoing
+                        .send(Message::text(append.to_string()))
+                        .await
+                        .context("failed to stream audio")?;
+                }
+                None => {
+                    let commit = json!({ "type": "input_audio_buffer.commit" });
+                    outgoing
+                        .send(Message::text(commit.to_string()))
+                        .await
+                        .context("failed to commit audio")?;
+                    awaiting_commit = true;
+                    drain_deadline = Some(Instant::now() + DRAIN_TIMEOUT);
+                }
+            },
+            message = incoming.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => return Err(anyhow!(err).context("realtime connection failed")),
+                };
+                let event = match serde_json::from_str::<ServerEvent>(text.as_str()) {
+                    Ok(event) => event,
+                    Err(err) => {
+                        log::debug!("ignoring unparsable realtime event: {err}");
+                        continue;
+                    }
+                };
+                match event {
+                    ServerEvent::Committed { item_id, previous_item_id } => {
+                        transcript.commit(&item_id, previous_item_id.as_deref());
+                        pending.insert(item_id);
+                        awaiting_commit = false;
+                    }
+                    ServerEvent::Delta { item_id, delta } => {
+                        let _ = events.send(TranscriptEvent::Delta { item_id, text: delta });
+                    }
+                    ServerEvent::Completed { item_id, transcript: text } => {
+                        pending.remove(&item_id);
+                        transcript.complete(&item_id, &text);
+                        let _ = events.send(TranscriptEvent::Completed { item_id, text });
+                    }
+                    ServerEvent::Failed { item_id, error } => {
+                        pending.remove(&item_id);
+                        let _ = events.send(TranscriptEvent::Error(error.message));
+                    }
+                    ServerEvent::Error { error } => {
+                        // Server VAD may already have committed everything.
+                        if awaiting_commit && error.code.as_deref() == Some(COMMIT_EMPTY) {
+                            awaiting_commit = false;
+                            continue;
+                        }
+                        awaiting_commit = false;
+                        let _ = events.send(TranscriptEvent::Error(error.message));
+                    }
+                    ServerEvent::Other => {}
+                }
+            },
+            _ = sleep_until(drain_deadline.unwrap_or_else(Instant::now)), if drain_deadline.is_some() => {
+                log::warn!("gave up waiting for {} pending transcripts", pending.len());
+                break;
+            }
+        }
+    }
+
+    let _ = outgoing.send(Message::Close(None)).await;
+    Ok(transcript.join())
+}
+
+/// Final transcripts kept in commit order, following each committed item's
+/// `previous_item_id`.
+#[derive(Debug, Default)]
+struct Utterances {
+    order: Vec<String>,
+    text: HashMap<String, String>,
+}
+
+impl Utterances {
+    fn commit(&mut self, item_id: &str, previous_item_id: Option<&str>) {
+        if self.order.iter().any(|known| known == item_id) {
+            return;
+        }
+        let index = previous_item_id
+            .and_then(|previous| self.order.iter().position(|known| known == previous))
+            .map_or(self.order.len(), |index| index + 1);
+        self.order.insert(index, item_id.to_string());
+    }
+
+    fn complete(&mut self, item_id: &str, text: &str) {
+        // A transcript may arrive for an item whose commit was missed.
+        self.commit(item_id, None);
+        self.text
+            .insert(item_id.to_string(), text.trim().to_string());
+    }
+
+    fn join(&self) -> String {
+        self.order
+            .iter()
+            .filter_map(|item_id| self.text.get(item_id))
+            .filter(|text| !text.is_empty())
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// `https://host/v1` becomes `wss://host/v1/realtime?intent=transcription`,
+/// keeping the endpoint's `api-version`.
+fn realtime_url(config: &OpenAIConfig) -> Result<String> {
+    let mut url = Url::parse(&openai::endpoint_url(config, REALTIME_PATH, None)?)?;
+    let scheme = match url.scheme() {
+        "https" | "wss" => "wss",
+        "http" | "ws" => "ws",
+        _ => bail!(
+            "unsupported base URL for realtime transcription: {}",
+            config.base_url
+        ),
+    };
+    url.set_scheme(scheme)
+        .map_err(|()| anyhow!("cannot use {scheme} with {}", config.base_url))?;
+    url.query_pairs_mut().append_pair("intent", "transcription");
+    Ok(url.into())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum ServerEvent {
+    #[serde(rename = "input_audio_buffer.committed")]
+    Committed {
+        item_id: String,
+        #[serde(default)]
+        previous_item_id: Option<String>,
+    },
+    #[serde(rename = "conversation.item.input_audio_transcription.delta")]
+    Delta { item_id: String, delta: String },
+    #[serde(rename = "conversation.item.input_audio_transcription.completed")]
+    Completed { item_id: String, transcript: String },
+    #[serde(rename = "conversation.item.input_audio_transcription.failed")]
+    Failed { item_id: String, error: ServerError },
+    #[serde(rename = "error")]
+    Error { error: ServerError },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerError {
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(base_url: &str) -> OpenAIConfig {
+        OpenAIConfig {
+            base_url: base_url.into(),
+            ..OpenAIConfig::default()
+        }
+    }
+
+    #[test]
+    fn joins_transcripts_in_commit_order() {
+        let mut utterances = Utterances::default();
+        utterances.commit("a", None);
+        utterances.commit("b", Some("a"));
+        utterances.commit("c", Some("b"));
+        utterances.complete("c", " third ");
+        utterances.complete("a", "first");
+        utterances.complete("b", "");
+        assert_eq!(utterances.join(), "first third");
+
+        // An item goes right after its `previous_item_id`, wherever that is.
+        utterances.commit("d", Some("a"));
+        utterances.complete("d", "second");
+        assert_eq!(utterances.join(), "first second third");
+    }
+
+    #[test]
+    fn derives_websocket_url_from_base_url() {
+        assert_eq!(
+            realtime_url(&config("https://api.openai.com/v1/")).unwrap(),
+            "wss://api.openai.com/v1/realtime?intent=transcription"
+        );
+        assert_eq!(
+            realtime_url(&config("http://127.0.0.1:8080/v1")).unwrap(),
+            "ws://127.0.0.1:8080/v1/realtime?intent=transcription"
+        );
+        assert!(realtime_url(&config("ftp://example.com")).is_err());
+
+        let mut azure = config("https://res.openai.azure.com/openai");
+        azure.endpoint.api_version = Some("2025-04-01-preview".into());
+        assert_eq!(
+            realtime_url(&azure).unwrap(),
+            "wss://res.openai.azure.com/openai/realtime?api-version=2025-04-01-preview&intent=transcription"
+        );
+    }
+}
@@ -0,0 +1,600 @@
+//! Request and stream event types of the OpenAI Responses API
+//! (`POST /responses`), used for models whose capabilities select it.
+
+use serde::{Deserialize, Serialize};
+
+use crate::capture::PreparedImage;
+use crate::config::{ReasoningEffort, ServiceTier, Verbosity};
+use crate::openai::sendable_images;
+use crate::provider::AnalyzeRequest;
+use crate::session::{Citation, ConversationRole};
+use crate::tokens::{budget_history, PromptParts};
+use crate::usage::TokenUsage;
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ResponsesPayload {
+    model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instructions: Option<String>,
+    input: Vec<InputItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<Tool>,
+    /// `none` asks for a final answer once the tool round limit is reached.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning: Option<Reasoning>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<TextOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    service_tier: Option<&'static str>,
+    pub(crate) stream: bool,
+    /// The conversation lives in the local session, not on the server.
+    store: bool,
+}
+
+/// An item of the `input` list: a message, or a tool call and its result
+/// replayed from an earlier round of the same answer.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum InputItem {
+    Message {
+        role: &'static str,
+        content: Vec<InputContent>,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        arguments: String,
+    },
+    FunctionCallOutput {
+        call_id: String,
+        output: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum InputContent {
+    InputText {
+        text: String,
+    },
+    InputImage {
+        image_url: String,
+        detail: &'static str,
+    },
+    /// Earlier assistant turns are replayed as output.
+    OutputText {
+        text: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Tool {
+    WebSearch,
+    Function {
+        name: String,
+        description: String,
+        parameters: serde_json::Value,
+        /// Strict mode would reject the free-form schemas of MCP tools.
+        strict: bool,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct TextOptions {
+    verbosity: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct Reasoning {
+    effort: &'static str,
+    /// Asks for the summary streamed as `reasoning_summary_text` deltas.
+    summary: &'static str,
+}
+
+pub(crate) fn build_responses_payload(request: &AnalyzeRequest) -> ResponsesPayload {
+    let mut input = Vec::new();
+    for entry in budget_history(&PromptParts::from_request(request)).entries {
+        let (role, content) = match entry.role {
+            ConversationRole::System | ConversationRole::Error => (
+                "system",
+                InputContent::InputText {
+                    text: entry.content.clone(),
+                },
+            ),
+            ConversationRole::User => (
+                "user",
+                InputContent::InputText {
+                    text: entry.content.clone(),
+                },
+            ),
+            ConversationRole::Assistant | ConversationRole::Reasoning => (
+                "assistant",
+                InputContent::OutputText {
+                    text: entry.content.clone(),
+                },
+            ),
+            // Tool results only make sense next to the call that produced them.
+            ConversationRole::Tool => continue,
+        };
+        input.push(InputItem::Message {
+            role,
+            content: vec![content],
+        });
+    }
+
+    let mut question = vec![InputContent::InputText {
+        text: request.text_prompt.trim().to_string(),
+    }];
+    question.extend(
+        sendable_images(request)
+            .iter()
+            .map(|image| InputContent::InputImage {
+                image_url: image.data_url(),
+                detail: image.detail.as_str(),
+            }),
+    );
+    input.push(InputItem::Message {
+        role: "user",
+        content: question,
+    });
+
+    let capabilities = &request.capabilities;
+    let options = &request.config.responses;
+    // Models without minimal effort get the lowest level they accept.
+    let effort = match options.effort {
+        ReasoningEffort::Minimal if !capabilities.minimal_effort => ReasoningEffort::Low,
+        effort => effort,
+    };
+    // Minimal effort rejects the web search tool.
+    let minimal = capabilities.reasoning && effort == ReasoningEffort::Minimal;
+    let web_search = capabilities.web_search && options.web_search && !minimal;
+    let mut tools = Vec::new();
+    if web_search {
+        tools.push(Tool::WebSearch);
+    }
+    if let Some(registry) = &request.tools {
+        tools.extend(
+            registry
+                .definitions()
+                .into_iter()
+                .map(|definition| Tool::Function {
+                    name: definition.name,
+                    description: definition.description,
+                    parameters: definition.parameters,
+                    strict: false,
+                }),
+        );
+    }
+    let service_tier = match options.service_tier {
+        ServiceTier::Auto => None,
+        ServiceTier::Priority if !capabilities.priority_tier => None,
+        tier => Some(tier.as_str()),
+    };
+    ResponsesPayload {
+        model: request.config.model.clone(),
+        instructions: request
+            .custom_prompt
+            .as_deref()
+            .map(str::trim)
+            .filter(|prompt| !prompt.is_empty())
+            .map(str::to_string),
+        input,
+        max_output_tokens: capabilities.clamp_output_tokens(request.config.max_output_tokens),
+        temperature: (!capabilities.reasoning).then_some(request.config.temperature),
+        tools,
+        tool_choice: None,
+        reasoning: capabilities.reasoning.then_some(Reasoning {
+            effort: effort.as_str(),
+            summary: "auto",
+        }),
+        // Medium is the default, and the only value models before gpt-5 accept.
+        text: (capabilities.verbosity && options.verbosity != Verbosity::Medium).then_some(
+            TextOptions {
+                verbosity: options.verbosity.as_str(),
+            },
+        ),
+        service_tier,
+        stream: true,
+        store: false,
+    }
+}
+
+impl ResponsesPayload {
+    /// Replays a round that ended in tool calls: its text, then the calls.
+    pub(crate) fn push_function_calls(&mut self, text: &str, calls: &[FunctionCall]) {
+        if !text.is_empty() {
+            self.input.push(InputItem::Message {
+                role: "assistant",
+                content: vec![InputContent::OutputText {
+                    text: text.to_string(),
+                }],
+            });
+        }
+        self.input
+            .extend(calls.iter().cloned().map(|call| InputItem::FunctionCall {
+                call_id: call.call_id,
+                name: call.name,
+                arguments: call.arguments,
+            }));
+    }
+
+    pub(crate) fn push_function_output(&mut self, call_id: String, output: String) {
+        self.input
+            .push(InputItem::FunctionCallOutput { call_id, output });
+    }
+
+    /// Lets the model call tools, or keeps them declared, so earlier calls
+    /// stay valid, while asking for a plain answer.
+    pub(crate) fn allow_tool_calls(&mut self, allow: bool) {
+        self.tool_choice = (!allow).then_some("none");
+    }
+
+    /// Tool outputs are text only, so captured images follow as a user turn.
+    pub(crate) fn push_images(&mut self, images: &[PreparedImage]) {
+        if images.is_empty() {
+            return;
+        }
+        self.input.push(InputItem::Message {
+            role: "user",
+            content: images
+                .iter()
+                .map(|image| InputContent::InputImage {
+                    image_url: image.data_url(),
+                    detail: image.detail.as_str(),
+                })
+                .collect(),
+        });
+    }
+}
+
+/// One event of a streamed response. The `type` field repeats the SSE
+/// event name.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub(crate) enum ResponsesEvent {
+    #[serde(rename = "response.output_text.delta")]
+    OutputTextDelta { delta: String },
+    #[serde(rename = "response.reasoning_summary_part.added")]
+    ReasoningSummaryPartAdded {},
+    #[serde(rename = "response.reasoning_summary_text.delta")]
+    ReasoningSummaryTextDelta { delta: String },
+    #[serde(rename = "response.web_search_call.in_progress")]
+    WebSearchInProgress {},
+    #[serde(rename = "response.web_search_call.searching")]
+    WebSearchSearching {},
+    #[serde(rename = "response.web_search_call.completed")]
+    WebSearchCompleted {},
+    #[serde(rename = "response.output_text.annotation.added")]
+    AnnotationAdded { annotation: Annotation },
+    /// Repeats the part's annotations once its text is complete.
+    #[serde(rename = "response.content_part.done")]
+    ContentPartDone { part: ContentPart },
+    /// A finished output item; only function calls are acted on.
+    #[serde(rename = "response.output_item.done")]
+    OutputItemDone { item: OutputItem },
+    #[serde(rename = "response.completed")]
+    Completed { response: ResponseObject },
+    /// Ended early, e.g. at `max_output_tokens`; the output so far stands.
+    #[serde(rename = "response.incomplete")]
+    Incomplete { response: ResponseObject },
+    #[serde(rename = "response.failed")]
+    Failed { response: ResponseObject },
+    #[serde(rename = "error")]
+    Error(ResponseError),
+    /// Lifecycle and item events the client has no use for.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum OutputItem {
+    FunctionCall(FunctionCall),
+    /// Read only from responses that were not streamed.
+    Message {
+        #[serde(default)]
+        content: Vec<ContentPart>,
+    },
+    /// Reasoning and web search calls, already streamed.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct FunctionCall {
+    pub call_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub arguments: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ContentPart {
+    /// Empty for refusals.
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Annotation {
+    UrlCitation {
+        url: String,
+        #[serde(default)]
+        title: Option<String>,
+    },
+    /// File citations and other kinds without a link.
+    #[serde(other)]
+    Other,
+}
+
+impl Annotation {
+    pub fn citation(self) -> Option<Citation> {
+        match self {
+            Annotation::UrlCitation { url, title } => Some(Citation { url, title }),
+            Annotation::Other => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ResponseObject {
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Only filled in on responses that were not streamed.
+    #[serde(default)]
+    pub output: Vec<OutputItem>,
+    #[serde(default)]
+    pub usage: Option<ResponsesUsage>,
+    #[serde(default)]
+    pub error: Option<ResponseError>,
+    #[serde(default)]
+    pub incomplete_details: Option<IncompleteDetails>,
+}
+
+impl ResponseObject {
+    /// The text of the message items, joined.
+    pub fn output_text(&self) -> String {
+        self.output
+            .iter()
+            .filter_map(|item| match item {
+                OutputItem::Message { content } => Some(content),
+                _ => None,
+            })
+            .flatten()
+            .map(|part| part.text.as_str())
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ResponseError {
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct IncompleteDetails {
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ResponsesUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub input_tokens_details: Option<InputTokensDetails>,
+    #[serde(default)]
+    pub output_tokens_details: Option<OutputTokensDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct InputTokensDetails {
+    #[serde(default)]
+    pub cached_tokens: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct OutputTokensDetails {
+    #[serde(default)]
+    pub reasoning_tokens: u64,
+}
+
+impl From<ResponsesUsage> for TokenUsage {
+    fn from(usage: ResponsesUsage) -> Self {
+        Self {
+            prompt_tokens: usage.input_tokens,
+            completion_tokens: usage.output_tokens,
+            reasoning_tokens: usage
+                .output_tokens_details
+                .map_or(0, |details| details.reasoning_tokens),
+            cached_tokens: usage
+                .input_tokens_details
+                .map_or(0, |details| details.cached_tokens),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::capture::PreparedImage;
+    use crate::config::{ImageDetail, OpenAIConfig, ResponsesSettings, UploadFormat};
+    use crate::session::ConversationEntry;
+
+    fn request(model: &str) -> AnalyzeRequest {
+        AnalyzeRequest {
+            request_id: Default::default(),
+            provider: crate::config::ProviderKind::OpenAI,
+            capabilities: crate::models::capabilities(model, &Default::default()),
+            config: OpenAIConfig {
+                model: model.to_string(),
+                ..OpenAIConfig::default()
+            },
+            text_prompt: "what is this?".into(),
+            custom_prompt: Some("Be brief.".into()),
+            images: vec![PreparedImage {
+                bytes: vec![1, 2, 3],
+                format: UploadFormat::Png,
+                width: 1,
+                height: 1,
+                detail: ImageDetail::High,
+            }],
+            history: VecDeque::from([
+                ConversationEntry::new(ConversationRole::User, "hi"),
+                ConversationEntry::new(ConversationRole::Assistant, "hello"),
+            ]),
+            tools: None,
+            max_tool_rounds: 0,
+            cancel: Default::default(),
+        }
+    }
+
+    #[test]
+    fn builds_the_responses_request_shape() {
+        let payload = serde_json::to_value(build_responses_payload(&request("gpt-5"))).unwrap();
+
+        assert_eq!(payload["instructions"], "Be brief.");
+        assert_eq!(payload["max_output_tokens"], 2048);
+        assert_eq!(payload["stream"], true);
+        assert_eq!(payload["store"], false);
+        assert_eq!(
+            payload["tools"],
+            serde_json::json!([{ "type": "web_search" }])
+        );
+        assert_eq!(payload["reasoning"]["effort"], "medium");
+        assert_eq!(payload["reasoning"]["summary"], "auto");
+        assert!(payload.get("service_tier").is_none());
+        assert!(payload.get("text").is_none());
+        assert!(payload.get("temperature").is_none());
+        for legacy in ["messages", "max_tokens", "modalities", "reasoning_effort"] {
+            assert!(payload.get(legacy).is_none(), "{legacy}");
+        }
+
+        let input = payload["input"].as_array().unwrap();
+        assert_eq!(input.len(), 3);
+        assert_eq!(input[0]["content"][0]["type"], "input_text");
+        assert_eq!(input[1]["role"], "assistant");
+        assert_eq!(input[1]["content"][0]["type"], "output_text");
+        let question = input[2]["content"].as_array().unwrap();
+        assert_eq!(question[0]["text"], "what is this?");
+        assert_eq!(question[1]["type"], "input_image");
+        assert_eq!(question[1]["image_url"], "data:image/png;base64,AQID");
+        assert_eq!(question[1]["detail"], "high");
+    }
+
+    #[test]
+    fn applies_the_responses_settings() {
+        let mut request = request("gpt-5");
+        request.config.responses = ResponsesSettings {
+            effort: ReasoningEffort::Low,
+            verbosity: Verbosity::High,
+            web_search: false,
+            service_tier: ServiceTier::Priority,
+        };
+        let payload = serde_json::to_value(build_responses_payload(&request)).unwrap();
+        assert_eq!(payload["reasoning"]["effort"], "low");
+        assert_eq!(payload["text"]["verbosity"], "high");
+        assert_eq!(payload["service_tier"], "priority");
+        assert!(payload.get("tools").is_none());
+
+        request.config.responses.effort = ReasoningEffort::Minimal;
+        request.config.responses.web_search = true;
+        let payload = serde_json::to_value(build_responses_payload(&request)).unwrap();
+        assert_eq!(payload["reasoning"]["effort"], "minimal");
+        assert!(payload.get("tools").is_none());
+
+        // gpt-5-nano cannot be served on the priority tier.
+        let mut request = self::request("gpt-5-nano");
+        request.config.responses.service_tier = ServiceTier::Priority;
+        let payload = serde_json::to_value(build_responses_payload(&request)).unwrap();
+        assert!(payload.get("service_tier").is_none());
+    }
+
+    #[test]
+    fn leaves_out_what_o4_mini_rejects() {
+        let mut request = request("o4-mini");
+        request.config.responses.effort = ReasoningEffort::Minimal;
+        request.config.responses.verbosity = Verbosity::Low;
+        let payload = serde_json::to_value(build_responses_payload(&request)).unwrap();
+        assert_eq!(payload["reasoning"]["effort"], "low");
+        assert!(payload.get("text").is_none());
+    }
+
+    #[test]
+    fn parses_terminal_and_unknown_events() {
+        let parse = |data: &str| serde_json::from_str::<ResponsesEvent>(data).unwrap();
+
+        let ResponsesEvent::Failed { response } = parse(
+            r#"{"type":"response.failed","response":{"status":"failed",
+                "error":{"code":"server_error","message":"boom"}}}"#,
+        ) else {
+            panic!("expected response.failed");
+        };
+        assert_eq!(
+            response.error.unwrap().code.as_deref(),
+            Some("server_error")
+        );
+
+        let ResponsesEvent::Incomplete { response } = parse(
+            r#"{"type":"response.incomplete","response":{"status":"incomplete",
+                "incomplete_details":{"reason":"max_output_tokens"}}}"#,
+        ) else {
+            panic!("expected response.incomplete");
+        };
+        assert_eq!(
+            response.incomplete_details.unwrap().reason,
+            "max_output_tokens"
+        );
+
+        let ResponsesEvent::Error(error) = parse(
+            r#"{"type":"error","code":"rate_limit_exceeded","message":"slow down","param":null}"#,
+        ) else {
+            panic!("expected error");
+        };
+        assert_eq!(error.message, "slow down");
+
+        assert!(matches!(
+            parse(r#"{"type":"response.web_search_call.searching","item_id":"ws_1"}"#),
+            ResponsesEvent::WebSearchSearching {}
+        ));
+        assert!(matches!(
+            parse(r#"{"type":"response.output_item.added","item":{}}"#),
+            ResponsesEvent::Other
+        ));
+
+        let ResponsesEvent::ContentPartDone { part } = parse(
+            r#"{"type":"response.content_part.done","part":{"type":"output_text","text":"x",
+                "annotations":[{"type":"url_citation","url":"https://a.example","title":"A",
+                                "start_index":0,"end_index":1},
+                               {"type":"file_citation","file_id":"file_1","index":0}]}}"#,
+        ) else {
+            panic!("expected response.content_part.done");
+        };
+        let citations: Vec<_> = part
+            .annotations
+            .into_iter()
+            .filter_map(Annotation::citation)
+            .collect();
+        assert_eq!(
+            citations,
+            [Citation {
+                url: "https://a.example".into(),
+                title: Some("A".into()),
+            }]
+        );
+    }
+}
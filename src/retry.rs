@@ -0,0 +1,298 @@
+//! Retry with jittered exponential backoff for requests that fail before any
+//! response body has been consumed.
+
+use std::time::{Duration, SystemTime};
+
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+use crate::config::RetrySettings;
+use crate::error::{ApiError, ApiErrorKind};
+
+/// Headers OpenAI uses to say when the request/token budget refills,
+/// formatted like `1s`, `250ms` or `6m0s`.
+const RATE_LIMIT_RESET_HEADERS: [&str; 2] =
+    ["x-ratelimit-reset-requests", "x-ratelimit-reset-tokens"];
+
+/// Longest server-requested wait that is honored; anything beyond it is
+/// treated as a malformed header.
+const MAX_SERVER_DELAY: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Per-attempt wait for the response headers. Running out of it is not
+    /// retried, for the same reason as other timeouts.
+    pub response_timeout: Option<Duration>,
+}
+
+impl From<&RetrySettings> for RetryPolicy {
+    fn from(settings: &RetrySettings) -> Self {
+        Self {
+            max_retries: settings.max_retries,
+            initial_backoff: Duration::from_millis(settings.initial_backoff_ms),
+            max_backoff: Duration::from_millis(settings.max_backoff_ms.max(1)),
+            response_timeout: None,
+        }
+    }
+}
+
+/// Passed to the caller before each backoff sleep.
+#[derive(Debug, Clone)]
+pub struct RetryNotice {
+    /// 1-based number of the retry about to happen.
+    pub attempt: u32,
+    pub max_retries: u32,
+    pub delay: Duration,
+    pub reason: String,
+}
+
+impl RetryPolicy {
+    pub fn with_response_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.response_timeout = timeout;
+        self
+    }
+
+    /// Equal-jitter exponential backoff: a random delay in the upper half of
+    /// `initial * 2^attempt`, capped at `max_backoff`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.min(16)))
+            .min(self.max_backoff);
+        let millis = exp.as_millis() as u64;
+        if millis < 2 {
+            return exp;
+        }
+        Duration::from_millis(rand::thread_rng().gen_range(millis / 2..=millis))
+    }
+
+    /// Prefers a server-provided delay over the computed backoff. The server
+    /// delay is not bound by `max_backoff`, since retrying earlier than asked
+    /// only earns another rejection.
+    pub fn delay_for(&self, attempt: u32, headers: Option<&HeaderMap>) -> Duration {
+        headers
+            .and_then(retry_delay_from_headers)
+            .map(|delay| delay.min(MAX_SERVER_DELAY))
+            .unwrap_or_else(|| self.backoff(attempt))
+    }
+
+    /// Sends the request built by `build`, retrying connection errors, 429 and
+    /// 5xx responses. Timeouts are not retried: the server may already be
+    /// working on the request, and sending it again would be billed twice.
+    /// Nor is a 429 whose body reports an exhausted quota or billing limit,
+    /// which waiting does not fix; it comes back as an error. Non-retryable
+    /// statuses, and the last attempt's response, are returned to the caller
+    /// unchanged.
+    pub async fn send<F, N>(&self, mut build: F, mut on_retry: N) -> Result<Response, ApiError>
+    where
+        F: FnMut() -> RequestBuilder,
+        N: FnMut(RetryNotice),
+    {
+        let mut attempt = 0;
+        loop {
+            let result = self.send_once(build()).await;
+            let retryable = match &result {
+                Ok(res) => is_retryable_status(res.status()),
+                Err((_, retryable)) => *retryable,
+            };
+            if !retryable || attempt >= self.max_retries {
+                return result.map_err(|(err, _)| err);
+            }
+            let (delay, reason) = match result {
+                Ok(res) => {
+                    let delay = self.delay_for(attempt, Some(res.headers()));
+                    let reason = format!("HTTP {}", res.status());
+                    // The body is only read here, where the response is
+                    // dropped for a retry anyway.
+                    if res.status() == StatusCode::TOO_MANY_REQUESTS {
+                        let error = ApiError::from_response(res).await;
+                        if error.kind == ApiErrorKind::Quota {
+                            return Err(error);
+                        }
+                    }
+                    (delay, reason)
+                }
+                Err((err, _)) => (self.backoff(attempt), err.message),
+            };
+
+            attempt += 1;
+            log::warn!(
+                "request failed ({reason}); retry {attempt}/{} in {delay:?}",
+                self.max_retries
+            );
+            on_retry(RetryNotice {
+                attempt,
+                max_retries: self.max_retries,
+                delay,
+                reason,
+            });
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// One attempt; failures come with whether they are worth retrying.
+    async fn send_once(&self, request: RequestBuilder) -> Result<Response, (ApiError, bool)> {
+        let send = request.send();
+        let result = match self.response_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, send).await.map_err(|_| {
+                let message = format!("no response within {}s", timeout.as_secs());
+                (ApiError::timeout(message), false)
+            })?,
+            None => send.await,
+        };
+        result.map_err(|err| {
+            let retryable = err.is_connect();
+            (ApiError::network(err), retryable)
+        })
+    }
+}
+
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Reads `Retry-After` (seconds or HTTP date) and falls back to the longest
+/// `x-ratelimit-reset-*` value.
+pub fn retry_delay_from_headers(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(value) = headers.get(RETRY_AFTER).and_then(|v| v.to_str().ok()) {
+        let value = value.trim();
+        if let Ok(seconds) = value.parse::<f64>() {
+            if seconds.is_finite() && seconds >= 0.0 {
+                return Some(Duration::from_secs_f64(seconds));
+            }
+        }
+        if let Ok(date) = chrono::DateTime::parse_from_rfc2822(value) {
+            let target: SystemTime = date.into();
+            return Some(
+                target
+                    .duration_since(SystemTime::now())
+                    .unwrap_or(Duration::ZERO),
+            );
+        }
+    }
+
+    RATE_LIMIT_RESET_HEADERS
+        .iter()
+        .filter_map(|name| headers.get(*name)?.to_str().ok())
+        .filter_map(parse_reset_duration)
+        .max()
+}
+
+/// Parses Go-style durations such as `20ms`, `1.5s` or `1h2m3s`.
+pub fn parse_reset_duration(value: &str) -> Option<Duration> {
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    let mut total = 0.0f64;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_len].parse().ok()?;
+        rest = &rest[number_len..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let scale = match &rest[..unit_len] {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 0.001,
+            "us" | "µs" => 0.000_001,
+            "ns" => 0.000_000_001,
+            _ => return None,
+        };
+        total += number * scale;
+        rest = &rest[unit_len..];
+    }
+    Some(Duration::from_secs_f64(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn parses_rate_limit_reset_durations() {
+        assert_eq!(parse_reset_duration("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(
+            parse_reset_duration("250ms"),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(parse_reset_duration("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(
+            parse_reset_duration("1h2m3.5s"),
+            Some(Duration::from_millis(3_723_500))
+        );
+        assert_eq!(parse_reset_duration("soon"), None);
+        assert_eq!(parse_reset_duration(""), None);
+    }
+
+    #[test]
+    fn retry_after_seconds_take_precedence() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("7"));
+        headers.insert(
+            "x-ratelimit-reset-requests",
+            HeaderValue::from_static("20s"),
+        );
+        assert_eq!(
+            retry_delay_from_headers(&headers),
+            Some(Duration::from_secs(7))
+        );
+    }
+
+    #[test]
+    fn falls_back_to_longest_rate_limit_reset() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-ratelimit-reset-requests",
+            HeaderValue::from_static("120ms"),
+        );
+        headers.insert("x-ratelimit-reset-tokens", HeaderValue::from_static("2s"));
+        assert_eq!(
+            retry_delay_from_headers(&headers),
+            Some(Duration::from_secs(2))
+        );
+    }
+
+    #[test]
+    fn backoff_is_jittered_within_cap() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(1_000),
+            response_timeout: None,
+        };
+        for attempt in 0..10 {
+            let delay = policy.backoff(attempt);
+            let ceiling = Duration::from_millis(100 * 2u64.pow(attempt)).min(policy.max_backoff);
+            assert!(
+                delay <= ceiling && delay >= ceiling / 2,
+                "attempt {attempt}: {delay:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn server_delay_is_not_capped_by_max_backoff() {
+        let policy = RetryPolicy {
+            max_retries: 1,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            response_timeout: None,
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("60"));
+        assert_eq!(policy.delay_for(0, Some(&headers)), Duration::from_secs(60));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("86400"));
+        assert_eq!(policy.delay_for(0, Some(&headers)), MAX_SERVER_DELAY);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::{HashSet, VecDeque};
 use std::{fs, path::PathBuf};
 
 use anyhow::{Context, Result};
@@ -6,6 +7,8 @@ use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::usage::TokenUsage;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ConversationRole {
@@ -14,6 +17,8 @@ pub enum ConversationRole {
     Assistant,
     Reasoning,
     Error,
+    /// A local tool the model called while answering, with its output.
+    Tool,
 }
 
 impl ConversationRole {
@@ -24,22 +29,36 @@ impl ConversationRole {
             ConversationRole::Assistant => "Ghost",
             ConversationRole::Reasoning => "Reasoning",
             ConversationRole::Error => "Error",
+            ConversationRole::Tool => "Tool",
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum WebSearchStatus {
+    #[default]
     NotUsed,
     InProgress,
     Searching,
     Completed,
 }
 
-impl Default for WebSearchStatus {
-    fn default() -> Self {
-        WebSearchStatus::NotUsed
+/// A web page the model cited while answering with web search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Citation {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+impl Citation {
+    /// The title, or the URL for pages cited without one.
+    pub fn label(&self) -> &str {
+        self.title
+            .as_deref()
+            .filter(|title| !title.trim().is_empty())
+            .unwrap_or(&self.url)
     }
 }
 
@@ -52,6 +71,19 @@ pub struct ConversationEntry {
     pub reasoning: Option<String>,
     #[serde(default)]
     pub web_search_status: WebSearchStatus,
+    /// Sources of a web search answer, numbered from 1 in this order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<Citation>,
+    /// Set when the answer is incomplete: stopped by the user, cut off at the
+    /// output token limit, or lost with the connection.
+    #[serde(default)]
+    pub interrupted: bool,
+    /// Tokens billed for producing this answer.
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+    /// Cost of `usage` in US dollars, when the model's price is known.
+    #[serde(default)]
+    pub cost_usd: Option<f64>,
     pub timestamp: DateTime<Utc>,
 }
 
@@ -63,15 +95,39 @@ impl ConversationEntry {
             content: content.into(),
             reasoning: None,
             web_search_status: WebSearchStatus::NotUsed,
+            citations: Vec::new(),
+            interrupted: false,
+            usage: None,
+            cost_usd: None,
             timestamp: Utc::now(),
         }
     }
+
+    /// Adds a source unless the same URL is already listed.
+    pub fn add_citation(&mut self, citation: Citation) {
+        if !self.citations.iter().any(|known| known.url == citation.url) {
+            self.citations.push(citation);
+        }
+    }
+}
+
+/// Rolling summary that stands in for older turns in request history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSummary {
+    /// Pinned System entry sent ahead of the remaining history.
+    pub entry: ConversationEntry,
+    /// Ids of the entries the summary replaces.
+    pub covered: HashSet<Uuid>,
 }
 
 #[derive(Default)]
 struct SessionState {
     session_id: Uuid,
     entries: Vec<ConversationEntry>,
+    summary: Option<SessionSummary>,
+    /// Usage and known cost of requests that left no entry behind.
+    unlisted_usage: TokenUsage,
+    unlisted_cost: f64,
 }
 
 impl SessionState {
@@ -79,8 +135,23 @@ impl SessionState {
         Self {
             session_id: Uuid::new_v4(),
             entries: Vec::new(),
+            summary: None,
+            unlisted_usage: TokenUsage::default(),
+            unlisted_cost: 0.0,
         }
     }
+
+    fn usage_totals(&self) -> (TokenUsage, f64) {
+        let mut usage = self.unlisted_usage;
+        let mut cost = self.unlisted_cost;
+        for entry in &self.entries {
+            if let Some(entry_usage) = entry.usage {
+                usage += entry_usage;
+            }
+            cost += entry.cost_usd.unwrap_or_default();
+        }
+        (usage, cost)
+    }
 }
 
 pub struct SessionManager {
@@ -111,6 +182,14 @@ impl SessionManager {
         self.state.lock().entries.push(entry);
     }
 
+    /// Takes an entry out of the session, such as an answer that is being
+    /// replaced.
+    pub fn remove(&self, id: Uuid) -> Option<ConversationEntry> {
+        let mut guard = self.state.lock();
+        let index = guard.entries.iter().position(|entry| entry.id == id)?;
+        Some(guard.entries.remove(index))
+    }
+
     pub fn reset(&self) -> Uuid {
         let mut guard = self.state.lock();
         *guard = SessionState::new_session();
@@ -122,6 +201,61 @@ impl SessionManager {
         guard.entries = entries;
     }
 
+    /// Counts tokens billed for a request that adds no entry of its own,
+    /// such as a history summary.
+    pub fn add_usage(&self, usage: TokenUsage, cost_usd: Option<f64>) {
+        let mut guard = self.state.lock();
+        guard.unlisted_usage += usage;
+        guard.unlisted_cost += cost_usd.unwrap_or_default();
+    }
+
+    /// Summed usage and known cost of every request in the session.
+    pub fn usage_totals(&self) -> (TokenUsage, f64) {
+        self.state.lock().usage_totals()
+    }
+
+    pub fn summary(&self) -> Option<SessionSummary> {
+        self.state.lock().summary.clone()
+    }
+
+    /// Replaces the rolling summary. `covered` adds to the entries already
+    /// summarized, since the new text is expected to include the old one.
+    pub fn apply_summary(&self, text: String, covered: impl IntoIterator<Item = Uuid>) {
+        let mut guard = self.state.lock();
+        let summary = guard.summary.get_or_insert_with(|| SessionSummary {
+            entry: ConversationEntry::new(ConversationRole::System, String::new()),
+            covered: HashSet::new(),
+        });
+        summary.entry.content = text;
+        summary.entry.timestamp = Utc::now();
+        summary.covered.extend(covered);
+    }
+
+    /// History to send with a request: the pinned summary, if any, followed
+    /// by the entries it does not cover. The session log is unaffected.
+    pub fn request_history<'a>(
+        &self,
+        entries: impl IntoIterator<Item = &'a ConversationEntry>,
+    ) -> VecDeque<ConversationEntry> {
+        let guard = self.state.lock();
+        let mut history = VecDeque::new();
+        if let Some(summary) = &guard.summary {
+            history.push_back(summary.entry.clone());
+        }
+        history.extend(
+            entries
+                .into_iter()
+                .filter(|entry| {
+                    guard
+                        .summary
+                        .as_ref()
+                        .is_none_or(|summary| !summary.covered.contains(&entry.id))
+                })
+                .cloned(),
+        );
+        history
+    }
+
     pub fn write_plaintext_log(&self) -> Result<PathBuf> {
         let guard = self.state.lock();
         let txt_filename = format!("{}-conversation.txt", guard.session_id);
@@ -157,16 +291,52 @@ impl SessionManager {
                 WebSearchStatus::NotUsed => {}
             }
 
-            buffer.push_str("\n");
+            if !entry.citations.is_empty() {
+                buffer.push_str("[Sources]\n");
+                for (index, citation) in entry.citations.iter().enumerate() {
+                    match citation.title.as_deref().filter(|title| !title.trim().is_empty()) {
+                        Some(title) => buffer.push_str(&format!(
+                            "[{}] {title} <{}>\n",
+                            index + 1,
+                            citation.url
+                        )),
+                        None => buffer.push_str(&format!("[{}] {}\n", index + 1, citation.url)),
+                    }
+                }
+            }
+
+            if entry.interrupted {
+                buffer.push_str("[Interrupted]\n");
+            }
+
+            if let Some(usage) = entry.usage {
+                buffer.push_str(&format!(
+                    "[Usage] prompt {} (cached {}), completion {} (reasoning {})",
+                    usage.prompt_tokens,
+                    usage.cached_tokens,
+                    usage.completion_tokens,
+                    usage.reasoning_tokens
+                ));
+                if let Some(cost) = entry.cost_usd {
+                    buffer.push_str(&format!(", ${cost:.6}"));
+                }
+                buffer.push('\n');
+            }
+
+            buffer.push('\n');
         }
         fs::write(&txt_path, buffer)
             .with_context(|| format!("failed to write conversation log to {}", txt_path.display()))?;
 
         // Write JSON log (structured)
+        let (total_usage, total_cost) = guard.usage_totals();
         let json_data = serde_json::json!({
             "session_id": guard.session_id,
+            "usage": total_usage,
+            "cost_usd": total_cost,
             "entries": guard.entries,
             "entry_count": guard.entries.len(),
+            "summary": guard.summary,
         });
         let json_content = serde_json::to_string_pretty(&json_data)
             .context("failed to serialize conversation to JSON")?;
@@ -0,0 +1,310 @@
+//! Spoken answers: splitting streamed text into sentences, decoding the
+//! speech endpoint's audio and playing it on the default output device.
+//!
+//! Each sentence is synthesized as soon as it is complete, so playback of the
+//! first one starts while the rest of the answer is still streaming. Clips
+//! are queued in order and the player can skip the current one or stop all.
+
+use std::collections::VecDeque;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{SampleFormat, StreamConfig};
+use parking_lot::Mutex;
+
+use crate::audio::Resampler;
+use crate::config::SpeechFormat;
+
+/// Sample rate of the endpoint's raw `pcm` output.
+pub const SPEECH_PCM_SAMPLE_RATE: u32 = 24_000;
+const CODE_FENCE: &str = "```";
+
+/// Cuts streamed markdown into speakable sentences. Fenced code blocks are
+/// skipped and inline markup is dropped.
+#[derive(Debug, Default)]
+pub struct SentenceChunker {
+    pending: String,
+    in_code_block: bool,
+}
+
+impl SentenceChunker {
+    /// Adds streamed text and returns the sentences it completed.
+    pub fn push(&mut self, delta: &str) -> Vec<String> {
+        self.pending.push_str(delta);
+        let mut sentences = Vec::new();
+        while let Some((end, rest)) = self.next_boundary() {
+            let piece: String = self.pending[..end].to_string();
+            self.pending.drain(..rest);
+            if let Some(sentence) = self.speakable(&piece) {
+                sentences.push(sentence);
+            }
+        }
+        sentences
+    }
+
+    /// Returns whatever is left once the answer is complete.
+    pub fn finish(&mut self) -> Option<String> {
+        let rest = std::mem::take(&mut self.pending);
+        let sentence = self.speakable(&rest);
+        self.in_code_block = false;
+        sentence
+    }
+
+    /// Byte range of the next complete piece: where its text ends and where
+    /// the following one starts. A line end always completes a piece; inside
+    /// code blocks nothing else does.
+    fn next_boundary(&self) -> Option<(usize, usize)> {
+        let newline = self.pending.find('\n').map(|index| (index, index + 1));
+        if self.in_code_block {
+            return newline;
+        }
+        let mut chars = self.pending.char_indices().peekable();
+        while let Some((index, c)) = chars.next() {
+            if c == '\n' {
+                break;
+            }
+            let end = index + c.len_utf8();
+            match c {
+                // Full-width punctuation needs no trailing space.
+                '。' | '！' | '？' => return Some((end, end)),
+                // Wait for the next character so "3.14" stays whole.
+                '.' | '!' | '?' => match chars.peek() {
+                    Some((_, next)) if next.is_whitespace() => return Some((end, end)),
+                    Some(_) => {}
+                    None => return None,
+                },
+                _ => {}
+            }
+        }
+        newline
+    }
+
+    fn speakable(&mut self, piece: &str) -> Option<String> {
+        let trimmed = piece.trim();
+        if trimmed.starts_with(CODE_FENCE) {
+            self.in_code_block = !self.in_code_block;
+            return None;
+        }
+        if self.in_code_block {
+            return None;
+        }
+        let text: String = trimmed
+            .trim_start_matches(['#', '>', '-', '*', ' '])
+            .chars()
+            .filter(|c| !matches!(c, '*' | '`'))
+            .collect();
+        let text = text.trim();
+        text.chars()
+            .any(char::is_alphanumeric)
+            .then(|| text.to_string())
+    }
+}
+
+/// Decodes speech audio into mono samples and their sample rate.
+pub fn decode_speech(audio: &[u8], format: SpeechFormat) -> Result<(Vec<f32>, u32)> {
+    match format {
+        SpeechFormat::Pcm => {
+            let samples = audio
+                .chunks_exact(2)
+                .map(|pair| i16::from_le_bytes([pair[0], pair[1]]) as f32 / i16::MAX as f32)
+                .collect();
+            Ok((samples, SPEECH_PCM_SAMPLE_RATE))
+        }
+        SpeechFormat::Wav => {
+            let mut reader =
+                hound::WavReader::new(Cursor::new(audio)).context("invalid WAV speech audio")?;
+            let spec = reader.spec();
+            let interleaved: Vec<f32> = match spec.sample_format {
+                hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+                hound::SampleFormat::Int => {
+                    let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+                    reader
+                        .samples::<i32>()
+                        .map(|sample| sample.map(|sample| sample as f32 / scale))
+                        .collect::<Result<_, _>>()?
+                }
+            };
+            let channels = spec.channels.max(1) as usize;
+            let samples = interleaved
+                .chunks_exact(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+                .collect();
+            Ok((samples, spec.sample_rate))
+        }
+    }
+}
+
+#[derive(Default)]
+struct PlaybackQueue {
+    /// Clips at the device sample rate, oldest first.
+    clips: VecDeque<Vec<f32>>,
+    /// Next sample of the front clip.
+    position: usize,
+}
+
+impl PlaybackQueue {
+    fn next_sample(&mut self) -> f32 {
+        while let Some(clip) = self.clips.front() {
+            if let Some(sample) = clip.get(self.position) {
+                self.position += 1;
+                return *sample;
+            }
+            self.clips.pop_front();
+            self.position = 0;
+        }
+        0.0
+    }
+}
+
+/// Plays queued clips on the default output device. The stream outputs
+/// silence while the queue is empty.
+pub struct SpeechPlayer {
+    _stream: cpal::Stream,
+    queue: Arc<Mutex<PlaybackQueue>>,
+    sample_rate: u32,
+}
+
+impl SpeechPlayer {
+    pub fn new() -> Result<Self> {
+        let host = cpal::default_host();
+        let device = host
+            .default_output_device()
+            .context("no output audio device available")?;
+        let config = device
+            .default_output_config()
+            .context("failed to query default output config")?;
+        let sample_rate = config.sample_rate().0;
+        let sample_format = config.sample_format();
+        let queue = Arc::new(Mutex::new(PlaybackQueue::default()));
+
+        let stream = build_stream(&device, &config.into(), sample_format, Arc::clone(&queue))?;
+        stream.play().context("failed to start audio playback")?;
+
+        Ok(Self {
+            _stream: stream,
+            queue,
+            sample_rate,
+        })
+    }
+
+    /// Queues mono `samples` recorded at `sample_rate` after the current clips.
+    pub fn enqueue(&self, samples: &[f32], sample_rate: u32) {
+        let clip = if sample_rate == self.sample_rate {
+            samples.to_vec()
+        } else {
+            Resampler::new(sample_rate, self.sample_rate).process(samples)
+        };
+        if !clip.is_empty() {
+            self.queue.lock().clips.push_back(clip);
+        }
+    }
+
+    /// Drops the clip being played and continues with the next one.
+    pub fn skip(&self) {
+        let mut queue = self.queue.lock();
+        queue.clips.pop_front();
+        queue.position = 0;
+    }
+
+    pub fn stop(&self) {
+        let mut queue = self.queue.lock();
+        queue.clips.clear();
+        queue.position = 0;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        !self.queue.lock().clips.is_empty()
+    }
+}
+
+/// Writes the next mono sample to every channel of each frame.
+fn output_callback<T: Copy + Send + 'static>(
+    channels: usize,
+    queue: Arc<Mutex<PlaybackQueue>>,
+    from_f32: fn(f32) -> T,
+) -> impl FnMut(&mut [T], &cpal::OutputCallbackInfo) + Send + 'static {
+    move |data: &mut [T], _| {
+        let mut queue = queue.lock();
+        for frame in data.chunks_exact_mut(channels) {
+            let sample = from_f32(queue.next_sample().clamp(-1.0, 1.0));
+            frame.fill(sample);
+        }
+    }
+}
+
+fn build_stream(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    format: SampleFormat,
+    queue: Arc<Mutex<PlaybackQueue>>,
+) -> Result<cpal::Stream> {
+    let err_fn = |err| log::error!("audio output stream error: {err}");
+    let channels = config.channels as usize;
+
+    let stream = match format {
+        SampleFormat::F32 => device.build_output_stream(
+            config,
+            output_callback(channels, queue, |sample| sample),
+            err_fn,
+            None,
+        )?,
+        SampleFormat::I16 => device.build_output_stream(
+            config,
+            output_callback(channels, queue, |sample| (sample * i16::MAX as f32) as i16),
+            err_fn,
+            None,
+        )?,
+        SampleFormat::U16 => device.build_output_stream(
+            config,
+            output_callback(channels, queue, |sample| {
+                ((sample + 1.0) / 2.0 * u16::MAX as f32) as u16
+            }),
+            err_fn,
+            None,
+        )?,
+        other => bail!("unsupported output sample format: {other:?}"),
+    };
+
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_streamed_markdown_into_sentences() {
+        let mut chunker = SentenceChunker::default();
+        let mut sentences = Vec::new();
+        for delta in [
+            "## Plan\nPi is 3.",
+            "14 roughly. Run **this",
+            "**:\n```sh\nls -la. now\n```\n",
+            "Done! 好的。最後",
+        ] {
+            sentences.extend(chunker.push(delta));
+        }
+        sentences.extend(chunker.finish());
+
+        assert_eq!(
+            sentences,
+            [
+                "Plan",
+                "Pi is 3.14 roughly.",
+                "Run this:",
+                "Done!",
+                "好的。",
+                "最後"
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_raw_pcm_at_24_khz() {
+        let (samples, rate) = decode_speech(&[0, 0, 0xff, 0x7f], SpeechFormat::Pcm).unwrap();
+        assert_eq!(rate, SPEECH_PCM_SAMPLE_RATE);
+        assert_eq!(samples, [0.0, 1.0]);
+    }
+}
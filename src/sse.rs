@@ -0,0 +1,329 @@
+//! Incremental decoder for `text/event-stream` bodies.
+//!
+//! Bytes are buffered until a full line is available, so multi-byte UTF-8
+//! sequences split across network chunks are decoded intact. Field parsing
+//! follows the WHATWG event-stream rules: `\n`, `\r\n` and `\r` all end a line,
+//! repeated `data:` fields are joined with `\n`, comment lines are ignored and
+//! `id:`/`retry:` are tracked across events.
+
+use std::collections::VecDeque;
+
+use futures::{Stream, StreamExt};
+
+/// A dispatched server-sent event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    /// Value of the `event:` field, or `"message"` when none was sent.
+    pub event: String,
+    pub data: String,
+    /// Last event id seen on the stream, carried over between events.
+    pub id: Option<String>,
+    /// Reconnection delay in milliseconds, if the event carried `retry:`.
+    pub retry: Option<u64>,
+}
+
+impl SseEvent {
+    /// OpenAI-style streams terminate with a literal `[DONE]` payload.
+    pub fn is_done(&self) -> bool {
+        self.data == "[DONE]"
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    /// Set when the previous chunk ended in `\r`, so a leading `\n` in the
+    /// next chunk belongs to the same line terminator.
+    pending_cr: bool,
+    started: bool,
+    event_type: String,
+    data: String,
+    has_data: bool,
+    last_event_id: Option<String>,
+    retry: Option<u64>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk of the response body and returns every event it completed.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        let mut chunk = chunk;
+        if self.pending_cr {
+            self.pending_cr = false;
+            if let Some(rest) = chunk.strip_prefix(b"\n") {
+                chunk = rest;
+            }
+        }
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        let mut start = 0;
+        let mut index = 0;
+        while index < self.buffer.len() {
+            match self.buffer[index] {
+                b'\n' => {
+                    self.process_line(start, index, &mut events);
+                    index += 1;
+                    start = index;
+                }
+                b'\r' => {
+                    self.process_line(start, index, &mut events);
+                    index += 1;
+                    match self.buffer.get(index) {
+                        Some(b'\n') => index += 1,
+                        Some(_) => {}
+                        None => self.pending_cr = true,
+                    }
+                    start = index;
+                }
+                _ => index += 1,
+            }
+        }
+        self.buffer.drain(..start);
+        events
+    }
+
+    /// Flushes a trailing line and pending event at end of stream.
+    ///
+    /// The spec discards an unterminated final event, but several
+    /// OpenAI-compatible servers close the body without the final blank line,
+    /// so it is dispatched instead.
+    pub fn finish(&mut self) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        if !self.buffer.is_empty() {
+            let end = self.buffer.len();
+            self.process_line(0, end, &mut events);
+            self.buffer.clear();
+        }
+        self.pending_cr = false;
+        if let Some(event) = self.dispatch() {
+            events.push(event);
+        }
+        events
+    }
+
+    fn process_line(&mut self, start: usize, end: usize, events: &mut Vec<SseEvent>) {
+        let mut line = &self.buffer[start..end];
+        if !self.started {
+            self.started = true;
+            if let Some(rest) = line.strip_prefix("\u{feff}".as_bytes()) {
+                line = rest;
+            }
+        }
+
+        if line.is_empty() {
+            if let Some(event) = self.dispatch() {
+                events.push(event);
+            }
+            return;
+        }
+        if line[0] == b':' {
+            return;
+        }
+
+        let (field, value) = match line.iter().position(|byte| *byte == b':') {
+            Some(colon) => {
+                let value = &line[colon + 1..];
+                (&line[..colon], value.strip_prefix(b" ").unwrap_or(value))
+            }
+            None => (line, &[][..]),
+        };
+        let value = String::from_utf8_lossy(value);
+
+        match field {
+            b"event" => self.event_type = value.into_owned(),
+            b"data" => {
+                if self.has_data {
+                    self.data.push('\n');
+                }
+                self.data.push_str(&value);
+                self.has_data = true;
+            }
+            b"id" if !value.contains('\0') => self.last_event_id = Some(value.into_owned()),
+            b"retry" if !value.is_empty() && value.bytes().all(|byte| byte.is_ascii_digit()) => {
+                self.retry = value.parse().ok();
+            }
+            _ => {}
+        }
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event_type = std::mem::take(&mut self.event_type);
+        let retry = self.retry.take();
+        if !self.has_data {
+            self.data.clear();
+            return None;
+        }
+        self.has_data = false;
+        Some(SseEvent {
+            event: if event_type.is_empty() {
+                "message".to_string()
+            } else {
+                event_type
+            },
+            data: std::mem::take(&mut self.data),
+            id: self.last_event_id.clone(),
+            retry,
+        })
+    }
+}
+
+/// Adapts a byte stream (such as `reqwest::Response::bytes_stream`) into
+/// decoded events.
+pub struct SseStream<S> {
+    inner: S,
+    decoder: SseDecoder,
+    pending: VecDeque<SseEvent>,
+    finished: bool,
+}
+
+impl<S, B, E> SseStream<S>
+where
+    S: Stream<Item = Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            decoder: SseDecoder::new(),
+            pending: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    /// Returns the next event, or `None` once the body is exhausted.
+    pub async fn next_event(&mut self) -> Option<Result<SseEvent, E>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.finished {
+                return None;
+            }
+            match self.inner.next().await {
+                Some(Ok(chunk)) => self.pending.extend(self.decoder.feed(chunk.as_ref())),
+                Some(Err(err)) => return Some(Err(err)),
+                None => {
+                    self.finished = true;
+                    self.pending.extend(self.decoder.finish());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_in_chunks(input: &[u8], chunk_size: usize) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::new();
+        let mut events = Vec::new();
+        for chunk in input.chunks(chunk_size) {
+            events.extend(decoder.feed(chunk));
+        }
+        events.extend(decoder.finish());
+        events
+    }
+
+    #[test]
+    fn decodes_single_event() {
+        let events = decode_in_chunks(b"data: hello\n\n", 64);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "message");
+        assert_eq!(events[0].data, "hello");
+        assert_eq!(events[0].id, None);
+    }
+
+    #[test]
+    fn keeps_multibyte_characters_split_across_chunks() {
+        let input = "data: {\"delta\":\"你好，世界\"}\n\n".as_bytes();
+        for chunk_size in 1..input.len() {
+            let events = decode_in_chunks(input, chunk_size);
+            assert_eq!(events.len(), 1, "chunk size {chunk_size}");
+            assert_eq!(events[0].data, "{\"delta\":\"你好，世界\"}");
+        }
+    }
+
+    #[test]
+    fn splits_events_at_every_chunk_boundary() {
+        let input = b"event: response.output_text.delta\ndata: {\"delta\":\"a\"}\n\nevent: response.completed\ndata: {}\n\n";
+        for chunk_size in 1..input.len() {
+            let events = decode_in_chunks(input, chunk_size);
+            assert_eq!(events.len(), 2, "chunk size {chunk_size}");
+            assert_eq!(events[0].event, "response.output_text.delta");
+            assert_eq!(events[0].data, "{\"delta\":\"a\"}");
+            assert_eq!(events[1].event, "response.completed");
+        }
+    }
+
+    #[test]
+    fn joins_multi_line_data() {
+        let events = decode_in_chunks(b"data: first\ndata: second\ndata\n\n", 64);
+        assert_eq!(events[0].data, "first\nsecond\n");
+    }
+
+    #[test]
+    fn accepts_crlf_and_bare_cr_terminators() {
+        let input = b"event: a\r\ndata: 1\r\n\r\nevent: b\rdata: 2\r\r";
+        for chunk_size in 1..input.len() {
+            let events = decode_in_chunks(input, chunk_size);
+            assert_eq!(events.len(), 2, "chunk size {chunk_size}");
+            assert_eq!(
+                (events[0].event.as_str(), events[0].data.as_str()),
+                ("a", "1")
+            );
+            assert_eq!(
+                (events[1].event.as_str(), events[1].data.as_str()),
+                ("b", "2")
+            );
+        }
+    }
+
+    #[test]
+    fn tracks_id_and_retry_fields() {
+        let events = decode_in_chunks(
+            b"id: 7\nretry: 1500\ndata: x\n\ndata: y\n\nretry: soon\nid: 8\ndata: z\n\n",
+            64,
+        );
+        assert_eq!(events[0].id.as_deref(), Some("7"));
+        assert_eq!(events[0].retry, Some(1500));
+        assert_eq!(events[1].id.as_deref(), Some("7"));
+        assert_eq!(events[1].retry, None);
+        assert_eq!(events[2].id.as_deref(), Some("8"));
+        assert_eq!(events[2].retry, None);
+    }
+
+    #[test]
+    fn ignores_comments_and_events_without_data() {
+        let events = decode_in_chunks(b": keep-alive\n\nevent: ping\n\ndata:no-space\n\n", 64);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "message");
+        assert_eq!(events[0].data, "no-space");
+    }
+
+    #[test]
+    fn strips_leading_byte_order_mark() {
+        let events = decode_in_chunks("\u{feff}data: bom\n\n".as_bytes(), 1);
+        assert_eq!(events[0].data, "bom");
+    }
+
+    #[tokio::test]
+    async fn stream_adapter_yields_events_then_none() {
+        let chunks: Vec<Result<&[u8], ()>> = vec![Ok(b"data: a\n\nda"), Ok(b"ta: b")];
+        let mut events = SseStream::new(futures::stream::iter(chunks));
+        assert_eq!(events.next_event().await.unwrap().unwrap().data, "a");
+        assert_eq!(events.next_event().await.unwrap().unwrap().data, "b");
+        assert!(events.next_event().await.is_none());
+    }
+
+    #[test]
+    fn flushes_unterminated_event_on_finish() {
+        let events = decode_in_chunks(b"data: [DONE]", 4);
+        assert_eq!(events.len(), 1);
+        assert!(events[0].is_done());
+    }
+}
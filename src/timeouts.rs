@@ -0,0 +1,188 @@
+//! Connect, first-byte and idle timeouts for provider requests.
+//!
+//! Requests have no overall deadline: a streamed answer may take as long as
+//! it needs while chunks keep arriving, and fails as stalled once they stop.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use futures::{Stream, StreamExt};
+use parking_lot::Mutex;
+use reqwest::{Client, RequestBuilder};
+
+use crate::config::TimeoutSettings;
+use crate::error::ApiError;
+use crate::retry::RetryPolicy;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    pub first_byte: Option<Duration>,
+    pub idle: Option<Duration>,
+}
+
+impl From<&TimeoutSettings> for Timeouts {
+    fn from(settings: &TimeoutSettings) -> Self {
+        let limit = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+        Self {
+            connect: limit(settings.connect_secs),
+            first_byte: limit(settings.first_byte_secs),
+            idle: limit(settings.idle_secs),
+        }
+    }
+}
+
+impl Timeouts {
+    /// Bounds the wait for response headers of every retry attempt. The
+    /// same `first_byte` deadline then carries on into [`Self::watch_body`].
+    pub fn apply_to(&self, policy: RetryPolicy) -> RetryPolicy {
+        policy.with_response_timeout(self.first_byte)
+    }
+
+    /// Bounds a request whose body is read in one go.
+    pub fn limit(&self, request: RequestBuilder) -> RequestBuilder {
+        match self.first_byte {
+            Some(timeout) => request.timeout(timeout),
+            None => request,
+        }
+    }
+
+    /// Fails a streamed body whose first chunk arrives more than `first_byte`
+    /// after the request was `sent`, or whose later chunks are more than
+    /// `idle` apart.
+    pub fn watch_body<S, B>(
+        &self,
+        sent: Instant,
+        body: S,
+    ) -> impl Stream<Item = Result<B, ApiError>> + Unpin
+    where
+        S: Stream<Item = reqwest::Result<B>> + Unpin,
+    {
+        let Self {
+            first_byte, idle, ..
+        } = *self;
+        Box::pin(futures::stream::unfold(
+            Some((body, true)),
+            move |state| async move {
+                let (mut body, first) = state?;
+                let (limit, wait) = if first {
+                    (
+                        first_byte,
+                        first_byte.map(|limit| limit.saturating_sub(sent.elapsed())),
+                    )
+                } else {
+                    (idle, idle)
+                };
+                let next = match (limit, wait) {
+                    (Some(limit), Some(wait)) => {
+                        match tokio::time::timeout(wait, body.next()).await {
+                            Ok(next) => next,
+                            Err(_) => {
+                                let message = if first {
+                                    format!("no response data within {}s", limit.as_secs())
+                                } else {
+                                    format!("stream stalled: no data for {}s", limit.as_secs())
+                                };
+                                return Some((Err(ApiError::timeout(message)), None));
+                            }
+                        }
+                    }
+                    _ => body.next().await,
+                };
+                match next? {
+                    Ok(chunk) => Some((Ok(chunk), Some((body, false)))),
+                    Err(err) => Some((Err(ApiError::network(err)), None)),
+                }
+            },
+        ))
+    }
+}
+
+/// reqwest fixes the connect timeout per client, so one client is kept for
+/// every configured value.
+#[derive(Default)]
+pub struct HttpClients {
+    clients: Mutex<HashMap<Option<Duration>, Client>>,
+}
+
+impl HttpClients {
+    pub fn get(&self, timeouts: &Timeouts) -> Result<Client> {
+        let mut clients = self.clients.lock();
+        if let Some(client) = clients.get(&timeouts.connect) {
+            return Ok(client.clone());
+        }
+        let mut builder = Client::builder();
+        if let Some(connect) = timeouts.connect {
+            builder = builder.connect_timeout(connect);
+        }
+        let client = builder.build()?;
+        clients.insert(timeouts.connect, client.clone());
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ApiErrorKind;
+
+    fn timeouts(first_byte_ms: u64, idle_ms: u64) -> Timeouts {
+        Timeouts {
+            connect: None,
+            first_byte: Some(Duration::from_millis(first_byte_ms)),
+            idle: Some(Duration::from_millis(idle_ms)),
+        }
+    }
+
+    #[test]
+    fn zero_seconds_disables_a_limit() {
+        let settings = TimeoutSettings {
+            connect_secs: 5,
+            first_byte_secs: 0,
+            idle_secs: 30,
+        };
+        let timeouts = Timeouts::from(&settings);
+        assert_eq!(timeouts.connect, Some(Duration::from_secs(5)));
+        assert_eq!(timeouts.first_byte, None);
+        assert_eq!(timeouts.idle, Some(Duration::from_secs(30)));
+    }
+
+    #[tokio::test]
+    async fn reports_a_stall_after_the_last_chunk() {
+        let chunks = futures::stream::iter([Ok::<_, reqwest::Error>("a"), Ok("b")])
+            .chain(futures::stream::pending());
+        let mut body = timeouts(1_000, 20).watch_body(Instant::now(), Box::pin(chunks));
+
+        assert_eq!(body.next().await.unwrap().unwrap(), "a");
+        assert_eq!(body.next().await.unwrap().unwrap(), "b");
+        let error = body.next().await.unwrap().unwrap_err();
+        assert_eq!(error.kind, ApiErrorKind::Timeout);
+        assert!(error.message.starts_with("stream stalled"), "{error}");
+        assert!(body.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn waits_longer_for_the_first_chunk() {
+        let chunks = futures::stream::once(async {
+            tokio::time::sleep(Duration::from_millis(40)).await;
+            Ok::<_, reqwest::Error>("late")
+        });
+        let mut body = timeouts(1_000, 10).watch_body(Instant::now(), Box::pin(chunks));
+        assert_eq!(body.next().await.unwrap().unwrap(), "late");
+        assert!(body.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn counts_the_header_wait_against_the_first_byte_limit() {
+        let sent = Instant::now() - Duration::from_millis(900);
+        let chunks = futures::stream::once(async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            Ok::<_, reqwest::Error>("late")
+        });
+        let mut body = timeouts(1_000, 1_000).watch_body(sent, Box::pin(chunks));
+        let error = body.next().await.unwrap().unwrap_err();
+        assert_eq!(error.kind, ApiErrorKind::Timeout);
+        assert!(error.message.starts_with("no response data"), "{error}");
+    }
+}
@@ -0,0 +1,269 @@
+//! Token estimates used to fit conversation history into a model's context.
+//!
+//! Counts come from the tiktoken BPE matching the model (`cl100k_base` for
+//! the GPT-4/3.5 family, `o200k_base` otherwise). For non-OpenAI models the
+//! result is an approximation, which is good enough for budgeting.
+
+use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+use tiktoken_rs::{cl100k_base_singleton, o200k_base_singleton, CoreBPE};
+
+use crate::capture::PreparedImage;
+use crate::config::ImageDetail;
+use crate::provider::AnalyzeRequest;
+use crate::session::{ConversationEntry, ConversationRole};
+
+/// Per-message framing overhead (role and separators) in chat formats.
+const TOKENS_PER_MESSAGE: usize = 4;
+/// Tokens priming the assistant reply.
+const REPLY_PRIMING_TOKENS: usize = 3;
+/// Base cost of an image, and the whole cost at `detail: low`.
+const IMAGE_BASE_TOKENS: usize = 85;
+/// Cost of each 512 px tile at `detail: high`.
+const IMAGE_TILE_TOKENS: usize = 170;
+/// Output space kept free when `max_output_tokens` is unset.
+const DEFAULT_OUTPUT_RESERVE: usize = 4_096;
+
+fn bpe_for(model: &str) -> &'static CoreBPE {
+    match get_tokenizer(model) {
+        Some(Tokenizer::Cl100kBase) => cl100k_base_singleton(),
+        _ => o200k_base_singleton(),
+    }
+}
+
+pub fn count_tokens(model: &str, text: &str) -> usize {
+    if text.is_empty() {
+        return 0;
+    }
+    bpe_for(model).encode_with_special_tokens(text).len()
+}
+
+/// Longest suffix of `text` that fits in `max_tokens`, cut at a character
+/// boundary. Whisper-style prompts only use their final tokens, so the end
+/// is the part worth keeping.
+pub fn last_tokens<'a>(model: &str, text: &'a str, max_tokens: usize) -> &'a str {
+    if count_tokens(model, text) <= max_tokens {
+        return text;
+    }
+    let boundaries: Vec<usize> = text
+        .char_indices()
+        .map(|(index, _)| index)
+        .chain([text.len()])
+        .collect();
+    // Binary search for the first boundary whose suffix still fits.
+    let (mut too_long, mut fits) = (0, boundaries.len() - 1);
+    while fits - too_long > 1 {
+        let middle = (too_long + fits) / 2;
+        if count_tokens(model, &text[boundaries[middle]..]) <= max_tokens {
+            fits = middle;
+        } else {
+            too_long = middle;
+        }
+    }
+    &text[boundaries[fits]..]
+}
+
+/// Cost of a `width`×`height` image in OpenAI's accounting. The image is
+/// fitted into 2048×2048, its short side scaled down to 768 px and the
+/// result covered with 512 px tiles. `auto` is counted like `high`.
+pub fn image_tokens(width: u32, height: u32, detail: ImageDetail) -> usize {
+    if detail == ImageDetail::Low || width == 0 || height == 0 {
+        return IMAGE_BASE_TOKENS;
+    }
+    let (mut width, mut height) = (width as f64, height as f64);
+    let fit = (2048.0 / width.max(height)).min(1.0);
+    width *= fit;
+    height *= fit;
+    let shrink = (768.0 / width.min(height)).min(1.0);
+    width *= shrink;
+    height *= shrink;
+    let tiles = (width / 512.0).ceil() as usize * (height / 512.0).ceil() as usize;
+    IMAGE_BASE_TOKENS + tiles * IMAGE_TILE_TOKENS
+}
+
+/// Everything that goes into one request, borrowed from wherever it lives.
+pub struct PromptParts<'a> {
+    pub model: &'a str,
+    pub context_window: usize,
+    pub max_output_tokens: Option<u32>,
+    pub system_prompt: Option<&'a str>,
+    pub text_prompt: &'a str,
+    /// Combined cost of the attached images.
+    pub image_tokens: usize,
+    /// Oldest first.
+    pub history: Vec<&'a ConversationEntry>,
+}
+
+impl<'a> PromptParts<'a> {
+    pub fn from_request(request: &'a AnalyzeRequest) -> Self {
+        Self {
+            model: &request.config.model,
+            context_window: request.capabilities.context_window,
+            max_output_tokens: request
+                .capabilities
+                .clamp_output_tokens(request.config.max_output_tokens),
+            system_prompt: request.custom_prompt.as_deref(),
+            text_prompt: &request.text_prompt,
+            image_tokens: request
+                .images
+                .iter()
+                .map(PreparedImage::estimated_tokens)
+                .sum(),
+            history: request.history.iter().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PromptEstimate {
+    /// Estimated prompt size including the history that fits.
+    pub tokens: usize,
+    /// Tokens available for the prompt: context window minus output reserve.
+    pub budget: usize,
+    pub history_included: usize,
+    pub history_total: usize,
+}
+
+impl PromptEstimate {
+    pub fn history_trimmed(&self) -> bool {
+        self.history_included < self.history_total
+    }
+}
+
+pub struct BudgetedHistory<'a> {
+    /// The most recent entries that fit, oldest first.
+    pub entries: Vec<&'a ConversationEntry>,
+    pub estimate: PromptEstimate,
+}
+
+/// Entries that never reach the API: tool transcripts and empty placeholders.
+pub(crate) fn is_sendable(entry: &ConversationEntry) -> bool {
+    !matches!(entry.role, ConversationRole::Tool) && !entry.content.trim().is_empty()
+}
+
+fn message_tokens(model: &str, text: &str) -> usize {
+    TOKENS_PER_MESSAGE + count_tokens(model, text)
+}
+
+/// Keeps the newest history entries whose combined size, together with the
+/// system prompt, question and images, stays inside the model's context
+/// window minus the space reserved for the answer.
+pub fn budget_history<'a>(parts: &PromptParts<'a>) -> BudgetedHistory<'a> {
+    let reserve = parts
+        .max_output_tokens
+        .map(|tokens| tokens as usize)
+        .unwrap_or(DEFAULT_OUTPUT_RESERVE);
+    let budget = parts.context_window.saturating_sub(reserve);
+
+    let mut used = REPLY_PRIMING_TOKENS
+        + message_tokens(parts.model, parts.text_prompt.trim())
+        + parts.image_tokens;
+    if let Some(prompt) = parts.system_prompt.filter(|p| !p.trim().is_empty()) {
+        used += message_tokens(parts.model, prompt.trim());
+    }
+
+    let sendable: Vec<&ConversationEntry> = parts
+        .history
+        .iter()
+        .copied()
+        .filter(|e| is_sendable(e))
+        .collect();
+    let mut entries = Vec::new();
+    for entry in sendable.iter().rev() {
+        let cost = message_tokens(parts.model, &entry.content);
+        if used + cost > budget {
+            break;
+        }
+        used += cost;
+        entries.push(*entry);
+    }
+    entries.reverse();
+
+    BudgetedHistory {
+        estimate: PromptEstimate {
+            tokens: used,
+            budget,
+            history_included: entries.len(),
+            history_total: sendable.len(),
+        },
+        entries,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts<'a>(
+        model: &'a str,
+        context_window: usize,
+        history: &'a [ConversationEntry],
+    ) -> PromptParts<'a> {
+        PromptParts {
+            model,
+            context_window,
+            max_output_tokens: None,
+            system_prompt: None,
+            text_prompt: "what now?",
+            image_tokens: 0,
+            history: history.iter().collect(),
+        }
+    }
+
+    #[test]
+    fn counts_with_model_tokenizer() {
+        assert_eq!(count_tokens("gpt-4o", "hello world"), 2);
+        assert_eq!(count_tokens("gpt-3.5-turbo", "hello world"), 2);
+        assert_eq!(count_tokens("gpt-4o", ""), 0);
+    }
+
+    #[test]
+    fn keeps_the_last_tokens() {
+        let text = "普羅米修斯 Kubernetes etcd Prometheus Grafana";
+        assert_eq!(last_tokens("gpt-4o", text, 1_000), text);
+        let cut = last_tokens("gpt-4o", text, 4);
+        assert!(text.ends_with(cut));
+        assert!(count_tokens("gpt-4o", cut) <= 4);
+        assert!(count_tokens("gpt-4o", &text[text.len() - cut.len() - 1..]) > 4);
+        assert_eq!(last_tokens("gpt-4o", text, 0), "");
+    }
+
+    #[test]
+    fn prices_images_by_tiles() {
+        assert_eq!(image_tokens(3840, 2160, ImageDetail::Low), 85);
+        // 1024×1024 becomes 768×768: four tiles.
+        assert_eq!(image_tokens(1024, 1024, ImageDetail::High), 765);
+        // 4K and 1080p both end up 1365×768: six tiles.
+        assert_eq!(image_tokens(3840, 2160, ImageDetail::Auto), 1105);
+        assert_eq!(image_tokens(1920, 1080, ImageDetail::High), 1105);
+        assert_eq!(image_tokens(512, 256, ImageDetail::High), 255);
+    }
+
+    #[test]
+    fn keeps_short_history_entirely() {
+        let history: Vec<_> = (0..50)
+            .map(|i| ConversationEntry::new(ConversationRole::User, format!("message {i}")))
+            .collect();
+        let budgeted = budget_history(&parts("gpt-4o", 128_000, &history));
+        assert_eq!(budgeted.entries.len(), 50);
+        assert!(!budgeted.estimate.history_trimmed());
+    }
+
+    #[test]
+    fn drops_oldest_entries_when_over_budget() {
+        // 8k of context, minus the 4k default output reserve.
+        let huge = "lorem ipsum ".repeat(3_000);
+        let history = vec![
+            ConversationEntry::new(ConversationRole::User, huge.clone()),
+            ConversationEntry::new(ConversationRole::Assistant, huge),
+            ConversationEntry::new(ConversationRole::User, "short"),
+            ConversationEntry::new(ConversationRole::Tool, "ignored"),
+        ];
+        let budgeted = budget_history(&parts("gpt-4", 8_192, &history));
+
+        assert_eq!(budgeted.entries.len(), 1);
+        assert_eq!(budgeted.entries[0].content, "short");
+        assert_eq!(budgeted.estimate.history_total, 3);
+        assert!(budgeted.estimate.history_trimmed());
+        assert!(budgeted.estimate.tokens <= budgeted.estimate.budget);
+    }
+}
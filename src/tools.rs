@@ -0,0 +1,364 @@
+//! Local tools the model can call during an answer.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use chrono::Local;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::capture::{capture_screen, prepare_upload, PreparedImage};
+use crate::config::{CaptureSettings, ToolSettings};
+
+/// Largest file the `read_file` tool returns, to keep tool output inside the
+/// model's context.
+const MAX_FILE_BYTES: u64 = 64 * 1024;
+
+/// Time the window manager gets to hide the window before a capture.
+const HIDE_DELAY: Duration = Duration::from_millis(200);
+
+/// Shows (`true`) or hides (`false`) the app window around a screenshot.
+pub type SetWindowVisible = Arc<dyn Fn(bool) + Send + Sync>;
+
+/// JSON-schema description of a tool, as advertised to the model.
+#[derive(Debug, Clone)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+/// Result of a tool call. Images are forwarded to the model as a follow-up
+/// user message because tool messages can only carry text.
+#[derive(Debug, Clone, Default)]
+pub struct ToolOutput {
+    pub text: String,
+    pub image: Option<PreparedImage>,
+}
+
+impl ToolOutput {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            image: None,
+        }
+    }
+}
+
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn definition(&self) -> ToolDefinition;
+
+    async fn call(&self, arguments: Value) -> Result<ToolOutput>;
+}
+
+#[derive(Default, Clone)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl std::fmt::Debug for ToolRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.tools.iter().map(|tool| tool.definition().name))
+            .finish()
+    }
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with the built-in local tools enabled by `settings`. The
+    /// screenshot tool captures and encodes like the capture hotkey, hiding
+    /// the window through `set_visible` when `capture` asks for it.
+    pub fn builtin(
+        settings: &ToolSettings,
+        capture: &CaptureSettings,
+        set_visible: Option<SetWindowVisible>,
+    ) -> Self {
+        let mut registry = Self::new();
+        registry.register(Arc::new(CurrentTimeTool));
+        registry.register(Arc::new(ClipboardTool));
+        registry.register(Arc::new(ScreenshotTool {
+            settings: capture.clone(),
+            set_visible,
+        }));
+        let allowed_dirs: Vec<String> = settings
+            .allowed_dirs
+            .iter()
+            .filter(|dir| !dir.trim().is_empty())
+            .cloned()
+            .collect();
+        if !allowed_dirs.is_empty() {
+            registry.register(Arc::new(ReadFileTool::new(&allowed_dirs)));
+        }
+        registry
+    }
+
+    pub fn register(&mut self, tool: Arc<dyn Tool>) {
+        let name = tool.definition().name;
+        self.tools
+            .retain(|existing| existing.definition().name != name);
+        self.tools.push(tool);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        self.tools.iter().map(|tool| tool.definition()).collect()
+    }
+
+    /// Runs the named tool with the raw JSON argument string from the model.
+    pub async fn call(&self, name: &str, arguments: &str) -> Result<ToolOutput> {
+        let tool = self
+            .tools
+            .iter()
+            .find(|tool| tool.definition().name == name)
+            .ok_or_else(|| anyhow!("unknown tool '{name}'"))?;
+        let arguments = if arguments.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str(arguments)
+                .with_context(|| format!("invalid arguments for tool '{name}'"))?
+        };
+        tool.call(arguments).await
+    }
+}
+
+struct CurrentTimeTool;
+
+#[async_trait]
+impl Tool for CurrentTimeTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "current_time".into(),
+            description: "Returns the current local date, time and UTC offset.".into(),
+            parameters: json!({ "type": "object", "properties": {} }),
+        }
+    }
+
+    async fn call(&self, _arguments: Value) -> Result<ToolOutput> {
+        Ok(ToolOutput::text(Local::now().to_rfc3339()))
+    }
+}
+
+struct ClipboardTool;
+
+#[async_trait]
+impl Tool for ClipboardTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "read_clipboard".into(),
+            description: "Returns the text currently on the user's clipboard.".into(),
+            parameters: json!({ "type": "object", "properties": {} }),
+        }
+    }
+
+    async fn call(&self, _arguments: Value) -> Result<ToolOutput> {
+        let text = tokio::task::spawn_blocking(|| -> Result<String> {
+            let mut clipboard = arboard::Clipboard::new().context("clipboard unavailable")?;
+            clipboard
+                .get_text()
+                .context("clipboard does not contain text")
+        })
+        .await??;
+        Ok(ToolOutput::text(text))
+    }
+}
+
+struct ScreenshotTool {
+    settings: CaptureSettings,
+    set_visible: Option<SetWindowVisible>,
+}
+
+#[async_trait]
+impl Tool for ScreenshotTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "capture_screenshot".into(),
+            description: "Captures the user's screen so you can look at it.".into(),
+            parameters: json!({ "type": "object", "properties": {} }),
+        }
+    }
+
+    async fn call(&self, _arguments: Value) -> Result<ToolOutput> {
+        let hide = self
+            .set_visible
+            .as_ref()
+            .filter(|_| self.settings.hide_before_capture);
+        if let Some(set_visible) = hide {
+            set_visible(false);
+            tokio::time::sleep(HIDE_DELAY).await;
+        }
+        let mode = self.settings.mode.clone();
+        let capture = tokio::task::spawn_blocking(move || capture_screen(mode)).await;
+        if let Some(set_visible) = hide {
+            set_visible(true);
+        }
+        let capture = capture??;
+
+        let upload = self.settings.upload.clone();
+        let image =
+            tokio::task::spawn_blocking(move || prepare_upload(&capture.png_bytes, &upload))
+                .await??;
+        Ok(ToolOutput {
+            text: format!(
+                "Captured a screenshot, sent at {}x{}; it is attached in the next message.",
+                image.width, image.height
+            ),
+            image: Some(image),
+        })
+    }
+}
+
+struct ReadFileTool {
+    allowed_dirs: Vec<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct ReadFileArgs {
+    path: String,
+}
+
+impl ReadFileTool {
+    fn new(allowed_dirs: &[String]) -> Self {
+        Self {
+            allowed_dirs: allowed_dirs.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    /// Resolves `path` (absolute, or relative to the first allowed directory)
+    /// and rejects anything that escapes the allow-list, including via `..`
+    /// or symlinks.
+    fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let requested = Path::new(path);
+        let candidate = if requested.is_absolute() {
+            requested.to_path_buf()
+        } else {
+            self.allowed_dirs
+                .first()
+                .context("no allowed directories configured")?
+                .join(requested)
+        };
+        let resolved = candidate
+            .canonicalize()
+            .with_context(|| format!("cannot open {}", candidate.display()))?;
+        let allowed = self
+            .allowed_dirs
+            .iter()
+            .filter_map(|dir| dir.canonicalize().ok())
+            .any(|dir| resolved.starts_with(dir));
+        if !allowed {
+            bail!("{} is outside the allowed directories", resolved.display());
+        }
+        Ok(resolved)
+    }
+}
+
+#[async_trait]
+impl Tool for ReadFileTool {
+    fn definition(&self) -> ToolDefinition {
+        let dirs: Vec<String> = self
+            .allowed_dirs
+            .iter()
+            .map(|dir| dir.display().to_string())
+            .collect();
+        ToolDefinition {
+            name: "read_file".into(),
+            description: format!(
+                "Reads a UTF-8 text file (up to {} KiB). Only files under these directories \
+                 are readable: {}. Relative paths resolve against the first one.",
+                MAX_FILE_BYTES / 1024,
+                dirs.join(", ")
+            ),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "File path to read" }
+                },
+                "required": ["path"]
+            }),
+        }
+    }
+
+    async fn call(&self, arguments: Value) -> Result<ToolOutput> {
+        let args: ReadFileArgs =
+            serde_json::from_value(arguments).context("expected a 'path' string argument")?;
+        let path = self.resolve(&args.path)?;
+        let metadata = tokio::fs::metadata(&path).await?;
+        if !metadata.is_file() {
+            bail!("{} is not a file", path.display());
+        }
+        if metadata.len() > MAX_FILE_BYTES {
+            bail!(
+                "{} is {} bytes; the limit is {MAX_FILE_BYTES}",
+                path.display(),
+                metadata.len()
+            );
+        }
+        let bytes = tokio::fs::read(&path).await?;
+        Ok(ToolOutput::text(
+            String::from_utf8_lossy(&bytes).into_owned(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ghost-tools-{name}-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn read_file_returns_allowed_file() {
+        let dir = temp_dir("allowed");
+        std::fs::write(dir.join("notes.md"), "hello").unwrap();
+        let tool = ReadFileTool::new(&[dir.display().to_string()]);
+
+        let output = tool.call(json!({ "path": "notes.md" })).await.unwrap();
+
+        assert_eq!(output.text, "hello");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_file_rejects_paths_outside_allow_list() {
+        let allowed = temp_dir("inside");
+        let outside = temp_dir("outside");
+        std::fs::write(outside.join("secret.txt"), "nope").unwrap();
+        let tool = ReadFileTool::new(&[allowed.display().to_string()]);
+
+        let escaped = format!(
+            "../{}/secret.txt",
+            outside.file_name().unwrap().to_string_lossy()
+        );
+        assert!(tool.call(json!({ "path": escaped })).await.is_err());
+        let absolute = outside.join("secret.txt").display().to_string();
+        assert!(tool.call(json!({ "path": absolute })).await.is_err());
+
+        std::fs::remove_dir_all(allowed).unwrap();
+        std::fs::remove_dir_all(outside).unwrap();
+    }
+
+    #[tokio::test]
+    async fn registry_dispatches_by_name() {
+        let registry =
+            ToolRegistry::builtin(&ToolSettings::default(), &CaptureSettings::default(), None);
+
+        let output = registry.call("current_time", "").await.unwrap();
+
+        assert!(chrono::DateTime::parse_from_rfc3339(&output.text).is_ok());
+        assert!(registry.call("does_not_exist", "{}").await.is_err());
+    }
+}
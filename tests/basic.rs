@@ -1,4 +1,9 @@
-use ghost_ai::config::{AppConfig, CaptureMode, ThemeVariant};
+use ghost_ai::config::{
+    AppConfig, CaptureMode, CompareTarget, EndpointSettings, ProviderKind, ThemeVariant,
+    TranscriptionLanguage, TranscriptionSettings,
+};
+use ghost_ai::session::{Citation, ConversationEntry, ConversationRole, SessionManager};
+use ghost_ai::usage::TokenUsage;
 
 #[test]
 fn default_openai_config_values_are_expected() {
@@ -38,6 +43,26 @@ fn transcription_is_enabled_by_default() {
     assert_eq!(config.transcription.model, "whisper-1");
 }
 
+#[test]
+fn voice_input_fills_the_question_and_records_with_ctrl_shift_m() {
+    let config = AppConfig::default();
+
+    assert!(!config.transcription.auto_submit);
+    assert_eq!(config.hotkeys.toggle_recording, "Ctrl+Shift+M");
+}
+
+#[test]
+fn transcription_language_accepts_any_code_or_auto() {
+    let settings: TranscriptionSettings = serde_json::from_str(r#"{"language": "zh"}"#).unwrap();
+    assert_eq!(settings.language.code(), Some("zh"));
+    assert!(!settings.use_active_prompt);
+
+    assert_eq!(TranscriptionLanguage::new(" NL ").code(), Some("nl"));
+    assert_eq!(TranscriptionLanguage::auto().code(), None);
+    assert_eq!(TranscriptionLanguage::new("").code(), None);
+    assert_eq!(TranscriptionLanguage::default().label(), "English (en)");
+}
+
 #[test]
 fn prompts_default_names_are_none() {
     let config = AppConfig::default();
@@ -45,3 +70,190 @@ fn prompts_default_names_are_none() {
     assert!(config.prompts.default_prompt_name.is_none());
     assert!(config.prompts.active_prompt_name.is_none());
 }
+
+#[test]
+fn tools_are_off_until_enabled() {
+    let config = AppConfig::default();
+    assert!(!config.tools.enabled);
+
+    let config: AppConfig = serde_json::from_str(r#"{"tools": {"max_rounds": 3}}"#).unwrap();
+    assert!(!config.tools.enabled);
+    assert_eq!(config.tools.max_rounds, 3);
+}
+
+#[test]
+fn provider_defaults_to_openai() {
+    let config = AppConfig::default();
+
+    assert_eq!(config.provider, ProviderKind::OpenAI);
+    assert_eq!(config.anthropic.base_url, "https://api.anthropic.com/v1");
+    assert_eq!(config.active_llm_config().model, config.openai.model);
+}
+
+#[test]
+fn anthropic_llm_config_swaps_credentials_but_shares_sampling() {
+    let mut config = AppConfig {
+        provider: ProviderKind::Anthropic,
+        ..AppConfig::default()
+    };
+    config.anthropic.api_key = "sk-ant-test".into();
+    config.openai.temperature = 0.3;
+
+    let resolved = config.active_llm_config();
+
+    assert_eq!(resolved.api_key, "sk-ant-test");
+    assert_eq!(resolved.base_url, config.anthropic.base_url);
+    assert_eq!(resolved.model, config.anthropic.model);
+    assert!((resolved.temperature - 0.3).abs() < f32::EPSILON);
+}
+
+#[test]
+fn compare_config_uses_the_target_provider_and_model() {
+    let mut config = AppConfig::default();
+    config.openai.api_key = "sk-test".into();
+    config.anthropic.api_key = "sk-ant-test".into();
+
+    let claude = config.compare_config(&CompareTarget {
+        provider: ProviderKind::Anthropic,
+        model: "claude-3-5-haiku-latest".into(),
+    });
+    let gpt = config.compare_config(&CompareTarget {
+        provider: ProviderKind::OpenAI,
+        model: "gpt-4.1-mini".into(),
+    });
+
+    assert_eq!(claude.api_key, "sk-ant-test");
+    assert_eq!(claude.model, "claude-3-5-haiku-latest");
+    assert_eq!(gpt.api_key, "sk-test");
+    assert_eq!(gpt.model, "gpt-4.1-mini");
+}
+
+#[test]
+fn summary_model_is_set_per_provider() {
+    let mut config = AppConfig::default();
+    config.compaction.model = "gpt-4o-mini".into();
+
+    let summary_model = |provider| config.compaction.summary_model(provider, "answer-model");
+    assert_eq!(summary_model(ProviderKind::OpenAI), "gpt-4o-mini");
+    assert_eq!(summary_model(ProviderKind::Anthropic), "answer-model");
+
+    config.compaction.anthropic_model = " claude-3-5-haiku-latest ".into();
+    assert_eq!(
+        config
+            .compaction
+            .summary_model(ProviderKind::Anthropic, "answer-model"),
+        "claude-3-5-haiku-latest"
+    );
+}
+
+#[test]
+fn summary_replaces_covered_entries_in_request_history_only() {
+    let dir = std::env::temp_dir().join(format!("ghost-basic-{}", uuid::Uuid::new_v4()));
+    let session = SessionManager::new(dir.clone()).unwrap();
+    let entries: Vec<ConversationEntry> = ["a", "b", "c"]
+        .into_iter()
+        .map(|text| ConversationEntry::new(ConversationRole::User, text))
+        .collect();
+    for entry in &entries {
+        session.append(entry.clone());
+    }
+
+    session.apply_summary("a and b happened".into(), [entries[0].id, entries[1].id]);
+    let history = session.request_history(&entries);
+
+    assert_eq!(history.len(), 2);
+    assert!(matches!(history[0].role, ConversationRole::System));
+    assert_eq!(history[0].content, "a and b happened");
+    assert_eq!(history[1].content, "c");
+    assert_eq!(session.entries().len(), 3);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn session_usage_totals_sum_answers() {
+    let dir = std::env::temp_dir().join(format!("ghost-basic-{}", uuid::Uuid::new_v4()));
+    let session = SessionManager::new(dir.clone()).unwrap();
+    for (prompt, cost) in [(100, Some(0.5)), (40, None)] {
+        let mut entry = ConversationEntry::new(ConversationRole::Assistant, "answer");
+        entry.usage = Some(TokenUsage {
+            prompt_tokens: prompt,
+            completion_tokens: 10,
+            ..TokenUsage::default()
+        });
+        entry.cost_usd = cost;
+        session.append(entry);
+    }
+
+    let (usage, cost) = session.usage_totals();
+    assert_eq!(usage.prompt_tokens, 140);
+    assert_eq!(usage.total(), 160);
+    assert!((cost - 0.5).abs() < f64::EPSILON);
+
+    // A history summary adds no entry but is billed all the same.
+    let summary = TokenUsage {
+        prompt_tokens: 900,
+        completion_tokens: 100,
+        ..TokenUsage::default()
+    };
+    session.add_usage(summary, Some(0.25));
+    let (usage, cost) = session.usage_totals();
+    assert_eq!(usage.total(), 1_160);
+    assert!((cost - 0.75).abs() < f64::EPSILON);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn session_logs_list_citations() {
+    let dir = std::env::temp_dir().join(format!("ghost-basic-{}", uuid::Uuid::new_v4()));
+    let session = SessionManager::new(dir.clone()).unwrap();
+    let mut entry = ConversationEntry::new(ConversationRole::Assistant, "Sunny.");
+    for (url, title) in [
+        ("https://a.example", Some("Forecast")),
+        ("https://a.example", Some("Forecast again")),
+        ("https://b.example", None),
+    ] {
+        entry.add_citation(Citation {
+            url: url.into(),
+            title: title.map(Into::into),
+        });
+    }
+    session.append(entry);
+
+    let txt_path = session.write_plaintext_log().unwrap();
+    let text = std::fs::read_to_string(&txt_path).unwrap();
+    assert!(text.contains("[Sources]\n[1] Forecast <https://a.example>\n[2] https://b.example\n"));
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(txt_path.with_extension("json")).unwrap())
+            .unwrap();
+    let citations = &json["entries"][0]["citations"];
+    assert_eq!(citations.as_array().unwrap().len(), 2);
+    assert_eq!(
+        citations[1],
+        serde_json::json!({ "url": "https://b.example" })
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn endpoint_headers_are_stored_as_an_object_and_checked_on_save() {
+    let mut endpoint: EndpointSettings =
+        serde_json::from_str(r#"{"headers": {"OpenAI-Project": "proj_1"}}"#).unwrap();
+    endpoint
+        .headers
+        .push((" X-Gateway ".into(), "token".into()));
+    endpoint.headers.push((String::new(), String::new()));
+    endpoint.tidy_headers().unwrap();
+    let json = serde_json::to_value(&endpoint).unwrap();
+    assert_eq!(
+        json["headers"],
+        serde_json::json!({ "OpenAI-Project": "proj_1", "X-Gateway": "token" })
+    );
+
+    endpoint
+        .headers
+        .push(("openai-project".into(), "proj_2".into()));
+    assert!(endpoint.tidy_headers().is_err());
+    endpoint.headers.pop();
+    endpoint.headers.push((String::new(), "orphan".into()));
+    assert!(endpoint.tidy_headers().is_err());
+}
@@ -0,0 +1,250 @@
+//! Scripted stand-in for the OpenAI HTTP API.
+//!
+//! Each route answers with its queued responses in order and keeps repeating
+//! the last one, so a test can script "fail once, then succeed". Every request
+//! is recorded for assertions on URLs, headers and bodies.
+
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use reqwest::StatusCode;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+    /// Keep the connection open after the body instead of ending it.
+    pub stall: bool,
+}
+
+impl MockResponse {
+    pub fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: body.to_string().into_bytes(),
+            stall: false,
+        }
+    }
+
+    /// Chat Completions style stream: `data:` lines ending with `[DONE]`.
+    pub fn chat_sse(chunks: &[Value]) -> Self {
+        let mut body = String::new();
+        for chunk in chunks {
+            body.push_str(&format!("data: {chunk}\n\n"));
+        }
+        body.push_str("data: [DONE]\n\n");
+        Self::sse(body)
+    }
+
+    /// A recorded event stream from `tests/fixtures`.
+    pub fn fixture(name: &str) -> Self {
+        let path = format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"));
+        Self::sse(std::fs::read_to_string(&path).unwrap_or_else(|err| panic!("{path}: {err}")))
+    }
+
+    fn sse(body: String) -> Self {
+        Self {
+            status: 200,
+            content_type: "text/event-stream",
+            body: body.into_bytes(),
+            stall: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path and query, e.g. `/v1/chat/completions?api-version=1`.
+    pub target: String,
+    /// Header names are lowercase.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).expect("request body is JSON")
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+struct Route {
+    method: &'static str,
+    path: &'static str,
+    responses: VecDeque<MockResponse>,
+}
+
+#[derive(Default)]
+struct State {
+    routes: Vec<Route>,
+    requests: Vec<RecordedRequest>,
+}
+
+pub struct MockServer {
+    address: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State::default()));
+        let accept_state = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_connection(stream, accept_state.clone()));
+            }
+        });
+        Self { address, state }
+    }
+
+    /// Queues `responses` for `method` requests to `path` (relative to `/v1`).
+    pub fn route(
+        &self,
+        method: &'static str,
+        path: &'static str,
+        responses: impl IntoIterator<Item = MockResponse>,
+    ) -> &Self {
+        self.state.lock().routes.push(Route {
+            method,
+            path,
+            responses: responses.into_iter().collect(),
+        });
+        self
+    }
+
+    pub fn base_url(&self) -> String {
+        format!("http://{}/v1", self.address)
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().requests.clone()
+    }
+}
+
+async fn serve_connection(stream: TcpStream, state: Arc<Mutex<State>>) {
+    let mut reader = BufReader::new(stream);
+    let Some(request) = read_request(&mut reader).await else {
+        return;
+    };
+    let path = request
+        .target
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    let response = {
+        let mut state = state.lock();
+        state.requests.push(request.clone());
+        state
+            .routes
+            .iter_mut()
+            .find(|route| route.method == request.method && format!("/v1/{}", route.path) == path)
+            .and_then(|route| {
+                if route.responses.len() > 1 {
+                    route.responses.pop_front()
+                } else {
+                    route.responses.front().cloned()
+                }
+            })
+    };
+    let response = response.unwrap_or_else(|| {
+        MockResponse::json(
+            404,
+            serde_json::json!({ "error": { "message": "no route" } }),
+        )
+    });
+
+    let status = StatusCode::from_u16(response.status).unwrap();
+    // A stalled body has no length, so the client waits for more.
+    let length = if response.stall {
+        String::new()
+    } else {
+        format!("content-length: {}\r\n", response.body.len())
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\ncontent-type: {}\r\n{length}connection: close\r\n\r\n",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or(""),
+        response.content_type,
+    );
+    let stream = reader.get_mut();
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(&response.body).await;
+    if response.stall {
+        std::future::pending::<()>().await;
+    }
+    let _ = stream.shutdown().await;
+}
+
+async fn read_request(reader: &mut BufReader<TcpStream>) -> Option<RecordedRequest> {
+    let mut line = String::new();
+    reader.read_line(&mut line).await.ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).await.ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':')?;
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    };
+
+    let mut body = Vec::new();
+    if header("transfer-encoding").is_some_and(|value| value.eq_ignore_ascii_case("chunked")) {
+        loop {
+            let mut size = String::new();
+            reader.read_line(&mut size).await.ok()?;
+            let size = usize::from_str_radix(size.trim(), 16).ok()?;
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).await.ok()?;
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    } else if let Some(length) = header("content-length").and_then(|value| value.parse().ok()) {
+        body.resize(length, 0);
+        reader.read_exact(&mut body).await.ok()?;
+    }
+
+    Some(RecordedRequest {
+        method,
+        target,
+        headers,
+        body,
+    })
+}
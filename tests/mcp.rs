@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+use ghost_ai::config::McpServerConfig;
+use ghost_ai::mcp::{McpClient, McpManager, McpServerStatus};
+use serde_json::{json, Value};
+
+const ECHO_SERVER_ENV: &str = "GHOST_MCP_ECHO_SERVER";
+
+/// Tiny MCP server with one `echo` tool and one resource. It runs inside this
+/// test binary when re-executed with `ECHO_SERVER_ENV` set, so the client
+/// talks to a real child process over stdio.
+#[test]
+fn echo_server() {
+    if std::env::var_os(ECHO_SERVER_ENV).is_none() {
+        return;
+    }
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    // End the line libtest started with the test name; the client skips it.
+    writeln!(stdout).unwrap();
+    for line in stdin.lock().lines() {
+        let Ok(line) = line else { break };
+        let request: Value = serde_json::from_str(&line).unwrap();
+        let Some(id) = request.get("id").cloned() else {
+            continue;
+        };
+        let params = &request["params"];
+        let response = match request["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({
+                "result": {
+                    "protocolVersion": params["protocolVersion"],
+                    "capabilities": { "tools": {}, "resources": {} },
+                    "serverInfo": { "name": "echo", "version": "0.0.0" },
+                }
+            }),
+            "tools/list" => json!({
+                "result": { "tools": [{
+                    "name": "echo",
+                    "description": "Echoes its input",
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": { "text": { "type": "string" } },
+                    },
+                }] }
+            }),
+            "tools/call" => json!({
+                "result": {
+                    "content": [{ "type": "text", "text": params["arguments"]["text"] }],
+                    "isError": params["arguments"]["text"] == "fail",
+                }
+            }),
+            "resources/list" => json!({
+                "result": { "resources": [{ "uri": "memo://readme", "name": "readme" }] }
+            }),
+            "resources/read" => json!({
+                "result": { "contents": [{ "uri": params["uri"], "text": "echo resource" }] }
+            }),
+            method => json!({ "error": { "code": -32601, "message": method } }),
+        };
+        let mut response = response.as_object().unwrap().clone();
+        response.insert("jsonrpc".into(), json!("2.0"));
+        response.insert("id".into(), id);
+        writeln!(stdout, "{}", Value::Object(response)).unwrap();
+        stdout.flush().unwrap();
+    }
+}
+
+fn echo_server_config(name: &str) -> McpServerConfig {
+    let mut env = BTreeMap::new();
+    env.insert(ECHO_SERVER_ENV.to_string(), "1".to_string());
+    McpServerConfig {
+        name: name.to_string(),
+        command: std::env::current_exe().unwrap().display().to_string(),
+        args: vec![
+            "echo_server".into(),
+            "--exact".into(),
+            "--nocapture".into(),
+            "--test-threads=1".into(),
+        ],
+        env,
+        enabled: true,
+    }
+}
+
+#[tokio::test]
+async fn client_lists_and_calls_tools_over_stdio() {
+    let client = McpClient::spawn(&echo_server_config("echo")).await.unwrap();
+
+    let tools = client.list_tools().await.unwrap();
+    assert_eq!(tools.len(), 1);
+    assert_eq!(tools[0].name, "echo");
+
+    let output = client
+        .call_tool("echo", json!({ "text": "hi" }))
+        .await
+        .unwrap();
+    assert_eq!(output.text, "hi");
+    assert!(client
+        .call_tool("echo", json!({ "text": "fail" }))
+        .await
+        .is_err());
+
+    let resources = client.list_resources().await.unwrap();
+    assert_eq!(resources[0].uri, "memo://readme");
+    assert_eq!(
+        client.read_resource("memo://readme").await.unwrap(),
+        "echo resource"
+    );
+}
+
+#[tokio::test]
+async fn manager_exposes_enabled_servers_as_tools() {
+    let manager = McpManager::new();
+    let mut config = echo_server_config("echo docs");
+
+    manager.sync(std::slice::from_ref(&config)).await;
+    assert_eq!(
+        manager.status("echo docs"),
+        Some(McpServerStatus::Running {
+            tools: 1,
+            resources: 1
+        })
+    );
+
+    let mut registry = ghost_ai::tools::ToolRegistry::new();
+    for tool in manager.tools() {
+        registry.register(tool);
+    }
+    let names: Vec<String> = registry.definitions().into_iter().map(|d| d.name).collect();
+    assert!(names.contains(&"echo_docs__echo".to_string()));
+    assert!(names.contains(&"echo_docs__read_resource".to_string()));
+    let output = registry
+        .call("echo_docs__echo", r#"{"text":"from the model"}"#)
+        .await
+        .unwrap();
+    assert_eq!(output.text, "from the model");
+
+    config.enabled = false;
+    manager.sync(&[config]).await;
+    assert_eq!(manager.status("echo docs"), None);
+    assert!(manager.tools().is_empty());
+}
+
+#[test]
+fn duplicate_server_names_are_renamed() {
+    let mut settings = ghost_ai::config::McpSettings {
+        servers: vec![
+            echo_server_config("docs"),
+            echo_server_config("docs"),
+            echo_server_config("docs-2"),
+            echo_server_config("docs"),
+        ],
+    };
+    assert_eq!(settings.dedupe_names(), ["docs-3", "docs-4"]);
+    let names: Vec<&str> = settings.servers.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, ["docs", "docs-3", "docs-2", "docs-4"]);
+}
@@ -0,0 +1,818 @@
+mod common;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use common::{MockResponse, MockServer};
+use ghost_ai::audio::RecordingResult;
+use ghost_ai::capture::PreparedImage;
+use ghost_ai::compaction;
+use ghost_ai::config::{
+    ImageDetail, OpenAIConfig, ProviderKind, RetrySettings, TimeoutSettings, TranscriptionLanguage,
+    UploadFormat,
+};
+use ghost_ai::error::{ApiError, ApiErrorKind};
+use ghost_ai::models;
+use ghost_ai::openai::OpenAIClient;
+use ghost_ai::provider::{AnalyzeRequest, Provider, StreamEvent};
+use ghost_ai::session::{ConversationEntry, ConversationRole};
+use ghost_ai::tools::{Tool, ToolDefinition, ToolOutput, ToolRegistry};
+use ghost_ai::usage::TokenUsage;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+fn config(server: &MockServer, model: &str) -> OpenAIConfig {
+    let mut config = OpenAIConfig {
+        api_key: "test-key".into(),
+        base_url: server.base_url(),
+        model: model.into(),
+        retry: RetrySettings {
+            max_retries: 2,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+        },
+        ..OpenAIConfig::default()
+    };
+    // The mock server stands in for api.openai.com.
+    config.endpoint.stream_usage = Some(true);
+    config
+}
+
+fn request(server: &MockServer, model: &str) -> AnalyzeRequest {
+    AnalyzeRequest {
+        request_id: Uuid::new_v4(),
+        provider: ProviderKind::OpenAI,
+        config: config(server, model),
+        capabilities: models::capabilities(model, &BTreeMap::new()),
+        text_prompt: "What is on screen?".into(),
+        custom_prompt: Some("Be brief.".into()),
+        images: Vec::new(),
+        history: VecDeque::new(),
+        tools: None,
+        max_tool_rounds: 0,
+        cancel: CancellationToken::new(),
+    }
+}
+
+fn drain(rx: &mut mpsc::UnboundedReceiver<(Uuid, StreamEvent)>) -> Vec<StreamEvent> {
+    let mut events = Vec::new();
+    while let Ok((_, event)) = rx.try_recv() {
+        events.push(event);
+    }
+    events
+}
+
+#[tokio::test]
+async fn streams_chat_completion_deltas_and_usage() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "chat/completions",
+        [MockResponse::chat_sse(&[
+            json!({ "choices": [{ "delta": { "role": "assistant", "content": "Hel" } }] }),
+            json!({ "choices": [{ "delta": { "content": "lo" } }] }),
+            json!({
+                "choices": [],
+                "usage": {
+                    "prompt_tokens": 12,
+                    "completion_tokens": 2,
+                    "prompt_tokens_details": { "cached_tokens": 4 },
+                },
+            }),
+        ])],
+    );
+    let client = OpenAIClient::new().unwrap();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let response = client
+        .analyze_stream(request(&server, "gpt-4o-mini"), tx)
+        .await
+        .unwrap();
+
+    assert_eq!(response.answer, "Hello");
+    assert!(!response.interrupted);
+    assert_eq!(
+        response.usage,
+        Some(TokenUsage {
+            prompt_tokens: 12,
+            completion_tokens: 2,
+            reasoning_tokens: 0,
+            cached_tokens: 4,
+        })
+    );
+    let deltas: Vec<String> = drain(&mut rx)
+        .into_iter()
+        .filter_map(|event| match event {
+            StreamEvent::Delta(delta) => Some(delta),
+            StreamEvent::Done(text) => Some(format!("done:{text}")),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(deltas, ["Hel", "lo", "done:Hello"]);
+
+    let sent = &server.requests()[0];
+    assert_eq!(sent.header("authorization"), Some("Bearer test-key"));
+    let body = sent.json();
+    assert_eq!(body["model"], "gpt-4o-mini");
+    assert_eq!(body["stream"], true);
+    assert_eq!(body["stream_options"]["include_usage"], true);
+    assert_eq!(body["messages"][0]["role"], "system");
+}
+
+#[tokio::test]
+async fn streams_responses_api_reasoning_and_web_search() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "responses",
+        [MockResponse::fixture("responses_web_search.sse")],
+    );
+    let client = OpenAIClient::new().unwrap();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut request = request(&server, "gpt-5");
+    request.images = vec![PreparedImage {
+        bytes: vec![1, 2, 3],
+        format: UploadFormat::Jpeg,
+        width: 1,
+        height: 1,
+        detail: ImageDetail::Low,
+    }];
+
+    let response = client.analyze_stream(request, tx).await.unwrap();
+
+    assert_eq!(response.answer, "Sunny, 24 °C.");
+    assert!(!response.interrupted);
+    assert_eq!(
+        response.usage,
+        Some(TokenUsage {
+            prompt_tokens: 1520,
+            completion_tokens: 310,
+            reasoning_tokens: 192,
+            cached_tokens: 1024,
+        })
+    );
+    let events = drain(&mut rx);
+    let summary = "**Checking the forecast**\n\nSearching the web.";
+    assert!(matches!(&events[0], StreamEvent::ReasoningDelta(text) if summary.starts_with(text)));
+    assert!(matches!(events[3], StreamEvent::WebSearchInProgress));
+    assert!(matches!(events[4], StreamEvent::WebSearchSearching));
+    assert!(matches!(events[5], StreamEvent::WebSearchCompleted));
+    let citations: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            StreamEvent::Citation(citation) => Some(citation.url.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        citations,
+        [
+            "https://weather.example/berlin?utm_source=openai",
+            "https://weather.example/berlin?utm_source=openai",
+            "https://forecast.example/today",
+        ]
+    );
+    // The response carries them too, for the log written when it finishes.
+    let returned: Vec<&str> = response.citations.iter().map(|c| c.url.as_str()).collect();
+    assert_eq!(returned, citations);
+    assert!(matches!(&events[11], StreamEvent::ReasoningDone(text) if text == summary));
+    assert!(matches!(&events[12], StreamEvent::Done(text) if text == "Sunny, 24 °C."));
+
+    let body = server.requests()[0].json();
+    assert_eq!(body["model"], "gpt-5");
+    assert_eq!(body["stream"], true);
+    assert_eq!(body["instructions"], "Be brief.");
+    assert_eq!(body["tools"], json!([{ "type": "web_search" }]));
+    assert_eq!(
+        body["reasoning"],
+        json!({ "effort": "medium", "summary": "auto" })
+    );
+    let question = &body["input"][0]["content"];
+    assert_eq!(
+        question[0],
+        json!({ "type": "input_text", "text": "What is on screen?" })
+    );
+    assert_eq!(
+        question[1],
+        json!({
+            "type": "input_image",
+            "image_url": "data:image/jpeg;base64,AQID",
+            "detail": "low",
+        })
+    );
+}
+
+#[tokio::test]
+async fn keeps_the_answer_of_an_incomplete_response() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "responses",
+        [MockResponse::fixture("responses_incomplete.sse")],
+    );
+    let client = OpenAIClient::new().unwrap();
+    let (tx, _rx) = mpsc::unbounded_channel();
+
+    let response = client
+        .analyze_stream(request(&server, "gpt-5-mini"), tx)
+        .await
+        .unwrap();
+
+    assert_eq!(response.answer, "The first three steps are");
+    assert!(response.interrupted);
+    assert_eq!(response.usage.unwrap().completion_tokens, 16);
+}
+
+#[tokio::test]
+async fn marks_a_response_stream_cut_off_before_completion_as_interrupted() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "responses",
+        [MockResponse::fixture("responses_truncated.sse")],
+    );
+    let client = OpenAIClient::new().unwrap();
+    let (tx, _rx) = mpsc::unbounded_channel();
+
+    let response = client
+        .analyze_stream(request(&server, "gpt-5-mini"), tx)
+        .await
+        .unwrap();
+
+    assert_eq!(response.answer, "The connection dropped after");
+    assert!(response.interrupted);
+    assert_eq!(response.usage, None);
+}
+
+#[tokio::test]
+async fn marks_a_chat_stream_cut_off_before_completion_as_interrupted() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "chat/completions",
+        [MockResponse::fixture("chat_truncated.sse")],
+    );
+    let client = OpenAIClient::new().unwrap();
+    let (tx, _rx) = mpsc::unbounded_channel();
+
+    let response = client
+        .analyze_stream(request(&server, "gpt-4o-mini"), tx)
+        .await
+        .unwrap();
+
+    assert_eq!(response.answer, "The connection dropped after");
+    assert!(response.interrupted);
+}
+
+#[tokio::test]
+async fn marks_a_chat_answer_stopped_at_the_token_limit_as_interrupted() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "chat/completions",
+        [MockResponse::chat_sse(&[
+            json!({ "choices": [{ "delta": { "content": "A long" }, "finish_reason": null }] }),
+            json!({ "choices": [{ "delta": {}, "finish_reason": "length" }] }),
+        ])],
+    );
+    let client = OpenAIClient::new().unwrap();
+    let (tx, _rx) = mpsc::unbounded_channel();
+
+    let response = client
+        .analyze_stream(request(&server, "gpt-4o-mini"), tx)
+        .await
+        .unwrap();
+
+    assert_eq!(response.answer, "A long");
+    assert!(response.interrupted);
+}
+
+/// Answers every lookup with the same definition.
+struct LookupTool;
+
+#[async_trait]
+impl Tool for LookupTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "lookup".into(),
+            description: "Looks a term up.".into(),
+            parameters: json!({ "type": "object", "properties": { "term": { "type": "string" } } }),
+        }
+    }
+
+    async fn call(&self, arguments: Value) -> anyhow::Result<ToolOutput> {
+        Ok(ToolOutput::text(format!(
+            "{}: a spirit",
+            arguments["term"].as_str().unwrap()
+        )))
+    }
+}
+
+#[tokio::test]
+async fn runs_function_calls_of_the_responses_api() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "responses",
+        [
+            MockResponse::fixture("responses_function_call.sse"),
+            MockResponse::fixture("responses_after_function_call.sse"),
+        ],
+    );
+    let client = OpenAIClient::new().unwrap();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut request = request(&server, "gpt-5");
+    let mut tools = ToolRegistry::new();
+    tools.register(Arc::new(LookupTool));
+    request.tools = Some(Arc::new(tools));
+    request.max_tool_rounds = 1;
+
+    let response = client.analyze_stream(request, tx).await.unwrap();
+
+    assert_eq!(response.answer, "It means a spirit.");
+    assert!(!response.interrupted);
+    assert_eq!(response.usage.unwrap().prompt_tokens, 640);
+    assert!(drain(&mut rx).iter().any(|event| matches!(
+        event,
+        StreamEvent::ToolResult { output, .. } if output == "ghost: a spirit"
+    )));
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    let first = requests[0].json();
+    assert_eq!(first["tools"][1]["type"], "function");
+    assert_eq!(first["tools"][1]["name"], "lookup");
+    assert_eq!(first["tools"][1]["strict"], false);
+    assert!(first.get("tool_choice").is_none());
+    // That was the only tool round, so the follow-up asks for an answer.
+    let second = requests[1].json();
+    assert_eq!(second["tool_choice"], "none");
+    let input = second["input"].as_array().unwrap();
+    assert_eq!(
+        input[input.len() - 2],
+        json!({
+            "type": "function_call",
+            "call_id": "call_Kx2",
+            "name": "lookup",
+            "arguments": "{\"term\":\"ghost\"}",
+        })
+    );
+    assert_eq!(
+        input[input.len() - 1],
+        json!({
+            "type": "function_call_output",
+            "call_id": "call_Kx2",
+            "output": "ghost: a spirit",
+        })
+    );
+}
+
+/// Returns a prepared JPEG, as the screenshot tool does.
+struct SnapshotTool;
+
+#[async_trait]
+impl Tool for SnapshotTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "snapshot".into(),
+            description: "Takes a snapshot.".into(),
+            parameters: json!({ "type": "object", "properties": {} }),
+        }
+    }
+
+    async fn call(&self, _arguments: Value) -> anyhow::Result<ToolOutput> {
+        Ok(ToolOutput {
+            text: "Snapshot attached.".into(),
+            image: Some(PreparedImage {
+                bytes: vec![1, 2, 3],
+                format: UploadFormat::Jpeg,
+                width: 4,
+                height: 3,
+                detail: ImageDetail::Low,
+            }),
+        })
+    }
+}
+
+#[tokio::test]
+async fn sends_tool_images_with_their_own_format_and_detail() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "chat/completions",
+        [
+            MockResponse::chat_sse(&[json!({
+                "choices": [{
+                    "delta": { "tool_calls": [{
+                        "index": 0,
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "snapshot", "arguments": "{}" },
+                    }] },
+                    "finish_reason": "tool_calls",
+                }],
+            })]),
+            MockResponse::chat_sse(&[json!({
+                "choices": [{ "delta": { "content": "A cat." }, "finish_reason": "stop" }],
+            })]),
+        ],
+    );
+    let client = OpenAIClient::new().unwrap();
+    let (tx, _rx) = mpsc::unbounded_channel();
+    let mut request = request(&server, "gpt-4o-mini");
+    let mut tools = ToolRegistry::new();
+    tools.register(Arc::new(SnapshotTool));
+    request.tools = Some(Arc::new(tools));
+    request.max_tool_rounds = 1;
+
+    let response = client.analyze_stream(request, tx).await.unwrap();
+
+    assert_eq!(response.answer, "A cat.");
+    let second = server.requests()[1].json();
+    let messages = second["messages"].as_array().unwrap();
+    let image = &messages[messages.len() - 1]["content"][0]["image_url"];
+    assert_eq!(image["url"], "data:image/jpeg;base64,AQID");
+    assert_eq!(image["detail"], "low");
+}
+
+#[tokio::test]
+async fn types_failed_responses_and_error_events() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "responses",
+        [MockResponse::fixture("responses_failed.sse")],
+    );
+    let client = OpenAIClient::new().unwrap();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let error = client
+        .analyze_stream(request(&server, "gpt-5"), tx)
+        .await
+        .unwrap_err();
+
+    assert_eq!(ApiError::find(&error).unwrap().kind, ApiErrorKind::Server);
+    let events = drain(&mut rx);
+    assert!(matches!(&events[0], StreamEvent::Delta(text) if text == "Partial"));
+    assert!(matches!(&events[1], StreamEvent::Error(text) if text.contains("processing")));
+
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "responses",
+        [MockResponse::fixture("responses_error.sse")],
+    );
+    let (tx, _rx) = mpsc::unbounded_channel();
+
+    let error = client
+        .analyze_stream(request(&server, "gpt-5"), tx)
+        .await
+        .unwrap_err();
+
+    assert_eq!(
+        ApiError::find(&error).unwrap().kind,
+        ApiErrorKind::RateLimit
+    );
+}
+
+#[tokio::test]
+async fn analyze_returns_the_complete_answer() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "chat/completions",
+        [MockResponse::json(
+            200,
+            json!({
+                "model": "gpt-4o-mini-2024-07-18",
+                "choices": [{ "message": { "role": "assistant", "content": "A terminal." } }],
+                "usage": { "prompt_tokens": 9, "completion_tokens": 3 },
+            }),
+        )],
+    );
+    let client = OpenAIClient::new().unwrap();
+
+    let response = client
+        .analyze(request(&server, "gpt-4o-mini"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.answer, "A terminal.");
+    assert_eq!(response.model, "gpt-4o-mini-2024-07-18");
+    assert_eq!(response.usage.unwrap().total(), 12);
+    assert_ne!(server.requests()[0].json()["stream"], true);
+}
+
+#[tokio::test]
+async fn summarizes_history_with_a_responses_model() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "responses",
+        [MockResponse::json(
+            200,
+            json!({
+                "model": "gpt-5-mini-2025-08-07",
+                "status": "completed",
+                "output": [
+                    { "type": "reasoning", "summary": [] },
+                    {
+                        "type": "message",
+                        "role": "assistant",
+                        "content": [{
+                            "type": "output_text",
+                            "text": "The user asked what a ghost is.",
+                            "annotations": [],
+                        }],
+                    },
+                ],
+                "usage": { "input_tokens": 120, "output_tokens": 9 },
+            }),
+        )],
+    );
+    let client = Arc::new(OpenAIClient::new().unwrap());
+    let entries = [ConversationEntry::new(
+        ConversationRole::User,
+        "What is a ghost?",
+    )];
+
+    let (summary, usage) = compaction::summarize(
+        client,
+        ProviderKind::OpenAI,
+        config(&server, "gpt-4o"),
+        "gpt-5-mini",
+        models::capabilities("gpt-5-mini", &BTreeMap::new()),
+        None,
+        &entries,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(summary, "The user asked what a ghost is.");
+    assert_eq!(usage.unwrap().total(), 129);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    let body = requests[0].json();
+    assert_eq!(body["model"], "gpt-5-mini");
+    assert_eq!(body["stream"], false);
+    assert!(body["input"][0]["content"][0]["text"]
+        .as_str()
+        .unwrap()
+        .contains("What is a ghost?"));
+}
+
+#[tokio::test]
+async fn retries_server_errors_before_succeeding() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "chat/completions",
+        [
+            MockResponse::json(503, json!({ "error": { "message": "overloaded" } })),
+            MockResponse::json(
+                200,
+                json!({ "choices": [{ "message": { "content": "Recovered." } }] }),
+            ),
+        ],
+    );
+    let client = OpenAIClient::new().unwrap();
+
+    let response = client
+        .analyze(request(&server, "gpt-4o-mini"))
+        .await
+        .unwrap();
+
+    assert_eq!(response.answer, "Recovered.");
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn does_not_retry_an_exhausted_quota() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "chat/completions",
+        [
+            MockResponse::json(
+                429,
+                json!({
+                    "error": {
+                        "message": "You exceeded your current quota.",
+                        "type": "insufficient_quota",
+                        "code": "insufficient_quota",
+                    },
+                }),
+            ),
+            MockResponse::json(
+                200,
+                json!({ "choices": [{ "message": { "content": "Unreachable." } }] }),
+            ),
+        ],
+    );
+    let client = OpenAIClient::new().unwrap();
+
+    let error = client
+        .analyze(request(&server, "gpt-4o-mini"))
+        .await
+        .unwrap_err();
+
+    let api_error = ApiError::find(&error).unwrap();
+    assert_eq!(api_error.kind, ApiErrorKind::Quota);
+    assert_eq!(api_error.status.map(|status| status.as_u16()), Some(429));
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn reports_error_statuses_on_the_stream() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "chat/completions",
+        [MockResponse::json(
+            400,
+            json!({ "error": { "message": "bad request", "type": "invalid_request_error" } }),
+        )],
+    );
+    let client = OpenAIClient::new().unwrap();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    let error = client
+        .analyze_stream(request(&server, "gpt-4o-mini"), tx)
+        .await
+        .unwrap_err();
+
+    assert!(format!("{error:#}").contains("400"), "{error:#}");
+    let api_error = ApiError::find(&error).unwrap();
+    assert_eq!(api_error.kind, ApiErrorKind::InvalidRequest);
+    assert_eq!(api_error.message, "bad request");
+    assert!(drain(&mut rx)
+        .iter()
+        .any(|event| matches!(event, StreamEvent::Error(_))));
+    // Client errors are not retried.
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn transcribes_uploads_with_segments() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "audio/transcriptions",
+        [MockResponse::json(
+            200,
+            json!({
+                "text": "Ship it.",
+                "language": "english",
+                "duration": 1.2,
+                "segments": [{ "id": 0, "start": 0.0, "end": 1.2, "text": " Ship it." }],
+            }),
+        )],
+    );
+    let client = OpenAIClient::new().unwrap();
+    let recording = RecordingResult {
+        wav_bytes: b"RIFF....WAVE".to_vec(),
+        sample_rate: 16_000,
+        channels: 1,
+        samples: vec![0.0; 16],
+    };
+
+    let transcription = client
+        .transcribe(
+            &config(&server, "gpt-4o-mini"),
+            recording,
+            &TranscriptionLanguage::new("en"),
+            "whisper-1",
+            Some("Kubernetes"),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(transcription.text, "Ship it.");
+    assert_eq!(transcription.segments.len(), 1);
+    assert_eq!(transcription.duration, Some(1.2));
+    let sent = &server.requests()[0];
+    assert!(sent
+        .header("content-type")
+        .unwrap()
+        .starts_with("multipart/form-data"));
+    let form = sent.text();
+    for field in ["model", "response_format", "language", "prompt", "file"] {
+        assert!(
+            form.contains(&format!("name=\"{field}\"")),
+            "missing {field}"
+        );
+    }
+    assert!(form.contains("verbose_json"));
+}
+
+#[tokio::test]
+async fn lists_models_as_the_credential_check() {
+    let server = MockServer::start().await;
+    server.route(
+        "GET",
+        "models",
+        [MockResponse::json(
+            200,
+            json!({ "object": "list", "data": [{ "id": "gpt-4o" }, { "id": "dall-e-3" }] }),
+        )],
+    );
+    let client = OpenAIClient::new().unwrap();
+
+    let models = client
+        .list_models(&config(&server, "gpt-4o"))
+        .await
+        .unwrap();
+    assert_eq!(models, ["dall-e-3", "gpt-4o"]);
+
+    let rejected = MockServer::start().await;
+    rejected.route(
+        "GET",
+        "models",
+        [MockResponse::json(
+            401,
+            json!({ "error": { "message": "Incorrect API key provided" } }),
+        )],
+    );
+    let error = client
+        .list_models(&config(&rejected, "gpt-4o"))
+        .await
+        .unwrap_err();
+    assert!(format!("{error:#}").contains("401"), "{error:#}");
+    assert_eq!(ApiError::find(&error).unwrap().kind, ApiErrorKind::Auth);
+}
+
+#[tokio::test]
+async fn types_context_length_errors() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "chat/completions",
+        [MockResponse::json(
+            400,
+            json!({
+                "error": {
+                    "message": "This model's maximum context length is 128000 tokens.",
+                    "type": "invalid_request_error",
+                    "code": "context_length_exceeded",
+                },
+            }),
+        )],
+    );
+    let client = OpenAIClient::new().unwrap();
+
+    let error = client
+        .analyze(request(&server, "gpt-4o-mini"))
+        .await
+        .unwrap_err();
+
+    let api_error = ApiError::find(&error).unwrap();
+    assert_eq!(api_error.kind, ApiErrorKind::ContextLengthExceeded);
+    assert_eq!(api_error.status.map(|status| status.as_u16()), Some(400));
+}
+
+#[tokio::test]
+async fn reports_a_stalled_stream_after_the_partial_answer() {
+    let server = MockServer::start().await;
+    server.route(
+        "POST",
+        "chat/completions",
+        [MockResponse {
+            status: 200,
+            content_type: "text/event-stream",
+            body: format!(
+                "data: {}\n\n",
+                json!({ "choices": [{ "delta": { "content": "Partial" } }] })
+            )
+            .into_bytes(),
+            stall: true,
+        }],
+    );
+    let client = OpenAIClient::new().unwrap();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut request = request(&server, "gpt-4o-mini");
+    request.config.timeouts = TimeoutSettings {
+        idle_secs: 1,
+        ..TimeoutSettings::default()
+    };
+
+    let error = client.analyze_stream(request, tx).await.unwrap_err();
+
+    let api_error = ApiError::find(&error).unwrap();
+    assert_eq!(api_error.kind, ApiErrorKind::Timeout);
+    assert!(
+        api_error.message.starts_with("stream stalled"),
+        "{api_error}"
+    );
+    let deltas: Vec<String> = drain(&mut rx)
+        .into_iter()
+        .filter_map(|event| match event {
+            StreamEvent::Delta(delta) => Some(delta),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(deltas, ["Partial"]);
+}
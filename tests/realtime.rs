@@ -0,0 +1,125 @@
+use futures::{SinkExt, StreamExt};
+use ghost_ai::config::{OpenAIConfig, TranscriptionSettings};
+use ghost_ai::realtime::{stream_transcription, TranscriptEvent};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+
+// The callback signature is fixed by tungstenite.
+#[allow(clippy::result_large_err)]
+fn check_handshake(request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+    assert_eq!(request.uri().query(), Some("intent=transcription"));
+    assert_eq!(request.headers()["authorization"], "Bearer test-key");
+    assert_eq!(request.headers()["openai-beta"], "realtime=v1");
+    Ok(response)
+}
+
+/// Stand-in for the Realtime API: acknowledges the commit sent when the
+/// audio ends and transcribes it as "hello world" in two deltas. Returns the
+/// number of PCM bytes it received.
+async fn serve_one_session(listener: TcpListener) -> usize {
+    let (stream, _) = listener.accept().await.unwrap();
+    let mut socket = tokio_tungstenite::accept_hdr_async(stream, check_handshake)
+        .await
+        .unwrap();
+
+    let mut received = 0;
+    while let Some(Ok(message)) = socket.next().await {
+        let Message::Text(text) = message else {
+            continue;
+        };
+        let event: Value = serde_json::from_str(text.as_str()).unwrap();
+        match event["type"].as_str().unwrap() {
+            "transcription_session.update" => {
+                let session = &event["session"];
+                assert_eq!(session["input_audio_format"], "pcm16");
+                assert_eq!(session["input_audio_transcription"]["language"], "en");
+                assert_eq!(
+                    session["input_audio_transcription"]["prompt"],
+                    "Kubernetes, etcd"
+                );
+            }
+            "input_audio_buffer.append" => {
+                use base64::Engine as _;
+                received += base64::engine::general_purpose::STANDARD
+                    .decode(event["audio"].as_str().unwrap())
+                    .unwrap()
+                    .len();
+            }
+            "input_audio_buffer.commit" => {
+                for reply in [
+                    json!({ "type": "input_audio_buffer.committed", "item_id": "item_1" }),
+                    json!({
+                        "type": "conversation.item.input_audio_transcription.delta",
+                        "item_id": "item_1",
+                        "delta": "hello ",
+                    }),
+                    json!({
+                        "type": "conversation.item.input_audio_transcription.delta",
+                        "item_id": "item_1",
+                        "delta": "world",
+                    }),
+                    json!({
+                        "type": "conversation.item.input_audio_transcription.completed",
+                        "item_id": "item_1",
+                        "transcript": "hello world",
+                    }),
+                ] {
+                    socket.send(Message::text(reply.to_string())).await.unwrap();
+                }
+            }
+            other => panic!("unexpected client event {other}"),
+        }
+    }
+    received
+}
+
+#[tokio::test]
+async fn streams_audio_and_collects_transcripts() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = tokio::spawn(serve_one_session(listener));
+
+    let config = OpenAIConfig {
+        api_key: "test-key".into(),
+        base_url: format!("http://{address}/v1"),
+        ..OpenAIConfig::default()
+    };
+    let (audio_tx, audio_rx) = mpsc::unbounded_channel();
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+    // One second of 48 kHz audio in 10 ms blocks.
+    for _ in 0..100 {
+        audio_tx.send(vec![0.25f32; 480]).unwrap();
+    }
+    drop(audio_tx);
+
+    let transcript = stream_transcription(
+        &config,
+        &TranscriptionSettings::default(),
+        Some("Kubernetes, etcd"),
+        48_000,
+        audio_rx,
+        events_tx,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(transcript, "hello world");
+    let mut events = Vec::new();
+    while let Ok(event) = events_rx.try_recv() {
+        events.push(event);
+    }
+    assert_eq!(events.len(), 3);
+    assert_eq!(
+        events[2],
+        TranscriptEvent::Completed {
+            item_id: "item_1".into(),
+            text: "hello world".into(),
+        }
+    );
+    // 24 kHz PCM16, give or take the resampler's last sample.
+    let received = server.await.unwrap();
+    assert!((47_990..=48_000).contains(&received), "{received}");
+}